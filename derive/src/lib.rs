@@ -0,0 +1,110 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Fields, LitStr, parse_macro_input};
+
+/// Implements `crate::database::SqlColumns` for a struct that also derives `FromRow`, taking
+/// the column names from its `#[from_row(...)]` attributes: `rename` replaces the field name,
+/// and `flatten` expands the columns of a nested struct in place.
+#[proc_macro_derive(SqlColumns, attributes(from_row))]
+pub fn derive_sql_columns(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+struct Column {
+    field: syn::Ident,
+    ty: syn::Type,
+    name: String,
+    flatten: bool,
+}
+
+fn expand(input: &DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(
+            input,
+            "SqlColumns can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(Error::new_spanned(input, "SqlColumns needs named fields"));
+    };
+
+    let mut columns = Vec::new();
+    for field in fields.named.iter() {
+        let ident = field.ident.clone().unwrap();
+        let mut column = Column {
+            name: ident.to_string(),
+            field: ident,
+            ty: field.ty.clone(),
+            flatten: false,
+        };
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("from_row")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    column.name = meta.value()?.parse::<LitStr>()?.value();
+                } else if meta.path.is_ident("flatten") {
+                    column.flatten = true;
+                } else {
+                    // Only matters for reading from Postgres, which FromRow takes care of
+                    let _ = meta.value()?.parse::<LitStr>()?;
+                }
+                Ok(())
+            })?;
+        }
+        columns.push(column);
+    }
+
+    let write_columns = columns.iter().map(|c| {
+        let (ty, name) = (&c.ty, &c.name);
+        if c.flatten {
+            quote!(<#ty as crate::database::SqlColumns>::write_columns(columns);)
+        } else {
+            quote!(columns.push(#name);)
+        }
+    });
+    let write_values = columns.iter().map(|c| {
+        let (ty, field) = (&c.ty, &c.field);
+        if c.flatten {
+            quote!(<#ty as crate::database::SqlColumns>::write_values(&self.#field, values);)
+        } else {
+            quote!(values.push(<#ty as crate::database::SqlField>::to_sql_value(&self.#field));)
+        }
+    });
+    let read_columns = columns.iter().map(|c| {
+        let (ty, field, name) = (&c.ty, &c.field, &c.name);
+        if c.flatten {
+            quote!(#field: <#ty as crate::database::SqlColumns>::read_columns(get)?,)
+        } else {
+            quote!(#field: <#ty as crate::database::SqlField>::from_sql_value(get(#name)?)?,)
+        }
+    });
+
+    let name = &input.ident;
+    Ok(quote! {
+        impl crate::database::SqlColumns for #name {
+            fn write_columns(columns: &mut Vec<&'static str>) {
+                #(#write_columns)*
+            }
+
+            fn write_values(&self, values: &mut Vec<crate::database::SqlValue>) {
+                #(#write_values)*
+            }
+
+            fn read_columns(
+                get: &mut dyn FnMut(
+                    &'static str,
+                ) -> Result<
+                    crate::database::SqlValue,
+                    Box<dyn std::error::Error + Send + Sync>,
+                >,
+            ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+                Ok(#name {
+                    #(#read_columns)*
+                })
+            }
+        }
+    })
+}
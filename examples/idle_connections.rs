@@ -0,0 +1,62 @@
+//! Opens many connections to a running server and leaves them idle, to check that it takes
+//! all of them without spending a thread on each.
+//!
+//! ```sh
+//! cargo run --release --example idle_connections -- <address> <connections> <hold secs> [server pid]
+//! ```
+//!
+//! The hold has to stay below the server's `idle_timeout_secs`, or it closes the connections
+//! itself. With the server's pid, its thread count is reported once everything is connected.
+//! The open file limit (`ulimit -n`) of both processes has to be above the number of
+//! connections.
+
+use std::{env, error::Error, fs, io::ErrorKind, time::Duration};
+
+use tokio::net::TcpStream;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let args = env::args().collect::<Vec<String>>();
+    let address = args.get(1).map_or("127.0.0.1:10030", |a| a.as_str());
+    let count = args.get(2).map_or(Ok(5000), |a| a.parse::<usize>())?;
+    let hold_secs = args.get(3).map_or(Ok(10), |a| a.parse::<u64>())?;
+    let server_pid = args.get(4).map(|a| a.parse::<u32>()).transpose()?;
+
+    let mut connections = Vec::with_capacity(count);
+    for i in 0..count {
+        let connection = TcpStream::connect(address)
+            .await
+            .map_err(|e| format!("Connection {} to {} failed: {}", i, address, e))?;
+        connections.push(connection);
+    }
+    println!("Opened {} connections to {}", count, address);
+
+    // Give the server a moment to pick all of them up
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    if let Some(pid) = server_pid {
+        let status = fs::read_to_string(format!("/proc/{}/status", pid))?;
+        let threads = status
+            .lines()
+            .find_map(|l| l.strip_prefix("Threads:"))
+            .ok_or("Couldn't find the thread count of the server")?;
+        println!("Server has {} threads", threads.trim());
+    }
+
+    tokio::time::sleep(Duration::from_secs(hold_secs)).await;
+    let mut open = 0;
+    let mut buffer = [0u8; 1024];
+    for connection in connections.iter() {
+        match connection.try_read(&mut buffer) {
+            Ok(0) => {}
+            // Whatever the server sent, the connection is still there
+            Ok(_) => open += 1,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => open += 1,
+            Err(_) => {}
+        }
+    }
+    println!(
+        "{} of {} connections still open after {} seconds",
+        open, count, hold_secs
+    );
+    Ok(())
+}
@@ -0,0 +1,200 @@
+-- Brings a database created from any version of res/schema.sql, from before there were
+-- migrations, up to migration 0001_initial_schema. Every statement is a no-op on parts that
+-- are already there, so this can be run on whatever version the database was created from.
+
+CREATE SEQUENCE IF NOT EXISTS uid;
+
+CREATE TABLE IF NOT EXISTS accounts (
+    member_no INTEGER PRIMARY KEY NOT NULL GENERATED ALWAYS AS IDENTITY,
+    login_id TEXT NOT NULL UNIQUE,
+    auth_key TEXT
+);
+
+CREATE TABLE IF NOT EXISTS characters (
+    member_no INTEGER NOT NULL,
+    character_id INTEGER PRIMARY KEY NOT NULL DEFAULT nextval('uid'),
+    mount_uid INTEGER NOT NULL UNIQUE, -- The value 0 is understood as "no mount"
+    mount_slots INTEGER NOT NULL, -- Maximum number of horses the character can own
+    nickname TEXT NOT NULL UNIQUE, -- TODO: Check length limit
+
+    -- Parts
+    char_id SMALLINT NOT NULL,
+    mouth_serial_id SMALLINT NOT NULL,
+    face_serial_id SMALLINT NOT NULL,
+    parts_val0 SMALLINT NOT NULL,
+
+    -- Appearance
+    appearance_val0 SMALLINT NOT NULL,
+    head_size SMALLINT NOT NULL,
+    height SMALLINT NOT NULL,
+    thigh_volume SMALLINT NOT NULL,
+    leg_volume SMALLINT NOT NULL,
+    appearance_val1 SMALLINT NOT NULL,
+
+    create_character_unk0 INTEGER NOT NULL,
+
+    CONSTRAINT fk_member_no FOREIGN KEY (member_no) REFERENCES accounts(member_no)
+);
+
+CREATE TABLE IF NOT EXISTS horses (
+    character_id INTEGER NOT NULL,
+
+    uid INTEGER PRIMARY KEY NOT NULL DEFAULT nextval('uid'),
+    tid INTEGER NOT NULL,
+    name TEXT NOT NULL,
+
+    -- Parts
+    skin_id SMALLINT NOT NULL,
+    mane_id SMALLINT NOT NULL,
+    tail_id SMALLINT NOT NULL,
+    face_id SMALLINT NOT NULL,
+
+    -- Appearance
+    scale SMALLINT NOT NULL,
+    leg_length SMALLINT NOT NULL,
+    leg_volume SMALLINT NOT NULL,
+    body_length SMALLINT NOT NULL,
+    body_volume SMALLINT NOT NULL,
+
+    -- Stats
+    agility INTEGER NOT NULL,
+    control INTEGER NOT NULL,
+    speed INTEGER NOT NULL,
+    strength INTEGER NOT NULL,
+    spirit INTEGER NOT NULL,
+
+    rating INTEGER NOT NULL,
+    class SMALLINT NOT NULL,
+    class_progress SMALLINT NOT NULL,
+    grade SMALLINT NOT NULL,
+    growth_points SMALLINT NOT NULL,
+
+    -- Vals0
+    stamina SMALLINT NOT NULL,
+    attractiveness SMALLINT NOT NULL,
+    hunger SMALLINT NOT NULL,
+    vals0_val0 SMALLINT NOT NULL,
+    vals0_val1 SMALLINT NOT NULL,
+    vals0_val2 SMALLINT NOT NULL,
+    vals0_val3 SMALLINT NOT NULL,
+    vals0_val4 SMALLINT NOT NULL,
+    vals0_val5 SMALLINT NOT NULL,
+    vals0_val6 SMALLINT NOT NULL,
+    vals0_val7 SMALLINT NOT NULL,
+    vals0_val8 SMALLINT NOT NULL,
+    vals0_val9 SMALLINT NOT NULL,
+    vals0_val10 SMALLINT NOT NULL,
+
+    -- Vals1
+    vals1_val0 SMALLINT NOT NULL,
+    vals1_val1 INTEGER NOT NULL,
+    date_of_birth INTEGER NOT NULL,
+    vals1_val3 SMALLINT NOT NULL,
+    vals1_val4 SMALLINT NOT NULL,
+    class_progression INTEGER NOT NULL,
+    vals1_val5 INTEGER NOT NULL,
+    potential_level SMALLINT NOT NULL,
+    has_potential SMALLINT NOT NULL,
+    potential_value SMALLINT NOT NULL,
+    vals1_val9 SMALLINT NOT NULL,
+    luck SMALLINT NOT NULL,
+    has_luck SMALLINT NOT NULL,
+    vals1_val12 SMALLINT NOT NULL,
+    fatigue SMALLINT NOT NULL,
+    vals1_val14 SMALLINT NOT NULL,
+    emblem SMALLINT NOT NULL,
+
+    -- Mastery
+    spur_magic_count INTEGER NOT NULL,
+    jump_count INTEGER NOT NULL,
+    sliding_time INTEGER NOT NULL,
+    gliding_distance INTEGER NOT NULL,
+
+    -- Remaining
+    val16 INTEGER NOT NULL,
+    val17 INTEGER NOT NULL,
+
+    CONSTRAINT fk_character_id FOREIGN KEY (character_id) REFERENCES characters(character_id)
+);
+
+CREATE TABLE IF NOT EXISTS stallions (
+    horse_uid INTEGER PRIMARY KEY NOT NULL,
+    price INTEGER NOT NULL,
+    times_mated INTEGER NOT NULL DEFAULT 0,
+
+    CONSTRAINT fk_horse_uid FOREIGN KEY (horse_uid) REFERENCES horses(uid) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS wallets (
+    character_id INTEGER PRIMARY KEY NOT NULL,
+    carrots INTEGER NOT NULL,
+    breeding_earnings INTEGER NOT NULL DEFAULT 0, -- Carrots earned through registered stallions, waiting to be collected
+
+    CONSTRAINT fk_character_id FOREIGN KEY (character_id) REFERENCES characters(character_id)
+);
+
+-- Ancestors of bred horses. The value 0 is understood as "unknown ancestor"
+CREATE TABLE IF NOT EXISTS horse_lineage (
+    horse_uid INTEGER PRIMARY KEY NOT NULL,
+    sire_uid INTEGER NOT NULL,
+    dam_uid INTEGER NOT NULL,
+    sire_sire_uid INTEGER NOT NULL DEFAULT 0,
+    sire_dam_uid INTEGER NOT NULL DEFAULT 0,
+    dam_sire_uid INTEGER NOT NULL DEFAULT 0,
+    dam_dam_uid INTEGER NOT NULL DEFAULT 0,
+    coat_bonus SMALLINT NOT NULL DEFAULT 0,
+
+    CONSTRAINT fk_horse_uid FOREIGN KEY (horse_uid) REFERENCES horses(uid) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS breeding_wishlist (
+    character_id INTEGER NOT NULL,
+    stallion_uid INTEGER NOT NULL,
+
+    PRIMARY KEY (character_id, stallion_uid),
+    CONSTRAINT fk_character_id FOREIGN KEY (character_id) REFERENCES characters(character_id),
+    -- Unregistering a stallion drops it from every wishlist
+    CONSTRAINT fk_stallion_uid FOREIGN KEY (stallion_uid) REFERENCES stallions(horse_uid) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS items (
+    uid INTEGER PRIMARY KEY NOT NULL DEFAULT nextval('uid'),
+    character_id INTEGER NOT NULL,
+    tid INTEGER NOT NULL,
+    count INTEGER NOT NULL,
+
+    UNIQUE (character_id, tid),
+    CONSTRAINT fk_character_id FOREIGN KEY (character_id) REFERENCES characters(character_id)
+);
+
+-- Every character owns exactly one ranch
+CREATE TABLE IF NOT EXISTS ranches (
+    ranch_id INTEGER PRIMARY KEY NOT NULL DEFAULT nextval('uid'),
+    owner_character_id INTEGER NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+
+    -- Options
+    visibility SMALLINT NOT NULL DEFAULT 0, -- The value 0 is understood as "public"
+    max_visitors SMALLINT NOT NULL DEFAULT 0, -- The value 0 is understood as "no limit"
+
+    visitor_count INTEGER NOT NULL DEFAULT 0, -- Characters currently in the ranch, kept up to date by the ranch server
+
+    CONSTRAINT fk_owner_character_id FOREIGN KEY (owner_character_id) REFERENCES characters(character_id)
+);
+
+-- Characters kicked out of a ranch, who can't enter it again until the ban expires
+CREATE TABLE IF NOT EXISTS ranch_bans (
+    ranch_id INTEGER NOT NULL,
+    character_id INTEGER NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+
+    PRIMARY KEY (ranch_id, character_id),
+    CONSTRAINT fk_ranch_id FOREIGN KEY (ranch_id) REFERENCES ranches(ranch_id) ON DELETE CASCADE,
+    CONSTRAINT fk_character_id FOREIGN KEY (character_id) REFERENCES characters(character_id)
+);
+
+-- Columns added to tables that older versions already had
+ALTER TABLE characters ADD COLUMN IF NOT EXISTS mount_slots INTEGER NOT NULL DEFAULT 3;
+ALTER TABLE characters ALTER COLUMN mount_slots DROP DEFAULT;
+ALTER TABLE horse_lineage ADD COLUMN IF NOT EXISTS coat_bonus SMALLINT NOT NULL DEFAULT 0;
+ALTER TABLE ranches ADD COLUMN IF NOT EXISTS visitor_count INTEGER NOT NULL DEFAULT 0;
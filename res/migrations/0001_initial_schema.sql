@@ -10,6 +10,7 @@ CREATE TABLE characters (
     member_no INTEGER NOT NULL,
     character_id INTEGER PRIMARY KEY NOT NULL DEFAULT nextval('uid'),
     mount_uid INTEGER NOT NULL UNIQUE, -- The value 0 is understood as "no mount"
+    mount_slots INTEGER NOT NULL, -- Maximum number of horses the character can own
     nickname TEXT NOT NULL UNIQUE, -- TODO: Check length limit
 
     -- Parts
@@ -109,5 +110,81 @@ CREATE TABLE horses (
     val16 INTEGER NOT NULL,
     val17 INTEGER NOT NULL,
 
+    CONSTRAINT fk_character_id FOREIGN KEY (character_id) REFERENCES characters(character_id)
+);
+
+CREATE TABLE stallions (
+    horse_uid INTEGER PRIMARY KEY NOT NULL,
+    price INTEGER NOT NULL,
+    times_mated INTEGER NOT NULL DEFAULT 0,
+
+    CONSTRAINT fk_horse_uid FOREIGN KEY (horse_uid) REFERENCES horses(uid) ON DELETE CASCADE
+);
+
+CREATE TABLE wallets (
+    character_id INTEGER PRIMARY KEY NOT NULL,
+    carrots INTEGER NOT NULL,
+    breeding_earnings INTEGER NOT NULL DEFAULT 0, -- Carrots earned through registered stallions, waiting to be collected
+
+    CONSTRAINT fk_character_id FOREIGN KEY (character_id) REFERENCES characters(character_id)
+);
+
+-- Ancestors of bred horses. The value 0 is understood as "unknown ancestor"
+CREATE TABLE horse_lineage (
+    horse_uid INTEGER PRIMARY KEY NOT NULL,
+    sire_uid INTEGER NOT NULL,
+    dam_uid INTEGER NOT NULL,
+    sire_sire_uid INTEGER NOT NULL DEFAULT 0,
+    sire_dam_uid INTEGER NOT NULL DEFAULT 0,
+    dam_sire_uid INTEGER NOT NULL DEFAULT 0,
+    dam_dam_uid INTEGER NOT NULL DEFAULT 0,
+    coat_bonus SMALLINT NOT NULL DEFAULT 0,
+
+    CONSTRAINT fk_horse_uid FOREIGN KEY (horse_uid) REFERENCES horses(uid) ON DELETE CASCADE
+);
+
+CREATE TABLE breeding_wishlist (
+    character_id INTEGER NOT NULL,
+    stallion_uid INTEGER NOT NULL,
+
+    PRIMARY KEY (character_id, stallion_uid),
+    CONSTRAINT fk_character_id FOREIGN KEY (character_id) REFERENCES characters(character_id),
+    -- Unregistering a stallion drops it from every wishlist
+    CONSTRAINT fk_stallion_uid FOREIGN KEY (stallion_uid) REFERENCES stallions(horse_uid) ON DELETE CASCADE
+);
+
+CREATE TABLE items (
+    uid INTEGER PRIMARY KEY NOT NULL DEFAULT nextval('uid'),
+    character_id INTEGER NOT NULL,
+    tid INTEGER NOT NULL,
+    count INTEGER NOT NULL,
+
+    UNIQUE (character_id, tid),
+    CONSTRAINT fk_character_id FOREIGN KEY (character_id) REFERENCES characters(character_id)
+);
+
+-- Every character owns exactly one ranch
+CREATE TABLE ranches (
+    ranch_id INTEGER PRIMARY KEY NOT NULL DEFAULT nextval('uid'),
+    owner_character_id INTEGER NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+
+    -- Options
+    visibility SMALLINT NOT NULL DEFAULT 0, -- The value 0 is understood as "public"
+    max_visitors SMALLINT NOT NULL DEFAULT 0, -- The value 0 is understood as "no limit"
+
+    visitor_count INTEGER NOT NULL DEFAULT 0, -- Characters currently in the ranch, kept up to date by the ranch server
+
+    CONSTRAINT fk_owner_character_id FOREIGN KEY (owner_character_id) REFERENCES characters(character_id)
+);
+
+-- Characters kicked out of a ranch, who can't enter it again until the ban expires
+CREATE TABLE ranch_bans (
+    ranch_id INTEGER NOT NULL,
+    character_id INTEGER NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+
+    PRIMARY KEY (ranch_id, character_id),
+    CONSTRAINT fk_ranch_id FOREIGN KEY (ranch_id) REFERENCES ranches(ranch_id) ON DELETE CASCADE,
     CONSTRAINT fk_character_id FOREIGN KEY (character_id) REFERENCES characters(character_id)
 );
\ No newline at end of file
@@ -0,0 +1,11 @@
+-- Codes the lobby hands out along with the address of the ranch server, which the ranch
+-- server takes as proof of who is entering which ranch. Each one can only be used once
+CREATE TABLE ranch_entry_codes (
+    character_id INTEGER PRIMARY KEY NOT NULL,
+    ranch_id INTEGER NOT NULL,
+    code INTEGER NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+
+    CONSTRAINT fk_character_id FOREIGN KEY (character_id) REFERENCES characters(character_id) ON DELETE CASCADE,
+    CONSTRAINT fk_ranch_id FOREIGN KEY (ranch_id) REFERENCES ranches(ranch_id) ON DELETE CASCADE
+);
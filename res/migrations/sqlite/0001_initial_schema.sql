@@ -0,0 +1,194 @@
+-- Stands in for Postgres' uid sequence, shared by characters, horses, items and ranches
+CREATE TABLE uid_sequence (
+    last_uid INTEGER NOT NULL
+);
+INSERT INTO uid_sequence (last_uid) VALUES (0);
+
+CREATE TABLE accounts (
+    member_no INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    login_id TEXT NOT NULL UNIQUE,
+    auth_key TEXT
+);
+
+CREATE TABLE characters (
+    member_no INTEGER NOT NULL,
+    character_id INTEGER PRIMARY KEY NOT NULL,
+    mount_uid INTEGER NOT NULL UNIQUE, -- The value 0 is understood as "no mount"
+    mount_slots INTEGER NOT NULL, -- Maximum number of horses the character can own
+    nickname TEXT NOT NULL UNIQUE, -- TODO: Check length limit
+
+    -- Parts
+    char_id INTEGER NOT NULL,
+    mouth_serial_id INTEGER NOT NULL,
+    face_serial_id INTEGER NOT NULL,
+    parts_val0 INTEGER NOT NULL,
+
+    -- Appearance
+    appearance_val0 INTEGER NOT NULL,
+    head_size INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    thigh_volume INTEGER NOT NULL,
+    leg_volume INTEGER NOT NULL,
+    appearance_val1 INTEGER NOT NULL,
+
+    create_character_unk0 INTEGER NOT NULL,
+
+    CONSTRAINT fk_member_no FOREIGN KEY (member_no) REFERENCES accounts(member_no)
+);
+
+CREATE TABLE horses (
+    character_id INTEGER NOT NULL,
+
+    uid INTEGER PRIMARY KEY NOT NULL,
+    tid INTEGER NOT NULL,
+    name TEXT NOT NULL,
+
+    -- Parts
+    skin_id INTEGER NOT NULL,
+    mane_id INTEGER NOT NULL,
+    tail_id INTEGER NOT NULL,
+    face_id INTEGER NOT NULL,
+
+    -- Appearance
+    scale INTEGER NOT NULL,
+    leg_length INTEGER NOT NULL,
+    leg_volume INTEGER NOT NULL,
+    body_length INTEGER NOT NULL,
+    body_volume INTEGER NOT NULL,
+
+    -- Stats
+    agility INTEGER NOT NULL,
+    control INTEGER NOT NULL,
+    speed INTEGER NOT NULL,
+    strength INTEGER NOT NULL,
+    spirit INTEGER NOT NULL,
+
+    rating INTEGER NOT NULL,
+    class INTEGER NOT NULL,
+    class_progress INTEGER NOT NULL,
+    grade INTEGER NOT NULL,
+    growth_points INTEGER NOT NULL,
+
+    -- Vals0
+    stamina INTEGER NOT NULL,
+    attractiveness INTEGER NOT NULL,
+    hunger INTEGER NOT NULL,
+    vals0_val0 INTEGER NOT NULL,
+    vals0_val1 INTEGER NOT NULL,
+    vals0_val2 INTEGER NOT NULL,
+    vals0_val3 INTEGER NOT NULL,
+    vals0_val4 INTEGER NOT NULL,
+    vals0_val5 INTEGER NOT NULL,
+    vals0_val6 INTEGER NOT NULL,
+    vals0_val7 INTEGER NOT NULL,
+    vals0_val8 INTEGER NOT NULL,
+    vals0_val9 INTEGER NOT NULL,
+    vals0_val10 INTEGER NOT NULL,
+
+    -- Vals1
+    vals1_val0 INTEGER NOT NULL,
+    vals1_val1 INTEGER NOT NULL,
+    date_of_birth INTEGER NOT NULL,
+    vals1_val3 INTEGER NOT NULL,
+    vals1_val4 INTEGER NOT NULL,
+    class_progression INTEGER NOT NULL,
+    vals1_val5 INTEGER NOT NULL,
+    potential_level INTEGER NOT NULL,
+    has_potential INTEGER NOT NULL,
+    potential_value INTEGER NOT NULL,
+    vals1_val9 INTEGER NOT NULL,
+    luck INTEGER NOT NULL,
+    has_luck INTEGER NOT NULL,
+    vals1_val12 INTEGER NOT NULL,
+    fatigue INTEGER NOT NULL,
+    vals1_val14 INTEGER NOT NULL,
+    emblem INTEGER NOT NULL,
+
+    -- Mastery
+    spur_magic_count INTEGER NOT NULL,
+    jump_count INTEGER NOT NULL,
+    sliding_time INTEGER NOT NULL,
+    gliding_distance INTEGER NOT NULL,
+
+    -- Remaining
+    val16 INTEGER NOT NULL,
+    val17 INTEGER NOT NULL,
+
+    CONSTRAINT fk_character_id FOREIGN KEY (character_id) REFERENCES characters(character_id)
+);
+
+CREATE TABLE stallions (
+    horse_uid INTEGER PRIMARY KEY NOT NULL,
+    price INTEGER NOT NULL,
+    times_mated INTEGER NOT NULL DEFAULT 0,
+
+    CONSTRAINT fk_horse_uid FOREIGN KEY (horse_uid) REFERENCES horses(uid) ON DELETE CASCADE
+);
+
+CREATE TABLE wallets (
+    character_id INTEGER PRIMARY KEY NOT NULL,
+    carrots INTEGER NOT NULL,
+    breeding_earnings INTEGER NOT NULL DEFAULT 0, -- Carrots earned through registered stallions, waiting to be collected
+
+    CONSTRAINT fk_character_id FOREIGN KEY (character_id) REFERENCES characters(character_id)
+);
+
+-- Ancestors of bred horses. The value 0 is understood as "unknown ancestor"
+CREATE TABLE horse_lineage (
+    horse_uid INTEGER PRIMARY KEY NOT NULL,
+    sire_uid INTEGER NOT NULL,
+    dam_uid INTEGER NOT NULL,
+    sire_sire_uid INTEGER NOT NULL DEFAULT 0,
+    sire_dam_uid INTEGER NOT NULL DEFAULT 0,
+    dam_sire_uid INTEGER NOT NULL DEFAULT 0,
+    dam_dam_uid INTEGER NOT NULL DEFAULT 0,
+    coat_bonus INTEGER NOT NULL DEFAULT 0,
+
+    CONSTRAINT fk_horse_uid FOREIGN KEY (horse_uid) REFERENCES horses(uid) ON DELETE CASCADE
+);
+
+CREATE TABLE breeding_wishlist (
+    character_id INTEGER NOT NULL,
+    stallion_uid INTEGER NOT NULL,
+
+    PRIMARY KEY (character_id, stallion_uid),
+    CONSTRAINT fk_character_id FOREIGN KEY (character_id) REFERENCES characters(character_id),
+    -- Unregistering a stallion drops it from every wishlist
+    CONSTRAINT fk_stallion_uid FOREIGN KEY (stallion_uid) REFERENCES stallions(horse_uid) ON DELETE CASCADE
+);
+
+CREATE TABLE items (
+    uid INTEGER PRIMARY KEY NOT NULL,
+    character_id INTEGER NOT NULL,
+    tid INTEGER NOT NULL,
+    count INTEGER NOT NULL,
+
+    UNIQUE (character_id, tid),
+    CONSTRAINT fk_character_id FOREIGN KEY (character_id) REFERENCES characters(character_id)
+);
+
+-- Every character owns exactly one ranch
+CREATE TABLE ranches (
+    ranch_id INTEGER PRIMARY KEY NOT NULL,
+    owner_character_id INTEGER NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+
+    -- Options
+    visibility INTEGER NOT NULL DEFAULT 0, -- The value 0 is understood as "public"
+    max_visitors INTEGER NOT NULL DEFAULT 0, -- The value 0 is understood as "no limit"
+
+    visitor_count INTEGER NOT NULL DEFAULT 0, -- Characters currently in the ranch, kept up to date by the ranch server
+
+    CONSTRAINT fk_owner_character_id FOREIGN KEY (owner_character_id) REFERENCES characters(character_id)
+);
+
+-- Characters kicked out of a ranch, who can't enter it again until the ban expires
+CREATE TABLE ranch_bans (
+    ranch_id INTEGER NOT NULL,
+    character_id INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL, -- Unix time
+
+    PRIMARY KEY (ranch_id, character_id),
+    CONSTRAINT fk_ranch_id FOREIGN KEY (ranch_id) REFERENCES ranches(ranch_id) ON DELETE CASCADE,
+    CONSTRAINT fk_character_id FOREIGN KEY (character_id) REFERENCES characters(character_id)
+);
\ No newline at end of file
@@ -0,0 +1,11 @@
+-- Cards offered after a failed breeding attempt, until the character picks one of them.
+-- The attempt was paid for, so the cards have to outlive the connection
+CREATE TABLE breeding_failure_cards (
+    character_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    item_tid INTEGER, -- NULL for carrots
+    amount INTEGER NOT NULL, -- Carrots, or count of the item
+
+    PRIMARY KEY (character_id, position),
+    CONSTRAINT fk_character_id FOREIGN KEY (character_id) REFERENCES characters(character_id) ON DELETE CASCADE
+);
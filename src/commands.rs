@@ -105,7 +105,8 @@ where
 }
 
 // Wrapper around Vec<u8> for buffers that take the whole remaining buffer.
-#[derive(Debug, Default)]
+// Meant for payloads that haven't been decoded yet.
+#[derive(Debug, Default, Clone)]
 pub struct RestOfBuffer {
     pub bytes: Vec<u8>,
 }
@@ -117,23 +118,13 @@ impl<'a> DekuReader<'a> for RestOfBuffer {
     where
         Self: Sized,
     {
-        let mut size = 0;
         let mut bytes = Vec::new();
-        loop {
-            bytes.resize(size + 1024, 0);
-            let result = reader.read_bytes(1024, &mut bytes[size..], deku::ctx::Order::Lsb0);
-            if let Err(error) = result {
-                match error {
-                    DekuError::Incomplete(need_size) => {
-                        size = size + 1024 - need_size.byte_size();
-                        bytes.resize(size, 0);
-                        return Ok(RestOfBuffer { bytes });
-                    }
-                    _ => return Err(error),
-                }
-            }
-            size = size + 1024;
+        let mut byte = [0u8; 1];
+        while !reader.end() {
+            reader.read_bytes(1, &mut byte, deku::ctx::Order::Lsb0)?;
+            bytes.push(byte[0]);
         }
+        Ok(RestOfBuffer { bytes })
     }
 }
 impl<'a> DekuWriter for RestOfBuffer {
@@ -230,4 +221,33 @@ mod tests {
         assert_eq!(packet.command_id, packet2.command_id);
         assert_eq!(packet.payload, packet2.payload);
     }
+
+    #[test]
+    fn test_ranch_action_decoding() {
+        use crate::commands::ranch::ranch_cmd_action::{RanchCmdAction, RanchCmdActionNotify};
+
+        let packet = Packet {
+            command_id: CommandId::AcCmdCRRanchCmdAction,
+            payload: vec![0x12, 0x00, 0x01],
+        };
+        let command = RanchCmdAction::try_from(&packet).unwrap();
+        assert_eq!(command.action.action_id, 0x12);
+        assert_eq!(command.action.variant, 0x01);
+        command.action.check_no_extra_data(&command.extra).unwrap();
+
+        let notify = RanchCmdActionNotify {
+            ranch_index: 2,
+            action: command.action,
+        };
+        let notify_packet: Packet = notify.try_into().unwrap();
+        assert_eq!(notify_packet.payload, vec![0x02, 0x00, 0x12, 0x00, 0x01]);
+
+        // Nothing that isn't understood gets passed on
+        let packet = Packet {
+            command_id: CommandId::AcCmdCRRanchCmdAction,
+            payload: vec![0x12, 0x00, 0x01, 0xAA, 0xBB, 0xCC],
+        };
+        let command = RanchCmdAction::try_from(&packet).unwrap();
+        assert!(command.action.check_no_extra_data(&command.extra).is_err());
+    }
 }
@@ -0,0 +1,25 @@
+use deku::{DekuRead, DekuWrite};
+
+use crate::{impl_command_traits, packet::CommandId};
+
+// TODO: Layout guessed
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct ChangeRanchOption {
+    pub visibility: u8,
+    pub max_visitors: u8,
+}
+impl_command_traits!(ChangeRanchOption, CommandId::AcCmdCLChangeRanchOption);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct ChangeRanchOptionOk {
+    pub visibility: u8,
+    pub max_visitors: u8,
+}
+impl_command_traits!(ChangeRanchOptionOk, CommandId::AcCmdCLChangeRanchOptionOK);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct ChangeRanchOptionCancel {}
+impl_command_traits!(
+    ChangeRanchOptionCancel,
+    CommandId::AcCmdCLChangeRanchOptionCancel
+);
@@ -12,6 +12,10 @@ pub struct EnterRanch {
 }
 impl_command_traits!(EnterRanch, CommandId::AcCmdCLEnterRanch);
 
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct EnterRanchRandomly {}
+impl_command_traits!(EnterRanchRandomly, CommandId::AcCmdCLEnterRanchRandomly);
+
 #[derive(Debug, Default, DekuRead, DekuWrite)]
 pub struct EnterRanchOk {
     pub ranch_uid: u32,
@@ -0,0 +1,7 @@
+use deku::{DekuRead, DekuWrite};
+
+use crate::{impl_command_traits, packet::CommandId};
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct Heartbeat {}
+impl_command_traits!(Heartbeat, CommandId::AcCmdCLHeartbeat);
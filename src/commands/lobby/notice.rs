@@ -0,0 +1,12 @@
+use std::ffi::CString;
+
+use deku::{DekuRead, DekuWrite};
+
+use crate::{impl_command_traits, packet::CommandId};
+
+// TODO: Check the layout against a capture. Only the message is known so far
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct Notice {
+    pub message: CString,
+}
+impl_command_traits!(Notice, CommandId::AcCmdLCNotice);
@@ -14,19 +14,32 @@ use crate::commands::{
     },
 };
 
+pub mod breeding_abandon;
 pub mod breeding_failure_card;
+pub mod breeding_failure_card_choose;
+pub mod breeding_take_money;
 pub mod breeding_wishlist;
+pub mod breeding_wishlist_add;
+pub mod breeding_wishlist_del;
+pub mod check_stallion_charge;
 pub mod enter_breeding_market;
 pub mod enter_ranch;
+pub mod expand_mount_slot;
+pub mod heartbeat;
+pub mod kick_ranch;
 pub mod leave_breeding_market;
+pub mod leave_ranch;
 pub mod mount_family_tree;
 pub mod ranch_chat;
 pub mod ranch_cmd_action;
 pub mod ranch_snapshot;
+pub mod register_stallion;
 pub mod request_npc_dress_list;
 pub mod request_storage;
 pub mod search_stallion;
 pub mod try_breeding;
+pub mod unregister_stallion;
+pub mod unregister_stallion_estimate_info;
 pub mod update_mount_nickname;
 pub mod wear_equipment;
 
@@ -0,0 +1,20 @@
+use deku::{DekuRead, DekuWrite};
+
+use crate::{impl_command_traits, packet::CommandId};
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct BreedingAbandon {
+    pub horse_uid: u32,
+}
+impl_command_traits!(BreedingAbandon, CommandId::AcCmdCRBreedingAbandon);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct BreedingAbandonOk {}
+impl_command_traits!(BreedingAbandonOk, CommandId::AcCmdCRBreedingAbandonOK);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct BreedingAbandonCancel {}
+impl_command_traits!(
+    BreedingAbandonCancel,
+    CommandId::AcCmdCRBreedingAbandonCancel
+);
@@ -0,0 +1,32 @@
+use deku::{DekuRead, DekuWrite};
+
+use crate::{impl_command_traits, packet::CommandId};
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct BreedingFailureCardChoose {
+    pub position: u8,
+}
+impl_command_traits!(
+    BreedingFailureCardChoose,
+    CommandId::AcCmdCRBreedingFailureCardChoose
+);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct BreedingFailureCardChooseCancel {}
+impl_command_traits!(
+    BreedingFailureCardChooseCancel,
+    CommandId::AcCmdCRBreedingFailureCardChooseCancel
+);
+
+// TODO: Layout guessed, the client might expect more fields
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct BreedingFailureCardChooseOk {
+    pub position: u8,
+    pub item_tid: u32,
+    pub item_count: u32,
+    pub updated_carrot_count: u32,
+}
+impl_command_traits!(
+    BreedingFailureCardChooseOk,
+    CommandId::AcCmdCRBreedingFailureCardChooseOK
+);
@@ -0,0 +1,21 @@
+use deku::{DekuRead, DekuWrite};
+
+use crate::{impl_command_traits, packet::CommandId};
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct BreedingTakeMoney {}
+impl_command_traits!(BreedingTakeMoney, CommandId::AcCmdCRBreedingTakeMoney);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct BreedingTakeMoneyCancel {}
+impl_command_traits!(
+    BreedingTakeMoneyCancel,
+    CommandId::AcCmdCRBreedingTakeMoneyCancel
+);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct BreedingTakeMoneyOk {
+    pub collected_carrots: u32,
+    pub updated_carrot_count: u32,
+}
+impl_command_traits!(BreedingTakeMoneyOk, CommandId::AcCmdCRBreedingTakeMoneyOK);
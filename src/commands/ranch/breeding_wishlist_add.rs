@@ -0,0 +1,23 @@
+use deku::{DekuRead, DekuWrite};
+
+use crate::{impl_command_traits, packet::CommandId};
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct BreedingWishlistAdd {
+    pub uid: u32,
+}
+impl_command_traits!(BreedingWishlistAdd, CommandId::AcCmdCRBreedingWishlistAdd);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct BreedingWishlistAddCancel {}
+impl_command_traits!(
+    BreedingWishlistAddCancel,
+    CommandId::AcCmdCRBreedingWishlistAddCancel
+);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct BreedingWishlistAddOk {}
+impl_command_traits!(
+    BreedingWishlistAddOk,
+    CommandId::AcCmdCRBreedingWishlistAddOK
+);
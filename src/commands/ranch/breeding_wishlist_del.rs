@@ -0,0 +1,23 @@
+use deku::{DekuRead, DekuWrite};
+
+use crate::{impl_command_traits, packet::CommandId};
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct BreedingWishlistDel {
+    pub uid: u32,
+}
+impl_command_traits!(BreedingWishlistDel, CommandId::AcCmdCRBreedingWishlistDel);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct BreedingWishlistDelCancel {}
+impl_command_traits!(
+    BreedingWishlistDelCancel,
+    CommandId::AcCmdCRBreedingWishlistDelCancel
+);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct BreedingWishlistDelOk {}
+impl_command_traits!(
+    BreedingWishlistDelOk,
+    CommandId::AcCmdCRBreedingWishlistDelOK
+);
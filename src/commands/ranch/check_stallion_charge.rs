@@ -0,0 +1,19 @@
+use deku::{DekuRead, DekuWrite};
+
+use crate::{impl_command_traits, packet::CommandId};
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct CheckStallionCharge {
+    pub horse_uid: u32,
+}
+impl_command_traits!(CheckStallionCharge, CommandId::AcCmdCRCheckStallionCharge);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct CheckStallionChargeOk {
+    pub horse_uid: u32,
+    pub charge: u32,
+}
+impl_command_traits!(
+    CheckStallionChargeOk,
+    CommandId::AcCmdCRCheckStallionChargeOK
+);
@@ -0,0 +1,22 @@
+use deku::{DekuRead, DekuWrite};
+
+use crate::{impl_command_traits, packet::CommandId};
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct ExpandMountSlot {}
+impl_command_traits!(ExpandMountSlot, CommandId::AcCmdCRExpandMountSlot);
+
+// TODO: Layout guessed
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct ExpandMountSlotOk {
+    pub mount_slots: u32,
+    pub updated_carrot_count: u32,
+}
+impl_command_traits!(ExpandMountSlotOk, CommandId::AcCmdCRExpandMountSlotOK);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct ExpandMountSlotCancel {}
+impl_command_traits!(
+    ExpandMountSlotCancel,
+    CommandId::AcCmdCRExpandMountSlotCancel
+);
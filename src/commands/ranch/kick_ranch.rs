@@ -0,0 +1,23 @@
+use deku::{DekuRead, DekuWrite};
+
+use crate::{impl_command_traits, packet::CommandId};
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct KickRanch {
+    pub character_id: u32,
+}
+impl_command_traits!(KickRanch, CommandId::AcCmdCRKickRanch);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct KickRanchOk {}
+impl_command_traits!(KickRanchOk, CommandId::AcCmdCRKickRanchOK);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct KickRanchCancel {}
+impl_command_traits!(KickRanchCancel, CommandId::AcCmdCRKickRanchCancel);
+
+#[derive(Debug, Default, Clone, DekuRead, DekuWrite)]
+pub struct KickRanchNotify {
+    pub character_id: u32,
+}
+impl_command_traits!(KickRanchNotify, CommandId::AcCmdRCKickRanchNotify);
@@ -0,0 +1,18 @@
+use deku::{DekuRead, DekuWrite};
+
+use crate::{impl_command_traits, packet::CommandId};
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct LeaveRanch {}
+impl_command_traits!(LeaveRanch, CommandId::AcCmdCRLeaveRanch);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct LeaveRanchOk {}
+impl_command_traits!(LeaveRanchOk, CommandId::AcCmdCRLeaveRanchOK);
+
+#[derive(Debug, Default, Clone, DekuRead, DekuWrite)]
+pub struct LeaveRanchNotify {
+    /// Index the character that left had in the ranch.
+    pub ranch_index: u32,
+}
+impl_command_traits!(LeaveRanchNotify, CommandId::AcCmdCRLeaveRanchNotify);
@@ -26,8 +26,9 @@ impl_command_traits!(MountFamilyTreeOk, CommandId::AcCmdCRMountFamilyTreeOK);
 
 #[derive(Debug, Default, DekuRead, DekuWrite)]
 pub struct MountFamilyTreeItem {
-    pub unk0: u8,
-    pub unk1: CString,
-    pub unk2: u8,
-    pub unk3: u16,
+    /// Sire and dam first, then the sire's parents followed by the dam's parents.
+    pub position: u8,
+    pub name: CString,
+    pub grade: u8,
+    pub tid: u16,
 }
@@ -2,17 +2,49 @@ use deku::{DekuRead, DekuWrite};
 
 use crate::{commands::RestOfBuffer, impl_command_traits, packet::CommandId};
 
+/// Emote or interaction performed by a character in the ranch.
+#[derive(Debug, Default, Clone, DekuRead, DekuWrite)]
+pub struct RanchAction {
+    pub action_id: u16,
+    pub variant: u8,
+}
+impl RanchAction {
+    /// Checks that nothing came after the action. Whatever that could be isn't understood,
+    /// and other clients would have to be sent it as it is.
+    pub fn check_no_extra_data(&self, extra: &RestOfBuffer) -> Result<(), String> {
+        if extra.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Ranch action {} with variant {} came with {} bytes of unknown data",
+                self.action_id,
+                self.variant,
+                extra.bytes.len()
+            ))
+        }
+    }
+}
+
+/// Any data past the action takes the rest of the command, so it has to be the last field.
 #[derive(Debug, Default, DekuRead, DekuWrite)]
 pub struct RanchCmdAction {
-    unk0: u16,
-    snapshot: RestOfBuffer,
+    pub action: RanchAction,
+    pub extra: RestOfBuffer,
 }
 impl_command_traits!(RanchCmdAction, CommandId::AcCmdCRRanchCmdAction);
 
+/// Same as [RanchCmdAction], but aimed at another character in the ranch.
 #[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct RanchCmdActionTo {
+    pub target_character_id: u32,
+    pub action: RanchAction,
+    pub extra: RestOfBuffer,
+}
+impl_command_traits!(RanchCmdActionTo, CommandId::AcCmdCRRanchCmdActionTo);
+
+#[derive(Debug, Default, Clone, DekuRead, DekuWrite)]
 pub struct RanchCmdActionNotify {
-    unk0: u16,
-    unk1: u16,
-    unk2: u8,
+    pub ranch_index: u16,
+    pub action: RanchAction,
 }
 impl_command_traits!(RanchCmdActionNotify, CommandId::AcCmdCRRanchCmdActionNotify);
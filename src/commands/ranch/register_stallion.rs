@@ -0,0 +1,23 @@
+use deku::{DekuRead, DekuWrite};
+
+use crate::{impl_command_traits, packet::CommandId};
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct RegisterStallion {
+    pub horse_uid: u32,
+    pub price: u32,
+}
+impl_command_traits!(RegisterStallion, CommandId::AcCmdCRRegisterStallion);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct RegisterStallionCancel {}
+impl_command_traits!(
+    RegisterStallionCancel,
+    CommandId::AcCmdCRRegisterStallionCancel
+);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct RegisterStallionOk {
+    pub horse_uid: u32,
+}
+impl_command_traits!(RegisterStallionOk, CommandId::AcCmdCRRegisterStallionOK);
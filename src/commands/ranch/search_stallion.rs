@@ -21,6 +21,7 @@ pub struct SearchStallion {
     pub unk6: u8,
     pub unk7: u8,
     pub unk8: u8,
+    /// Seemingly the skin, mane and tail ids to filter by. Empty lists match anything.
     pub unk9: [LengthPrefixedVec<1, u32>; 3],
     pub unk10: u8,
 }
@@ -0,0 +1,20 @@
+use deku::{DekuRead, DekuWrite};
+
+use crate::{impl_command_traits, packet::CommandId};
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct UnregisterStallion {
+    pub horse_uid: u32,
+}
+impl_command_traits!(UnregisterStallion, CommandId::AcCmdCRUnregisterStallion);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct UnregisterStallionCancel {}
+impl_command_traits!(
+    UnregisterStallionCancel,
+    CommandId::AcCmdCRUnregisterStallionCancel
+);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct UnregisterStallionOk {}
+impl_command_traits!(UnregisterStallionOk, CommandId::AcCmdCRUnregisterStallionOK);
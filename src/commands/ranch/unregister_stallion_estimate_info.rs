@@ -0,0 +1,32 @@
+use deku::{DekuRead, DekuWrite};
+
+use crate::{impl_command_traits, packet::CommandId};
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct UnregisterStallionEstimateInfo {
+    pub horse_uid: u32,
+}
+impl_command_traits!(
+    UnregisterStallionEstimateInfo,
+    CommandId::AcCmdCRUnregisterStallionEstimateInfo
+);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct UnregisterStallionEstimateInfoCancel {}
+impl_command_traits!(
+    UnregisterStallionEstimateInfoCancel,
+    CommandId::AcCmdCRUnregisterStallionEstimateInfoCancel
+);
+
+#[derive(Debug, Default, DekuRead, DekuWrite)]
+pub struct UnregisterStallionEstimateInfoOk {
+    pub unk0: u32,
+    pub times_mated: u32,
+    pub mating_compensation: u32,
+    pub unk3: u32,
+    pub mating_price: u32,
+}
+impl_command_traits!(
+    UnregisterStallionEstimateInfoOk,
+    CommandId::AcCmdCRUnregisterStallionEstimateInfoOK
+);
@@ -1,4 +1,5 @@
 pub mod address;
+pub mod alicia_time;
 pub mod character;
 pub mod horse;
 pub mod item;
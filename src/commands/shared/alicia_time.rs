@@ -0,0 +1,107 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Date as the client packs it into a `u32`, from the least significant bits:
+/// year (12 bits), month (4 bits), day (5 bits), hour (5 bits) and minute (6 bits).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AliciaTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+}
+impl AliciaTime {
+    pub fn now() -> Self {
+        let unix_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        Self::from_unix_time(unix_time)
+    }
+
+    /// Converts seconds since the Unix epoch (UTC)
+    pub fn from_unix_time(unix_time: i64) -> Self {
+        let days = unix_time.div_euclid(86400);
+        let seconds_of_day = unix_time.rem_euclid(86400);
+
+        // Civil date from days since the epoch, see https://howardhinnant.github.io/date_algorithms.html
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let day_of_era = z.rem_euclid(146097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        AliciaTime {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds_of_day / 3600) as u8,
+            minute: (seconds_of_day % 3600 / 60) as u8,
+        }
+    }
+
+    pub fn to_unix_time(self) -> i64 {
+        let year = self.year as i64 - if self.month <= 2 { 1 } else { 0 };
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let month = self.month as i64;
+        let day_of_year =
+            (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146097 + day_of_era - 719468;
+        days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60
+    }
+
+    /// Whole days elapsed since `earlier`. Negative if `earlier` is actually later.
+    pub fn days_since(self, earlier: AliciaTime) -> i64 {
+        (self.to_unix_time() - earlier.to_unix_time()).div_euclid(86400)
+    }
+}
+impl From<u32> for AliciaTime {
+    fn from(value: u32) -> Self {
+        AliciaTime {
+            year: (value & 0xFFF) as u16,
+            month: ((value >> 12) & 0xF) as u8,
+            day: ((value >> 16) & 0x1F) as u8,
+            hour: ((value >> 21) & 0x1F) as u8,
+            minute: ((value >> 26) & 0x3F) as u8,
+        }
+    }
+}
+impl From<AliciaTime> for u32 {
+    fn from(time: AliciaTime) -> Self {
+        (time.year as u32 & 0xFFF)
+            | (time.month as u32 & 0xF) << 12
+            | (time.day as u32 & 0x1F) << 16
+            | (time.hour as u32 & 0x1F) << 21
+            | (time.minute as u32 & 0x3F) << 26
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alicia_time_conversions() {
+        let time = AliciaTime::from(3097585636);
+        assert_eq!(
+            time,
+            AliciaTime {
+                year: 2020,
+                month: 6,
+                day: 1,
+                hour: 5,
+                minute: 46,
+            }
+        );
+        assert_eq!(u32::from(time), 3097585636);
+        assert_eq!(AliciaTime::from_unix_time(time.to_unix_time()), time);
+        assert_eq!(time.to_unix_time(), 1590990360);
+    }
+}
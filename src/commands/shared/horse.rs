@@ -1,11 +1,13 @@
 use std::ffi::CString;
 
+use alicia_derive::SqlColumns;
 use deku::{DekuRead, DekuWrite};
 use postgres_from_row::FromRow;
+use rand::Rng;
 
 use crate::database::{CStringSql, U8Sql, U16Sql, U32Sql};
 
-#[derive(Debug, Default, Clone, DekuRead, DekuWrite, FromRow)]
+#[derive(Debug, Default, Clone, DekuRead, DekuWrite, FromRow, SqlColumns)]
 pub struct Horse {
     #[from_row(from = "U32Sql")]
     pub uid: u32,
@@ -44,7 +46,7 @@ pub struct Horse {
     pub val17: u32,
 }
 
-#[derive(Debug, Clone, DekuRead, DekuWrite, FromRow)]
+#[derive(Debug, Clone, DekuRead, DekuWrite, FromRow, SqlColumns)]
 pub struct Parts {
     #[from_row(from = "U8Sql")]
     pub skin_id: u8,
@@ -67,16 +69,20 @@ impl Default for Parts {
 }
 impl Parts {
     pub fn random() -> Self {
+        Self::random_with(&mut rand::rng())
+    }
+
+    pub fn random_with<R: Rng + ?Sized>(rng: &mut R) -> Self {
         Self {
-            skin_id: rand::random_range(1..=15),
-            mane_id: rand::random_range(0..=40),
-            tail_id: rand::random_range(0..=30),
+            skin_id: rng.random_range(1..=15),
+            mane_id: rng.random_range(0..=40),
+            tail_id: rng.random_range(0..=30),
             face_id: 0,
         }
     }
 }
 
-#[derive(Debug, Default, Clone, DekuRead, DekuWrite, FromRow)]
+#[derive(Debug, Default, Clone, DekuRead, DekuWrite, FromRow, SqlColumns)]
 pub struct Appearance {
     #[from_row(from = "U8Sql")]
     pub scale: u8,
@@ -90,7 +96,7 @@ pub struct Appearance {
     pub body_volume: u8,
 }
 
-#[derive(Debug, Default, Clone, DekuRead, DekuWrite, FromRow)]
+#[derive(Debug, Default, Clone, DekuRead, DekuWrite, FromRow, SqlColumns)]
 pub struct Stats {
     #[from_row(from = "U32Sql")]
     pub agility: u32,
@@ -104,7 +110,7 @@ pub struct Stats {
     pub spirit: u32,
 }
 
-#[derive(Debug, Default, Clone, DekuRead, DekuWrite, FromRow)]
+#[derive(Debug, Default, Clone, DekuRead, DekuWrite, FromRow, SqlColumns)]
 pub struct Vals0 {
     #[from_row(from = "U16Sql")]
     pub stamina: u16,
@@ -142,7 +148,7 @@ pub struct Vals0 {
     pub val10: u16,
 }
 
-#[derive(Debug, Default, Clone, DekuRead, DekuWrite, FromRow)]
+#[derive(Debug, Default, Clone, DekuRead, DekuWrite, FromRow, SqlColumns)]
 pub struct Vals1 {
     #[from_row(from = "U8Sql", rename = "vals1_val0")]
     pub val0: u8,
@@ -184,7 +190,7 @@ pub struct Vals1 {
     pub emblem: u16,
 }
 
-#[derive(Debug, Default, Clone, DekuRead, DekuWrite, FromRow)]
+#[derive(Debug, Default, Clone, DekuRead, DekuWrite, FromRow, SqlColumns)]
 pub struct Mastery {
     #[from_row(from = "U32Sql")]
     pub spur_magic_count: u32,
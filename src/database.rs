@@ -1,16 +1,43 @@
 use std::{error::Error, ffi::CString, path::PathBuf, time::Duration};
 
 use postgresql_embedded::{PostgreSQL, Settings};
+use sqlx::{
+    SqlitePool,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode},
+};
 use tokio_postgres::{
-    Config, NoTls, Transaction,
+    Config, NoTls,
     types::{FromSql, IsNull, ToSql, Type, accepts, private::BytesMut, to_sql_checked},
 };
 
-use crate::settings::DatabaseSettings;
+use crate::{
+    database::{
+        account::AccountRepository,
+        character::CharacterRepository,
+        horse::HorseRepository,
+        item::ItemRepository,
+        migrations::{
+            MIGRATIONS_PATH, SQLITE_MIGRATIONS_PATH, load_migrations, run_migrations,
+            run_sqlite_migrations,
+        },
+        ranch::RanchRepository,
+        wallet::WalletRepository,
+        wishlist::WishlistRepository,
+    },
+    settings::DatabaseSettings,
+};
 
 pub mod account;
 pub mod character;
 pub mod horse;
+pub mod item;
+#[cfg(any(test, feature = "dev"))]
+pub mod memory;
+pub mod migrations;
+pub mod ranch;
+pub mod sqlite;
+pub mod wallet;
+pub mod wishlist;
 
 const DATABASE_NAME: &str = "alicia";
 const DATABASE_PATH: &str = "database";
@@ -47,8 +74,42 @@ pub async fn init_database(
     }
 }
 
+/// Every repository, as implemented by the transactions of each backend
+pub trait Repositories:
+    AccountRepository
+    + CharacterRepository
+    + HorseRepository
+    + ItemRepository
+    + RanchRepository
+    + WalletRepository
+    + WishlistRepository
+    + Send
+{
+}
+impl<T> Repositories for T where
+    T: AccountRepository
+        + CharacterRepository
+        + HorseRepository
+        + ItemRepository
+        + RanchRepository
+        + WalletRepository
+        + WishlistRepository
+        + Send
+{
+}
+
+/// Transaction on whichever backend the database uses
+pub type Transaction<'a> = dyn Repositories + 'a;
+
+enum Backend {
+    Postgres(deadpool_postgres::Pool),
+    #[cfg(any(test, feature = "dev"))]
+    Memory(Box<memory::MemoryDatabase>),
+    Sqlite(SqlitePool),
+}
+
 pub struct Database {
-    db_pool: deadpool_postgres::Pool,
+    backend: Backend,
 }
 impl Database {
     pub async fn new(
@@ -64,9 +125,10 @@ impl Database {
             .build()
             .unwrap();
 
-        let client = db_pool.get().await?;
+        let mut client = db_pool.get().await?;
 
-        if db_settings.wipe_on_startup {
+        // Nothing is touched on a dry run, not even to wipe it
+        if db_settings.wipe_on_startup && !db_settings.migrations_dry_run {
             // Wipe existing schema
             let _ = client
                 .batch_execute(
@@ -79,27 +141,105 @@ impl Database {
             ",
                 )
                 .await;
-            let schema_path = PathBuf::from("res/schema.sql");
-            let schema = tokio::fs::read_to_string(schema_path).await?;
-            client.batch_execute(&schema).await?;
         }
 
-        Ok(Database { db_pool })
+        // Bring the schema up to date
+        let migrations = load_migrations(&PathBuf::from(MIGRATIONS_PATH)).await?;
+        let pending_count =
+            run_migrations(&mut client, &migrations, db_settings.migrations_dry_run).await?;
+        if pending_count == 0 {
+            println!("Database schema is up to date");
+        }
+
+        Ok(Database {
+            backend: Backend::Postgres(db_pool),
+        })
+    }
+
+    pub async fn new_sqlite(db_settings: &DatabaseSettings) -> Result<Database, Box<dyn Error>> {
+        let path = PathBuf::from(&db_settings.sqlite_path);
+        if db_settings.wipe_on_startup && !db_settings.migrations_dry_run {
+            for suffix in ["", "-wal", "-shm"] {
+                let mut file = path.clone().into_os_string();
+                file.push(suffix);
+                match tokio::fs::remove_file(&file).await {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                    _ => {}
+                }
+            }
+        }
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let options = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .foreign_keys(true);
+        let db_pool = SqlitePool::connect_with(options).await?;
+
+        let migrations = load_migrations(&PathBuf::from(SQLITE_MIGRATIONS_PATH)).await?;
+        let pending_count =
+            run_sqlite_migrations(&db_pool, &migrations, db_settings.migrations_dry_run).await?;
+        if pending_count == 0 {
+            println!("Database schema is up to date");
+        }
+
+        Ok(Database {
+            backend: Backend::Sqlite(db_pool),
+        })
     }
 
+    /// Database that starts out empty and doesn't outlive the process
+    #[cfg(any(test, feature = "dev"))]
+    pub fn in_memory() -> Database {
+        Database {
+            backend: Backend::Memory(Box::default()),
+        }
+    }
+
+    /// Runs the function in a transaction, committing it only if the function succeeds.
+    /// On Postgres each transaction gets its own pooled connection, so transactions from
+    /// different tasks don't wait on each other
     pub async fn run_in_transaction<T>(
-        &mut self,
-        mut function: impl AsyncFnMut(&mut Transaction) -> Result<T, Box<dyn Error>>,
-    ) -> Result<T, Box<dyn Error>> {
-        let mut psql_client = self.db_pool.get().await?;
-        let mut transaction = psql_client.transaction().await?;
-        let result = function(&mut transaction).await;
-        if result.is_ok() {
-            transaction.commit().await?;
-        } else {
-            transaction.rollback().await?;
+        &self,
+        function: impl AsyncFnOnce(&mut Transaction) -> Result<T, Box<dyn Error + Send + Sync>>,
+    ) -> Result<T, Box<dyn Error + Send + Sync>> {
+        match &self.backend {
+            Backend::Postgres(db_pool) => {
+                let mut psql_client = db_pool.get().await?;
+                let mut transaction = psql_client.transaction().await?;
+                let result = function(&mut *transaction).await;
+                if result.is_ok() {
+                    transaction.commit().await?;
+                } else {
+                    transaction.rollback().await?;
+                }
+                result
+            }
+            #[cfg(any(test, feature = "dev"))]
+            Backend::Memory(memory) => {
+                let mut transaction = memory.transaction().await;
+                let result = function(&mut transaction).await;
+                if result.is_ok() {
+                    transaction.commit();
+                }
+                result
+            }
+            Backend::Sqlite(db_pool) => {
+                // Writers queue up on the database lock when starting instead of failing
+                // halfway through, much like they'd wait on row locks in Postgres
+                let mut transaction = db_pool.begin_with("BEGIN IMMEDIATE").await?;
+                let result = function(&mut transaction).await;
+                if result.is_ok() {
+                    transaction.commit().await?;
+                } else {
+                    transaction.rollback().await?;
+                }
+                result
+            }
         }
-        result
     }
 }
 
@@ -186,3 +326,134 @@ macro_rules! define_unsigned_int_db_wrapper {
 define_unsigned_int_db_wrapper!(U8Sql, u8, i16, INT2, 2);
 define_unsigned_int_db_wrapper!(U16Sql, u16, i16, INT2, 2);
 define_unsigned_int_db_wrapper!(U32Sql, u32, i32, INT4, 4);
+
+/// Value of a single column, as written and read through [`SqlColumns`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlValue {
+    Integer(i64),
+    Text(CString),
+}
+impl ToSql for SqlValue {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        w: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        // Unsigned values are reinterpreted to fit the column, like the wrappers above do
+        match self {
+            SqlValue::Integer(value) if *ty == Type::INT2 => (*value as i16).to_sql(ty, w),
+            SqlValue::Integer(value) if *ty == Type::INT4 => (*value as i32).to_sql(ty, w),
+            SqlValue::Integer(value) if *ty == Type::INT8 => value.to_sql(ty, w),
+            SqlValue::Text(value) if *ty == Type::VARCHAR || *ty == Type::TEXT => {
+                CStringSql::from(value.clone()).to_sql(ty, w)
+            }
+            _ => Err(format!("Can't write {:?} to a {} column", self, ty).into()),
+        }
+    }
+    accepts!(INT2, INT4, INT8, VARCHAR, TEXT);
+    to_sql_checked!();
+}
+
+/// Field types that [`SqlColumns`] knows how to map to a column
+pub trait SqlField: Sized {
+    fn to_sql_value(&self) -> SqlValue;
+    fn from_sql_value(value: SqlValue) -> Result<Self, Box<dyn Error + Send + Sync>>;
+}
+impl SqlField for CString {
+    fn to_sql_value(&self) -> SqlValue {
+        SqlValue::Text(self.clone())
+    }
+    fn from_sql_value(value: SqlValue) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        match value {
+            SqlValue::Text(value) => Ok(value),
+            _ => Err(format!("Expected text, got {:?}", value).into()),
+        }
+    }
+}
+macro_rules! impl_unsigned_sql_field {
+    ($($innertype:ty),*) => {
+        $(
+            impl SqlField for $innertype {
+                fn to_sql_value(&self) -> SqlValue {
+                    SqlValue::Integer(i64::from(*self))
+                }
+                fn from_sql_value(value: SqlValue) -> Result<Self, Box<dyn Error + Send + Sync>> {
+                    match value {
+                        SqlValue::Integer(value) => Ok(<$innertype>::try_from(value)?),
+                        _ => Err(format!("Expected an integer, got {:?}", value).into()),
+                    }
+                }
+            }
+        )*
+    };
+}
+impl_unsigned_sql_field!(u8, u16, u32);
+
+/// Row mapping generated by `#[derive(SqlColumns)]` from the `FromRow` attributes of a struct,
+/// so that queries going through every column don't have to list them by hand
+pub trait SqlColumns: Sized {
+    fn write_columns(columns: &mut Vec<&'static str>);
+    fn write_values(&self, values: &mut Vec<SqlValue>);
+    /// Builds the struct out of the value `get` returns for each column
+    fn read_columns(
+        get: &mut dyn FnMut(&'static str) -> Result<SqlValue, Box<dyn Error + Send + Sync>>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>>;
+
+    /// Column names, in the same order as [`SqlColumns::values`]
+    fn columns() -> Vec<&'static str> {
+        let mut columns = Vec::new();
+        Self::write_columns(&mut columns);
+        columns
+    }
+    fn values(&self) -> Vec<SqlValue> {
+        let mut values = Vec::new();
+        self.write_values(&mut values);
+        values
+    }
+}
+
+/// Parameter syntax of Postgres, for [`insert_sql`] and [`update_sql`]
+pub fn postgres_placeholder(index: usize) -> String {
+    format!("${}", index)
+}
+
+pub fn sql_params(values: &[SqlValue]) -> Vec<&(dyn ToSql + Sync)> {
+    values
+        .iter()
+        .map(|value| value as &(dyn ToSql + Sync))
+        .collect()
+}
+
+/// `INSERT` of the given columns, with `placeholder` writing the parameter for each 1-based index
+pub fn insert_sql(table: &str, columns: &[&str], placeholder: impl Fn(usize) -> String) -> String {
+    let placeholders = (1..=columns.len())
+        .map(placeholder)
+        .collect::<Vec<String>>();
+    format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        table,
+        columns.join(", "),
+        placeholders.join(", ")
+    )
+}
+
+/// `UPDATE` of the given columns on the row matching `key`, which comes as the last parameter
+pub fn update_sql(
+    table: &str,
+    columns: &[&str],
+    key: &str,
+    placeholder: impl Fn(usize) -> String,
+) -> String {
+    let assignments = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| format!("{} = {}", column, placeholder(i + 1)))
+        .collect::<Vec<String>>();
+    format!(
+        "UPDATE {} SET {} WHERE {} = {}",
+        table,
+        assignments.join(", "),
+        key,
+        placeholder(columns.len() + 1)
+    )
+}
@@ -1,5 +1,6 @@
 use std::error::Error;
 
+use async_trait::async_trait;
 use postgres_from_row::FromRow;
 use tokio_postgres::Transaction;
 
@@ -8,148 +9,185 @@ use crate::{
     entities::character::Character,
 };
 
-pub async fn get_character_by_member_no<'a>(
-    transaction: &mut Transaction<'a>,
-    member_no: u32,
-) -> Result<Option<Character>, Box<dyn Error>> {
-    let row_opt = transaction
-        .query_opt(
-            "SELECT * FROM characters WHERE member_no = $1",
-            &[&U32Sql::from(member_no)],
-        )
-        .await?;
-    if let Some(row) = row_opt {
-        let character = Character::try_from_row(&row)?;
-        Ok(Some(character))
-    } else {
-        Ok(None)
-    }
+/// Number of horses a newly created character can own.
+pub const DEFAULT_MOUNT_SLOTS: u32 = 3;
+/// Upper limit for the mount slots a character can expand to.
+pub const MAX_MOUNT_SLOTS: u32 = 10;
+
+#[async_trait]
+pub trait CharacterRepository {
+    async fn get_character_by_member_no(
+        &mut self,
+        member_no: u32,
+    ) -> Result<Option<Character>, Box<dyn Error + Send + Sync>>;
+    async fn get_character_by_id(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Option<Character>, Box<dyn Error + Send + Sync>>;
+    async fn insert_character(
+        &mut self,
+        member_no: u32,
+        character: &mut Character,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+    async fn update_character(
+        &mut self,
+        character: &Character,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+    async fn delete_character(
+        &mut self,
+        character_id: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
 }
 
-pub async fn get_character_by_id<'a>(
-    transaction: &mut Transaction<'a>,
-    character_id: u32,
-) -> Result<Option<Character>, Box<dyn Error>> {
-    let row_opt = transaction
-        .query_opt(
-            "SELECT * FROM characters WHERE character_id = $1",
-            &[&U32Sql::from(character_id)],
-        )
-        .await?;
-    if let Some(row) = row_opt {
-        let character = Character::try_from_row(&row)?;
-        Ok(Some(character))
-    } else {
-        Ok(None)
+#[async_trait]
+impl CharacterRepository for Transaction<'_> {
+    async fn get_character_by_member_no(
+        &mut self,
+        member_no: u32,
+    ) -> Result<Option<Character>, Box<dyn Error + Send + Sync>> {
+        let row_opt = self
+            .query_opt(
+                "SELECT * FROM characters WHERE member_no = $1",
+                &[&U32Sql::from(member_no)],
+            )
+            .await?;
+        if let Some(row) = row_opt {
+            let character = Character::try_from_row(&row)?;
+            Ok(Some(character))
+        } else {
+            Ok(None)
+        }
     }
-}
 
-pub async fn insert_character<'a>(
-    transaction: &mut Transaction<'a>,
-    member_no: u32,
-    character: &mut Character,
-) -> Result<(), Box<dyn Error>> {
-    let row = transaction
-        .query_one(
-            "INSERT INTO characters (
-                member_no,
-                nickname,
-                mount_uid,
-                char_id,
-                mouth_serial_id,
-                face_serial_id,
-                parts_val0,
-                appearance_val0,
-                head_size,
-                height,
-                thigh_volume,
-                leg_volume,
-                appearance_val1,
-                create_character_unk0
-            ) VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12,$13,$14)
-             RETURNING character_id",
-            &[
-                &U32Sql::from(member_no),
-                &character.nickname,
-                &U32Sql::from(character.mount_uid),
-                &U8Sql::from(character.character.parts.char_id),
-                &U8Sql::from(character.character.parts.mouth_serial_id),
-                &U8Sql::from(character.character.parts.face_serial_id),
-                &U8Sql::from(character.character.parts.val0),
-                &U16Sql::from(character.character.appearance.val0),
-                &U16Sql::from(character.character.appearance.head_size),
-                &U16Sql::from(character.character.appearance.height),
-                &U16Sql::from(character.character.appearance.thigh_volume),
-                &U16Sql::from(character.character.appearance.leg_volume),
-                &U16Sql::from(character.character.appearance.val1),
-                &U32Sql::from(character.create_character_unk0),
-            ],
-        )
-        .await?;
-    let character_id: U32Sql = row.try_get(0)?;
-    character.character_id = character_id.into();
-    Ok(())
-}
+    async fn get_character_by_id(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Option<Character>, Box<dyn Error + Send + Sync>> {
+        let row_opt = self
+            .query_opt(
+                "SELECT * FROM characters WHERE character_id = $1",
+                &[&U32Sql::from(character_id)],
+            )
+            .await?;
+        if let Some(row) = row_opt {
+            let character = Character::try_from_row(&row)?;
+            Ok(Some(character))
+        } else {
+            Ok(None)
+        }
+    }
 
-pub async fn update_character<'a>(
-    transaction: &mut Transaction<'a>,
-    character: &Character,
-) -> Result<(), Box<dyn Error>> {
-    let rows_affected = transaction
-        .execute(
-            "UPDATE characters SET
-                nickname = $1,
-                mount_uid = $2,
-                char_id = $3,
-                mouth_serial_id = $4,
-                face_serial_id = $5,
-                parts_val0 = $6,
-                appearance_val0 = $7,
-                head_size = $8,
-                height = $9,
-                thigh_volume = $10,
-                leg_volume = $11,
-                appearance_val1 = $12,
-                create_character_unk0 = $13
-            WHERE character_id = $14",
-            &[
-                &character.nickname,
-                &U32Sql::from(character.mount_uid),
-                &U8Sql::from(character.character.parts.char_id),
-                &U8Sql::from(character.character.parts.mouth_serial_id),
-                &U8Sql::from(character.character.parts.face_serial_id),
-                &U8Sql::from(character.character.parts.val0),
-                &U16Sql::from(character.character.appearance.val0),
-                &U16Sql::from(character.character.appearance.head_size),
-                &U16Sql::from(character.character.appearance.height),
-                &U16Sql::from(character.character.appearance.thigh_volume),
-                &U16Sql::from(character.character.appearance.leg_volume),
-                &U16Sql::from(character.character.appearance.val1),
-                &U32Sql::from(character.create_character_unk0),
-                &U32Sql::from(character.character_id),
-            ],
-        )
-        .await?;
-    if rows_affected == 1 {
+    async fn insert_character(
+        &mut self,
+        member_no: u32,
+        character: &mut Character,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let row = self
+            .query_one(
+                "INSERT INTO characters (
+                    member_no,
+                    nickname,
+                    mount_uid,
+                    mount_slots,
+                    char_id,
+                    mouth_serial_id,
+                    face_serial_id,
+                    parts_val0,
+                    appearance_val0,
+                    head_size,
+                    height,
+                    thigh_volume,
+                    leg_volume,
+                    appearance_val1,
+                    create_character_unk0
+                ) VALUES ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12,$13,$14,$15)
+                 RETURNING character_id",
+                &[
+                    &U32Sql::from(member_no),
+                    &character.nickname,
+                    &U32Sql::from(character.mount_uid),
+                    &U32Sql::from(character.mount_slots),
+                    &U8Sql::from(character.character.parts.char_id),
+                    &U8Sql::from(character.character.parts.mouth_serial_id),
+                    &U8Sql::from(character.character.parts.face_serial_id),
+                    &U8Sql::from(character.character.parts.val0),
+                    &U16Sql::from(character.character.appearance.val0),
+                    &U16Sql::from(character.character.appearance.head_size),
+                    &U16Sql::from(character.character.appearance.height),
+                    &U16Sql::from(character.character.appearance.thigh_volume),
+                    &U16Sql::from(character.character.appearance.leg_volume),
+                    &U16Sql::from(character.character.appearance.val1),
+                    &U32Sql::from(character.create_character_unk0),
+                ],
+            )
+            .await?;
+        let character_id: U32Sql = row.try_get(0)?;
+        character.character_id = character_id.into();
         Ok(())
-    } else {
-        Err(format!("Unexpected number of rows affected: {}", rows_affected).into())
     }
-}
 
-pub async fn delete_character<'a>(
-    transaction: &mut Transaction<'a>,
-    character_id: u32,
-) -> Result<(), Box<dyn Error>> {
-    let rows = transaction
-        .execute(
-            "DELETE FROM characters WHERE character_id = $1",
-            &[&character_id],
-        )
-        .await?;
-    if rows == 1 {
-        Ok(())
-    } else {
-        Err(format!("Unexpected number of rows affected: {}", rows).into())
+    async fn update_character(
+        &mut self,
+        character: &Character,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let rows_affected = self
+            .execute(
+                "UPDATE characters SET
+                    nickname = $1,
+                    mount_uid = $2,
+                    mount_slots = $3,
+                    char_id = $4,
+                    mouth_serial_id = $5,
+                    face_serial_id = $6,
+                    parts_val0 = $7,
+                    appearance_val0 = $8,
+                    head_size = $9,
+                    height = $10,
+                    thigh_volume = $11,
+                    leg_volume = $12,
+                    appearance_val1 = $13,
+                    create_character_unk0 = $14
+                WHERE character_id = $15",
+                &[
+                    &character.nickname,
+                    &U32Sql::from(character.mount_uid),
+                    &U32Sql::from(character.mount_slots),
+                    &U8Sql::from(character.character.parts.char_id),
+                    &U8Sql::from(character.character.parts.mouth_serial_id),
+                    &U8Sql::from(character.character.parts.face_serial_id),
+                    &U8Sql::from(character.character.parts.val0),
+                    &U16Sql::from(character.character.appearance.val0),
+                    &U16Sql::from(character.character.appearance.head_size),
+                    &U16Sql::from(character.character.appearance.height),
+                    &U16Sql::from(character.character.appearance.thigh_volume),
+                    &U16Sql::from(character.character.appearance.leg_volume),
+                    &U16Sql::from(character.character.appearance.val1),
+                    &U32Sql::from(character.create_character_unk0),
+                    &U32Sql::from(character.character_id),
+                ],
+            )
+            .await?;
+        if rows_affected == 1 {
+            Ok(())
+        } else {
+            Err(format!("Unexpected number of rows affected: {}", rows_affected).into())
+        }
+    }
+
+    async fn delete_character(
+        &mut self,
+        character_id: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let rows = self
+            .execute(
+                "DELETE FROM characters WHERE character_id = $1",
+                &[&character_id],
+            )
+            .await?;
+        if rows == 1 {
+            Ok(())
+        } else {
+            Err(format!("Unexpected number of rows affected: {}", rows).into())
+        }
     }
 }
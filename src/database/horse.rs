@@ -1,255 +1,562 @@
 use std::error::Error;
 
+use async_trait::async_trait;
 use postgres_from_row::FromRow;
 use tokio_postgres::Transaction;
 
 use crate::{
     commands::shared::horse::Horse,
-    database::{CStringSql, U8Sql, U16Sql, U32Sql},
+    database::{
+        SqlColumns, SqlField, SqlValue, U8Sql, U32Sql, insert_sql, postgres_placeholder,
+        sql_params, update_sql,
+    },
+    entities::{
+        lineage::{BreedingRecord, Lineage},
+        stallion::{Stallion, StallionListing},
+    },
+    genetics::{FailureReward, Foal},
 };
 
-pub async fn get_horses_by_character_id<'a>(
-    transaction: &mut Transaction<'a>,
-    character_id: u32,
-) -> Result<Vec<Horse>, Box<dyn Error>> {
-    let rows = transaction
-        .query(
-            "SELECT * FROM horses WHERE character_id = $1",
-            &[&U32Sql::from(character_id)],
-        )
-        .await?;
-    Ok(rows.iter().map(|row| Horse::from_row(row)).collect())
+pub const STALLION_LISTING_SELECT: &str = "SELECT
+        horses.*,
+        stallions.horse_uid, stallions.price, stallions.times_mated,
+        characters.character_id AS owner_character_id,
+        characters.nickname AS owner_nickname
+    FROM stallions
+    JOIN horses ON horses.uid = stallions.horse_uid
+    JOIN characters ON characters.character_id = horses.character_id";
+
+/// Every column of the horse but its uid, which is generated on insert and looked up on update
+pub fn horse_columns(horse: &Horse) -> (Vec<&'static str>, Vec<SqlValue>) {
+    Horse::columns()
+        .into_iter()
+        .zip(horse.values())
+        .filter(|(column, _)| *column != "uid")
+        .unzip()
 }
 
-pub async fn get_horse_by_uid<'a>(
-    transaction: &mut Transaction<'a>,
-    uid: u32,
-) -> Result<Option<Horse>, Box<dyn Error>> {
-    let row_opt = transaction
-        .query_opt("SELECT * FROM horses WHERE uid = $1", &[&U32Sql::from(uid)])
-        .await?;
-    if let Some(row) = row_opt {
-        let horse = Horse::try_from_row(&row)?;
-        Ok(Some(horse))
-    } else {
-        Ok(None)
+/// Criteria for listing registered stallions in the breeding market.
+/// Empty id lists match any value.
+#[derive(Debug, Default)]
+pub struct StallionFilter {
+    pub exclude_character_id: u32,
+    pub skin_ids: Vec<u32>,
+    pub mane_ids: Vec<u32>,
+    pub tail_ids: Vec<u32>,
+    pub limit: u32,
+}
+
+#[async_trait]
+pub trait HorseRepository {
+    async fn get_horses_by_character_id(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Vec<Horse>, Box<dyn Error + Send + Sync>>;
+    async fn count_horses_by_character_id(
+        &mut self,
+        character_id: u32,
+    ) -> Result<u32, Box<dyn Error + Send + Sync>>;
+    async fn get_horse_by_uid(
+        &mut self,
+        uid: u32,
+    ) -> Result<Option<Horse>, Box<dyn Error + Send + Sync>>;
+    async fn get_horses_by_uids(
+        &mut self,
+        uids: &[u32],
+    ) -> Result<Vec<Horse>, Box<dyn Error + Send + Sync>>;
+    async fn insert_horse(
+        &mut self,
+        character_id: u32,
+        horse: &mut Horse,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+    async fn update_horse(&mut self, horse: &mut Horse)
+    -> Result<(), Box<dyn Error + Send + Sync>>;
+    async fn remove_horse(&mut self, horse_uid: u32) -> Result<(), Box<dyn Error + Send + Sync>>;
+    async fn get_stallion(
+        &mut self,
+        horse_uid: u32,
+    ) -> Result<Option<Stallion>, Box<dyn Error + Send + Sync>>;
+    async fn insert_stallion(
+        &mut self,
+        stallion: &Stallion,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+    async fn remove_stallion(&mut self, horse_uid: u32)
+    -> Result<(), Box<dyn Error + Send + Sync>>;
+    async fn get_stallion_listing(
+        &mut self,
+        horse_uid: u32,
+    ) -> Result<Option<StallionListing>, Box<dyn Error + Send + Sync>>;
+    async fn record_stallion_mating(
+        &mut self,
+        horse_uid: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+    async fn search_stallions(
+        &mut self,
+        filter: &StallionFilter,
+    ) -> Result<Vec<StallionListing>, Box<dyn Error + Send + Sync>>;
+    async fn get_lineage(
+        &mut self,
+        horse_uid: u32,
+    ) -> Result<Option<Lineage>, Box<dyn Error + Send + Sync>>;
+    /// Breeding history of the given horses. Horses that don't exist are left out.
+    async fn get_breeding_records(
+        &mut self,
+        uids: &[u32],
+    ) -> Result<Vec<BreedingRecord>, Box<dyn Error + Send + Sync>>;
+    /// Records the parents of a newly bred horse, along with its grandparents as known
+    /// from the parents' own lineage.
+    async fn insert_lineage(&mut self, foal: &Foal) -> Result<(), Box<dyn Error + Send + Sync>>;
+    /// Cards offered to the character after its last failed breeding attempt, in the order
+    /// they were drawn. Empty if there are none to choose from.
+    async fn get_failure_cards(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Vec<FailureReward>, Box<dyn Error + Send + Sync>>;
+    /// Replaces the cards offered to the character.
+    async fn set_failure_cards(
+        &mut self,
+        character_id: u32,
+        cards: &[FailureReward],
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+    /// Removes the cards offered to the character and returns them, since choosing one uses
+    /// all of them up.
+    async fn take_failure_cards(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Vec<FailureReward>, Box<dyn Error + Send + Sync>>;
+}
+
+/// Failure card as stored, the item tid being `None` for carrots
+pub fn failure_card_columns(card: &FailureReward) -> (Option<u32>, u32) {
+    match card {
+        FailureReward::Carrots(carrots) => (None, *carrots),
+        FailureReward::Item { tid, count } => (Some(*tid), *count),
     }
 }
 
-pub async fn insert_horse<'a>(
-    transaction: &mut Transaction<'a>,
-    character_id: u32,
-    horse: &mut Horse,
-) -> Result<(), Box<dyn Error>> {
-    let row = transaction
-        .query_one(
-            "INSERT INTO horses (
-                character_id,
-                tid, name,
-                skin_id, mane_id, tail_id, face_id,
-                scale, leg_length, leg_volume, body_length, body_volume,
-                agility, control, speed, strength, spirit,
-                rating, class, class_progress, grade, growth_points,
-                stamina, attractiveness, hunger,
-                vals0_val0, vals0_val1, vals0_val2, vals0_val3, vals0_val4,
-                vals0_val5, vals0_val6, vals0_val7, vals0_val8, vals0_val9, vals0_val10,
-                vals1_val0, vals1_val1, date_of_birth, vals1_val3, vals1_val4,
-                class_progression, vals1_val5,
-                potential_level, has_potential, potential_value, vals1_val9,
-                luck, has_luck, vals1_val12, fatigue, vals1_val14, emblem,
-                spur_magic_count, jump_count, sliding_time, gliding_distance,
-                val16, val17
-            ) VALUES (
-                $1,
-                $2, $3,
-                $4, $5, $6, $7,
-                $8, $9, $10, $11, $12,
-                $13, $14, $15, $16, $17,
-                $18, $19, $20, $21, $22,
-                $23, $24, $25,
-                $26, $27, $28, $29, $30,
-                $31, $32, $33, $34, $35, $36,
-                $37, $38, $39, $40, $41,
-                $42, $43,
-                $44, $45, $46, $47,
-                $48, $49, $50, $51, $52, $53,
-                $54, $55, $56, $57,
-                $58, $59
+pub fn failure_card_from_columns(item_tid: Option<u32>, amount: u32) -> FailureReward {
+    match item_tid {
+        None => FailureReward::Carrots(amount),
+        Some(tid) => FailureReward::Item { tid, count: amount },
+    }
+}
+
+#[async_trait]
+impl HorseRepository for Transaction<'_> {
+    async fn get_horses_by_character_id(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Vec<Horse>, Box<dyn Error + Send + Sync>> {
+        let rows = self
+            .query(
+                "SELECT * FROM horses WHERE character_id = $1",
+                &[&U32Sql::from(character_id)],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| Horse::from_row(row)).collect())
+    }
+
+    async fn count_horses_by_character_id(
+        &mut self,
+        character_id: u32,
+    ) -> Result<u32, Box<dyn Error + Send + Sync>> {
+        let row = self
+            .query_one(
+                "SELECT COUNT(*) FROM horses WHERE character_id = $1",
+                &[&U32Sql::from(character_id)],
+            )
+            .await?;
+        let count: i64 = row.try_get(0)?;
+        Ok(count as u32)
+    }
+
+    async fn get_horse_by_uid(
+        &mut self,
+        uid: u32,
+    ) -> Result<Option<Horse>, Box<dyn Error + Send + Sync>> {
+        let row_opt = self
+            .query_opt("SELECT * FROM horses WHERE uid = $1", &[&U32Sql::from(uid)])
+            .await?;
+        if let Some(row) = row_opt {
+            let horse = Horse::try_from_row(&row)?;
+            Ok(Some(horse))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn get_horses_by_uids(
+        &mut self,
+        uids: &[u32],
+    ) -> Result<Vec<Horse>, Box<dyn Error + Send + Sync>> {
+        let uids = uids.iter().map(|uid| *uid as i32).collect::<Vec<i32>>();
+        let rows = self
+            .query("SELECT * FROM horses WHERE uid = ANY($1)", &[&uids])
+            .await?;
+        rows.iter()
+            .map(|row| Horse::try_from_row(row).map_err(|e| e.into()))
+            .collect()
+    }
+
+    async fn insert_horse(
+        &mut self,
+        character_id: u32,
+        horse: &mut Horse,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (mut columns, mut values) = horse_columns(horse);
+        columns.push("character_id");
+        values.push(character_id.to_sql_value());
+        let query = format!(
+            "{} RETURNING uid",
+            insert_sql("horses", &columns, postgres_placeholder)
+        );
+        let row = self.query_one(&query, &sql_params(&values)).await?;
+        let uid: U32Sql = row.try_get(0)?;
+        horse.uid = uid.into();
+        Ok(())
+    }
+
+    async fn update_horse(
+        &mut self,
+        horse: &mut Horse,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (columns, mut values) = horse_columns(horse);
+        values.push(horse.uid.to_sql_value());
+        let query = update_sql("horses", &columns, "uid", postgres_placeholder);
+        let rows_affected = self.execute(&query, &sql_params(&values)).await?;
+        if rows_affected != 1 {
+            Err(format!("Unexpected number of affected rows: {}", rows_affected).into())
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn remove_horse(&mut self, horse_uid: u32) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let rows_affected = self
+            .execute(
+                "DELETE FROM horses WHERE uid = $1",
+                &[&U32Sql::from(horse_uid)],
             )
-            RETURNING uid",
+            .await?;
+        if rows_affected != 1 {
+            Err(format!("More than one row affected: {}", rows_affected).into())
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn get_stallion(
+        &mut self,
+        horse_uid: u32,
+    ) -> Result<Option<Stallion>, Box<dyn Error + Send + Sync>> {
+        let row_opt = self
+            .query_opt(
+                "SELECT * FROM stallions WHERE horse_uid = $1",
+                &[&U32Sql::from(horse_uid)],
+            )
+            .await?;
+        if let Some(row) = row_opt {
+            Ok(Some(Stallion::try_from_row(&row)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn insert_stallion(
+        &mut self,
+        stallion: &Stallion,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.execute(
+            "INSERT INTO stallions (horse_uid, price, times_mated) VALUES ($1, $2, $3)",
             &[
-                &U32Sql::from(character_id),
-                &U32Sql::from(horse.tid),
-                &CStringSql::from(horse.name.clone()),
-                &U8Sql::from(horse.parts.skin_id),
-                &U8Sql::from(horse.parts.mane_id),
-                &U8Sql::from(horse.parts.tail_id),
-                &U8Sql::from(horse.parts.face_id),
-                &U8Sql::from(horse.appearance.scale),
-                &U8Sql::from(horse.appearance.leg_length),
-                &U8Sql::from(horse.appearance.leg_volume),
-                &U8Sql::from(horse.appearance.body_length),
-                &U8Sql::from(horse.appearance.body_volume),
-                &U32Sql::from(horse.stats.agility),
-                &U32Sql::from(horse.stats.control),
-                &U32Sql::from(horse.stats.speed),
-                &U32Sql::from(horse.stats.strength),
-                &U32Sql::from(horse.stats.spirit),
-                &U32Sql::from(horse.rating),
-                &U8Sql::from(horse.class),
-                &U8Sql::from(horse.class_progress),
-                &U8Sql::from(horse.grade),
-                &U16Sql::from(horse.growth_points),
-                &U16Sql::from(horse.vals0.stamina),
-                &U16Sql::from(horse.vals0.attractiveness),
-                &U16Sql::from(horse.vals0.hunger),
-                &U16Sql::from(horse.vals0.val0),
-                &U16Sql::from(horse.vals0.val1),
-                &U16Sql::from(horse.vals0.val2),
-                &U16Sql::from(horse.vals0.val3),
-                &U16Sql::from(horse.vals0.val4),
-                &U16Sql::from(horse.vals0.val5),
-                &U16Sql::from(horse.vals0.val6),
-                &U16Sql::from(horse.vals0.val7),
-                &U16Sql::from(horse.vals0.val8),
-                &U16Sql::from(horse.vals0.val9),
-                &U16Sql::from(horse.vals0.val10),
-                &U8Sql::from(horse.vals1.val0),
-                &U32Sql::from(horse.vals1.val1),
-                &U32Sql::from(horse.vals1.date_of_birth),
-                &U8Sql::from(horse.vals1.val3),
-                &U8Sql::from(horse.vals1.val4),
-                &U32Sql::from(horse.vals1.class_progression),
-                &U32Sql::from(horse.vals1.val5),
-                &U8Sql::from(horse.vals1.potential_level),
-                &U8Sql::from(horse.vals1.has_potential),
-                &U8Sql::from(horse.vals1.potential_value),
-                &U8Sql::from(horse.vals1.val9),
-                &U8Sql::from(horse.vals1.luck),
-                &U8Sql::from(horse.vals1.has_luck),
-                &U8Sql::from(horse.vals1.val12),
-                &U16Sql::from(horse.vals1.fatigue),
-                &U16Sql::from(horse.vals1.val14),
-                &U16Sql::from(horse.vals1.emblem),
-                &U32Sql::from(horse.mastery.spur_magic_count),
-                &U32Sql::from(horse.mastery.jump_count),
-                &U32Sql::from(horse.mastery.sliding_time),
-                &U32Sql::from(horse.mastery.gliding_distance),
-                &U32Sql::from(horse.val16),
-                &U32Sql::from(horse.val17),
+                &U32Sql::from(stallion.horse_uid),
+                &U32Sql::from(stallion.price),
+                &U32Sql::from(stallion.times_mated),
             ],
         )
         .await?;
-    let uid: U32Sql = row.try_get(0)?;
-    horse.uid = uid.into();
-    Ok(())
-}
+        Ok(())
+    }
 
-pub async fn update_horse<'a>(
-    transaction: &mut Transaction<'a>,
-    horse: &mut Horse,
-) -> Result<(), Box<dyn Error>> {
-    let rows_affected = transaction
-        .execute(
-            "UPDATE horses SET
-                    tid=$1, name=$2,
-                    skin_id=$3, mane_id=$4, tail_id=$5, face_id=$6,
-                    scale=$7, leg_length=$8, leg_volume=$9, body_length=$10, body_volume=$11,
-                    agility=$12, control=$13, speed=$14, strength=$15, spirit=$16,
-                    rating=$17, class=$18, class_progress=$19, grade=$20, growth_points=$21,
-                    stamina=$22, attractiveness=$23, hunger=$24,
-                    vals0_val0=$25, vals0_val1=$26, vals0_val2=$27, vals0_val3=$28, vals0_val4=$29,
-                    vals0_val5=$30, vals0_val6=$31, vals0_val7=$32, vals0_val8=$33, vals0_val9=$34, vals0_val10=$35,
-                    vals1_val0=$36, vals1_val1=$37, date_of_birth=$38, vals1_val3=$39, vals1_val4=$40,
-                    class_progression=$41, vals1_val5=$42,
-                    potential_level=$43, has_potential=$44, potential_value=$45, vals1_val9=$46,
-                    luck=$47, has_luck=$48, vals1_val12=$49, fatigue=$50, vals1_val14=$51, emblem=$52,
-                    spur_magic_count=$53, jump_count=$54, sliding_time=$55, gliding_distance=$56,
-                    val16=$57, val17=$58
-                WHERE uid=$59",
+    async fn remove_stallion(
+        &mut self,
+        horse_uid: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let rows_affected = self
+            .execute(
+                "DELETE FROM stallions WHERE horse_uid = $1",
+                &[&U32Sql::from(horse_uid)],
+            )
+            .await?;
+        if rows_affected != 1 {
+            Err(format!("Unexpected number of affected rows: {}", rows_affected).into())
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn get_stallion_listing(
+        &mut self,
+        horse_uid: u32,
+    ) -> Result<Option<StallionListing>, Box<dyn Error + Send + Sync>> {
+        let row_opt = self
+            .query_opt(
+                &format!("{} WHERE stallions.horse_uid = $1", STALLION_LISTING_SELECT),
+                &[&U32Sql::from(horse_uid)],
+            )
+            .await?;
+        if let Some(row) = row_opt {
+            Ok(Some(StallionListing::try_from_row(&row)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn record_stallion_mating(
+        &mut self,
+        horse_uid: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let rows_affected = self
+            .execute(
+                "UPDATE stallions SET times_mated = times_mated + 1 WHERE horse_uid = $1",
+                &[&U32Sql::from(horse_uid)],
+            )
+            .await?;
+        if rows_affected != 1 {
+            Err(format!("Unexpected number of affected rows: {}", rows_affected).into())
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn search_stallions(
+        &mut self,
+        filter: &StallionFilter,
+    ) -> Result<Vec<StallionListing>, Box<dyn Error + Send + Sync>> {
+        let to_params = |ids: &Vec<u32>| ids.iter().map(|id| *id as i32).collect::<Vec<i32>>();
+        let rows = self
+            .query(
+                &format!(
+                    "{}
+                WHERE horses.character_id <> $1
+                    AND (cardinality($2::INTEGER[]) = 0 OR horses.skin_id = ANY($2))
+                    AND (cardinality($3::INTEGER[]) = 0 OR horses.mane_id = ANY($3))
+                    AND (cardinality($4::INTEGER[]) = 0 OR horses.tail_id = ANY($4))
+                ORDER BY stallions.horse_uid
+                LIMIT $5",
+                    STALLION_LISTING_SELECT
+                ),
+                &[
+                    &U32Sql::from(filter.exclude_character_id),
+                    &to_params(&filter.skin_ids),
+                    &to_params(&filter.mane_ids),
+                    &to_params(&filter.tail_ids),
+                    &(filter.limit as i64),
+                ],
+            )
+            .await?;
+        rows.iter()
+            .map(|row| StallionListing::try_from_row(row).map_err(|e| e.into()))
+            .collect()
+    }
+
+    async fn get_lineage(
+        &mut self,
+        horse_uid: u32,
+    ) -> Result<Option<Lineage>, Box<dyn Error + Send + Sync>> {
+        let row_opt = self
+            .query_opt(
+                "SELECT
+                    sire_uid, dam_uid,
+                    sire_sire_uid, sire_dam_uid, dam_sire_uid, dam_dam_uid
+                FROM horse_lineage WHERE horse_uid = $1",
+                &[&U32Sql::from(horse_uid)],
+            )
+            .await?;
+        if let Some(row) = row_opt {
+            Ok(Some(Lineage::try_from_row(&row)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn get_breeding_records(
+        &mut self,
+        uids: &[u32],
+    ) -> Result<Vec<BreedingRecord>, Box<dyn Error + Send + Sync>> {
+        let uids = uids.iter().map(|uid| *uid as i32).collect::<Vec<i32>>();
+        let rows = self
+            .query(
+                "SELECT
+                    horses.uid AS horse_uid,
+                    (
+                        SELECT COUNT(*) FROM horse_lineage AS offspring
+                        WHERE offspring.sire_uid = horses.uid OR offspring.dam_uid = horses.uid
+                    )::INTEGER AS breeding_count,
+                    COALESCE(own.coat_bonus, 0) AS coat_bonus,
+                    EXISTS (
+                        SELECT 1 FROM stallions WHERE stallions.horse_uid = horses.uid
+                    ) AS is_stallion
+                FROM horses
+                LEFT JOIN horse_lineage AS own ON own.horse_uid = horses.uid
+                WHERE horses.uid = ANY($1)",
+                &[&uids],
+            )
+            .await?;
+        rows.iter()
+            .map(|row| BreedingRecord::try_from_row(row).map_err(|e| e.into()))
+            .collect()
+    }
+
+    async fn insert_lineage(&mut self, foal: &Foal) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.execute(
+            "INSERT INTO horse_lineage (
+                horse_uid, sire_uid, dam_uid,
+                sire_sire_uid, sire_dam_uid, dam_sire_uid, dam_dam_uid,
+                coat_bonus
+            )
+            SELECT
+                $1, $2, $3,
+                COALESCE(sire.sire_uid, 0), COALESCE(sire.dam_uid, 0),
+                COALESCE(dam.sire_uid, 0), COALESCE(dam.dam_uid, 0),
+                $4
+            FROM (SELECT 1) AS foal
+            LEFT JOIN horse_lineage AS sire ON sire.horse_uid = $2
+            LEFT JOIN horse_lineage AS dam ON dam.horse_uid = $3",
             &[
-                &U32Sql::from(horse.tid),
-                &CStringSql::from(horse.name.clone()),
-                &U8Sql::from(horse.parts.skin_id),
-                &U8Sql::from(horse.parts.mane_id),
-                &U8Sql::from(horse.parts.tail_id),
-                &U8Sql::from(horse.parts.face_id),
-                &U8Sql::from(horse.appearance.scale),
-                &U8Sql::from(horse.appearance.leg_length),
-                &U8Sql::from(horse.appearance.leg_volume),
-                &U8Sql::from(horse.appearance.body_length),
-                &U8Sql::from(horse.appearance.body_volume),
-                &U32Sql::from(horse.stats.agility),
-                &U32Sql::from(horse.stats.control),
-                &U32Sql::from(horse.stats.speed),
-                &U32Sql::from(horse.stats.strength),
-                &U32Sql::from(horse.stats.spirit),
-                &U32Sql::from(horse.rating),
-                &U8Sql::from(horse.class),
-                &U8Sql::from(horse.class_progress),
-                &U8Sql::from(horse.grade),
-                &U16Sql::from(horse.growth_points),
-                &U16Sql::from(horse.vals0.stamina),
-                &U16Sql::from(horse.vals0.attractiveness),
-                &U16Sql::from(horse.vals0.hunger),
-                &U16Sql::from(horse.vals0.val0),
-                &U16Sql::from(horse.vals0.val1),
-                &U16Sql::from(horse.vals0.val2),
-                &U16Sql::from(horse.vals0.val3),
-                &U16Sql::from(horse.vals0.val4),
-                &U16Sql::from(horse.vals0.val5),
-                &U16Sql::from(horse.vals0.val6),
-                &U16Sql::from(horse.vals0.val7),
-                &U16Sql::from(horse.vals0.val8),
-                &U16Sql::from(horse.vals0.val9),
-                &U16Sql::from(horse.vals0.val10),
-                &U8Sql::from(horse.vals1.val0),
-                &U32Sql::from(horse.vals1.val1),
-                &U32Sql::from(horse.vals1.date_of_birth),
-                &U8Sql::from(horse.vals1.val3),
-                &U8Sql::from(horse.vals1.val4),
-                &U32Sql::from(horse.vals1.class_progression),
-                &U32Sql::from(horse.vals1.val5),
-                &U8Sql::from(horse.vals1.potential_level),
-                &U8Sql::from(horse.vals1.has_potential),
-                &U8Sql::from(horse.vals1.potential_value),
-                &U8Sql::from(horse.vals1.val9),
-                &U8Sql::from(horse.vals1.luck),
-                &U8Sql::from(horse.vals1.has_luck),
-                &U8Sql::from(horse.vals1.val12),
-                &U16Sql::from(horse.vals1.fatigue),
-                &U16Sql::from(horse.vals1.val14),
-                &U16Sql::from(horse.vals1.emblem),
-                &U32Sql::from(horse.mastery.spur_magic_count),
-                &U32Sql::from(horse.mastery.jump_count),
-                &U32Sql::from(horse.mastery.sliding_time),
-                &U32Sql::from(horse.mastery.gliding_distance),
-                &U32Sql::from(horse.val16),
-                &U32Sql::from(horse.val17),
-                &U32Sql::from(horse.uid),
+                &U32Sql::from(foal.horse.uid),
+                &U32Sql::from(foal.parentage.sire_uid),
+                &U32Sql::from(foal.parentage.dam_uid),
+                &U8Sql::from(foal.coat_bonus),
             ],
         )
         .await?;
-    if rows_affected != 1 {
-        Err(format!("Unexpected number of affected rows: {}", rows_affected).into())
-    } else {
         Ok(())
     }
-}
 
-pub async fn remove_horse<'a>(
-    transaction: &mut Transaction<'a>,
-    horse_uid: u32,
-) -> Result<(), Box<dyn Error>> {
-    let rows_affected = transaction
-        .execute(
-            "DELETE FROM horses WHERE uid = $1",
-            &[&U32Sql::from(horse_uid)],
+    async fn get_failure_cards(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Vec<FailureReward>, Box<dyn Error + Send + Sync>> {
+        let rows = self
+            .query(
+                "SELECT item_tid, amount FROM breeding_failure_cards
+                WHERE character_id = $1 ORDER BY position",
+                &[&U32Sql::from(character_id)],
+            )
+            .await?;
+        rows.iter()
+            .map(|row| {
+                let item_tid: Option<U32Sql> = row.try_get(0)?;
+                let amount: U32Sql = row.try_get(1)?;
+                Ok(failure_card_from_columns(
+                    item_tid.map(u32::from),
+                    amount.into(),
+                ))
+            })
+            .collect()
+    }
+
+    async fn set_failure_cards(
+        &mut self,
+        character_id: u32,
+        cards: &[FailureReward],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.execute(
+            "DELETE FROM breeding_failure_cards WHERE character_id = $1",
+            &[&U32Sql::from(character_id)],
         )
         .await?;
-    if rows_affected != 1 {
-        Err(format!("More than one row affected: {}", rows_affected).into())
-    } else {
+        for (position, card) in cards.iter().enumerate() {
+            let (item_tid, amount) = failure_card_columns(card);
+            self.execute(
+                "INSERT INTO breeding_failure_cards (character_id, position, item_tid, amount)
+                VALUES ($1, $2, $3, $4)",
+                &[
+                    &U32Sql::from(character_id),
+                    &U32Sql::from(position as u32),
+                    &item_tid.map(U32Sql::from),
+                    &U32Sql::from(amount),
+                ],
+            )
+            .await?;
+        }
         Ok(())
     }
+
+    async fn take_failure_cards(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Vec<FailureReward>, Box<dyn Error + Send + Sync>> {
+        let rows = self
+            .query(
+                "DELETE FROM breeding_failure_cards WHERE character_id = $1
+                RETURNING position, item_tid, amount",
+                &[&U32Sql::from(character_id)],
+            )
+            .await?;
+        let mut cards = rows
+            .iter()
+            .map(|row| {
+                let position: U32Sql = row.try_get(0)?;
+                let item_tid: Option<U32Sql> = row.try_get(1)?;
+                let amount: U32Sql = row.try_get(2)?;
+                Ok((
+                    u32::from(position),
+                    failure_card_from_columns(item_tid.map(u32::from), amount.into()),
+                ))
+            })
+            .collect::<Result<Vec<(u32, FailureReward)>, Box<dyn Error + Send + Sync>>>()?;
+        // Rows come back in no particular order
+        cards.sort_by_key(|(position, _)| *position);
+        Ok(cards.into_iter().map(|(_, card)| card).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::database::migrations::{MIGRATIONS_PATH, load_migrations};
+
+    /// Columns of the horses table once every migration is applied
+    async fn schema_horse_columns() -> Vec<String> {
+        let migrations = load_migrations(&PathBuf::from(MIGRATIONS_PATH))
+            .await
+            .unwrap();
+        let mut columns = Vec::new();
+        for migration in migrations.iter() {
+            let mut in_horses = false;
+            for line in migration.sql.lines().map(|line| line.trim()) {
+                if line.starts_with("CREATE TABLE horses (") {
+                    in_horses = true;
+                } else if in_horses && line.starts_with(");") {
+                    in_horses = false;
+                } else if in_horses && !line.is_empty() && !line.starts_with("--") {
+                    let column = line.split_whitespace().next().unwrap();
+                    if column != "CONSTRAINT" {
+                        columns.push(column.to_owned());
+                    }
+                } else if let Some(rest) = line.strip_prefix("ALTER TABLE horses ADD COLUMN ") {
+                    columns.push(rest.split_whitespace().next().unwrap().to_owned());
+                }
+            }
+        }
+        columns.sort();
+        columns
+    }
+
+    #[tokio::test]
+    async fn test_horse_columns_match_schema() {
+        let mut columns = Horse::columns();
+        columns.push("character_id");
+        columns.sort();
+        assert_eq!(schema_horse_columns().await, columns);
+
+        let horse = Horse::default();
+        let (columns, values) = horse_columns(&horse);
+        assert_eq!(columns.len(), values.len());
+        assert!(!columns.contains(&"uid"));
+    }
 }
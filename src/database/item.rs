@@ -0,0 +1,40 @@
+use std::error::Error;
+
+use async_trait::async_trait;
+use tokio_postgres::Transaction;
+
+use crate::database::U32Sql;
+
+#[async_trait]
+pub trait ItemRepository {
+    /// Adds `count` items with the given `tid` to the character's inventory,
+    /// stacking them on top of the ones it already has.
+    async fn add_item(
+        &mut self,
+        character_id: u32,
+        tid: u32,
+        count: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+}
+
+#[async_trait]
+impl ItemRepository for Transaction<'_> {
+    async fn add_item(
+        &mut self,
+        character_id: u32,
+        tid: u32,
+        count: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.execute(
+            "INSERT INTO items (character_id, tid, count) VALUES ($1, $2, $3)
+            ON CONFLICT (character_id, tid) DO UPDATE SET count = items.count + EXCLUDED.count",
+            &[
+                &U32Sql::from(character_id),
+                &U32Sql::from(tid),
+                &U32Sql::from(count),
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,899 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use rand::seq::IndexedRandom;
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::{
+    commands::shared::horse::Horse,
+    database::{
+        account::AccountRepository, character::CharacterRepository, horse::HorseRepository,
+        horse::StallionFilter, item::ItemRepository, ranch::RanchRepository,
+        wallet::INITIAL_CARROTS, wallet::WalletRepository, wishlist::WishlistRepository,
+    },
+    entities::{
+        account::Account,
+        character::Character,
+        lineage::{BreedingRecord, Lineage},
+        ranch::{Ranch, RanchVisibility},
+        stallion::{Stallion, StallionListing},
+        wallet::Wallet,
+    },
+    genetics::{FailureReward, Foal},
+};
+
+#[derive(Clone)]
+struct CharacterRow {
+    member_no: u32,
+    character: Character,
+}
+
+#[derive(Clone)]
+struct HorseRow {
+    character_id: u32,
+    horse: Horse,
+}
+
+#[derive(Clone)]
+struct LineageRow {
+    lineage: Lineage,
+    coat_bonus: u8,
+}
+
+/// Every table of the schema, with the same keys and constraints
+#[derive(Clone, Default)]
+struct Tables {
+    /// Last value of the `uid` sequence, shared by characters, horses, items and ranches
+    last_uid: u32,
+    last_member_no: u32,
+
+    accounts: BTreeMap<u32, Account>,
+    characters: BTreeMap<u32, CharacterRow>,
+    horses: BTreeMap<u32, HorseRow>,
+    stallions: BTreeMap<u32, Stallion>,
+    wallets: BTreeMap<u32, Wallet>,
+    lineages: BTreeMap<u32, LineageRow>,
+    /// (character_id, stallion_uid)
+    wishlists: BTreeSet<(u32, u32)>,
+    /// Item count by (character_id, tid)
+    items: BTreeMap<(u32, u32), u32>,
+    ranches: BTreeMap<u32, Ranch>,
+    /// Ban expiry by (ranch_id, character_id)
+    ranch_bans: BTreeMap<(u32, u32), Instant>,
+    /// Ranch, code and expiry by character_id
+    ranch_entry_codes: BTreeMap<u32, (u32, u32, Instant)>,
+    /// Cards in the order they were drawn by character_id
+    breeding_failure_cards: BTreeMap<u32, Vec<FailureReward>>,
+}
+impl Tables {
+    fn next_uid(&mut self) -> u32 {
+        self.last_uid += 1;
+        self.last_uid
+    }
+
+    fn check_character(&self, character_id: u32) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.characters.contains_key(&character_id) {
+            Ok(())
+        } else {
+            Err(format!("Character {} doesn't exist", character_id).into())
+        }
+    }
+
+    fn stallion_listing(&self, stallion: &Stallion) -> Option<StallionListing> {
+        let horse_row = self.horses.get(&stallion.horse_uid)?;
+        let owner = self.characters.get(&horse_row.character_id)?;
+        Some(StallionListing {
+            stallion: stallion.clone(),
+            horse: horse_row.horse.clone(),
+            owner_character_id: owner.character.character_id,
+            owner_nickname: owner.character.nickname.clone(),
+        })
+    }
+}
+
+/// Database kept entirely in memory, meant for tests and trying the server out.
+/// Everything is lost when it's dropped.
+#[derive(Default)]
+pub struct MemoryDatabase {
+    tables: Mutex<Tables>,
+}
+impl MemoryDatabase {
+    /// Transactions run one at a time, each one working on its own copy of the tables,
+    /// which only replaces them if it's committed
+    pub async fn transaction(&self) -> MemoryTransaction<'_> {
+        let committed = self.tables.lock().await;
+        MemoryTransaction {
+            tables: committed.clone(),
+            committed,
+        }
+    }
+}
+
+pub struct MemoryTransaction<'a> {
+    committed: MutexGuard<'a, Tables>,
+    tables: Tables,
+}
+impl MemoryTransaction<'_> {
+    pub fn commit(mut self) {
+        *self.committed = self.tables;
+    }
+}
+
+#[async_trait]
+impl AccountRepository for MemoryTransaction<'_> {
+    async fn get_accounts(&mut self) -> Result<Vec<Account>, Box<dyn Error + Send + Sync>> {
+        Ok(self.tables.accounts.values().cloned().collect())
+    }
+
+    async fn get_account(
+        &mut self,
+        member_no: u32,
+    ) -> Result<Option<Account>, Box<dyn Error + Send + Sync>> {
+        Ok(self.tables.accounts.get(&member_no).cloned())
+    }
+
+    async fn add_account(
+        &mut self,
+        new_account: &mut Account,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let tables = &mut self.tables;
+        if tables
+            .accounts
+            .values()
+            .any(|a| a.login_id == new_account.login_id)
+        {
+            return Err(format!("Login id {} is already taken", new_account.login_id).into());
+        }
+        tables.last_member_no += 1;
+        new_account.member_no = tables.last_member_no;
+        tables
+            .accounts
+            .insert(new_account.member_no, new_account.clone());
+        Ok(())
+    }
+
+    async fn delete_account(&mut self, member_no: u32) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let tables = &mut self.tables;
+        if tables.characters.values().any(|c| c.member_no == member_no) {
+            return Err(format!("Account {} still has a character", member_no).into());
+        }
+        tables
+            .accounts
+            .remove(&member_no)
+            .map(|_| ())
+            .ok_or("Unexpected number of rows affected: 0".into())
+    }
+}
+
+#[async_trait]
+impl CharacterRepository for MemoryTransaction<'_> {
+    async fn get_character_by_member_no(
+        &mut self,
+        member_no: u32,
+    ) -> Result<Option<Character>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .tables
+            .characters
+            .values()
+            .find(|c| c.member_no == member_no)
+            .map(|c| c.character.clone()))
+    }
+
+    async fn get_character_by_id(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Option<Character>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .tables
+            .characters
+            .get(&character_id)
+            .map(|c| c.character.clone()))
+    }
+
+    async fn insert_character(
+        &mut self,
+        member_no: u32,
+        character: &mut Character,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let tables = &mut self.tables;
+        if !tables.accounts.contains_key(&member_no) {
+            return Err(format!("Account {} doesn't exist", member_no).into());
+        }
+        check_unique_character(tables, character, 0)?;
+        character.character_id = tables.next_uid();
+        tables.characters.insert(
+            character.character_id,
+            CharacterRow {
+                member_no,
+                character: character.clone(),
+            },
+        );
+        Ok(())
+    }
+
+    async fn update_character(
+        &mut self,
+        character: &Character,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        check_unique_character(&self.tables, character, character.character_id)?;
+        let row = self
+            .tables
+            .characters
+            .get_mut(&character.character_id)
+            .ok_or("Unexpected number of rows affected: 0")?;
+        row.character = character.clone();
+        Ok(())
+    }
+
+    async fn delete_character(
+        &mut self,
+        character_id: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let tables = &mut self.tables;
+        let referenced = tables
+            .horses
+            .values()
+            .any(|h| h.character_id == character_id)
+            || tables.wallets.contains_key(&character_id)
+            || tables.wishlists.iter().any(|(c, _)| *c == character_id)
+            || tables.items.keys().any(|(c, _)| *c == character_id)
+            || tables
+                .ranches
+                .values()
+                .any(|r| r.owner_character_id == character_id)
+            || tables.ranch_bans.keys().any(|(_, c)| *c == character_id);
+        if referenced {
+            return Err(format!("Character {} is still referenced", character_id).into());
+        }
+        tables.ranch_entry_codes.remove(&character_id);
+        tables.breeding_failure_cards.remove(&character_id);
+        tables
+            .characters
+            .remove(&character_id)
+            .map(|_| ())
+            .ok_or("Unexpected number of rows affected: 0".into())
+    }
+}
+
+/// Nicknames and mounts can't be shared between characters, other than `character_id` itself
+fn check_unique_character(
+    tables: &Tables,
+    character: &Character,
+    character_id: u32,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let others = || {
+        tables
+            .characters
+            .values()
+            .filter(|c| c.character.character_id != character_id)
+    };
+    if others().any(|c| c.character.nickname == character.nickname) {
+        return Err(format!("Nickname {} is already taken", character.nickname).into());
+    }
+    if others().any(|c| c.character.mount_uid == character.mount_uid) {
+        return Err(format!("Mount {} already has a rider", character.mount_uid).into());
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl HorseRepository for MemoryTransaction<'_> {
+    async fn get_horses_by_character_id(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Vec<Horse>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .tables
+            .horses
+            .values()
+            .filter(|h| h.character_id == character_id)
+            .map(|h| h.horse.clone())
+            .collect())
+    }
+
+    async fn count_horses_by_character_id(
+        &mut self,
+        character_id: u32,
+    ) -> Result<u32, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .tables
+            .horses
+            .values()
+            .filter(|h| h.character_id == character_id)
+            .count() as u32)
+    }
+
+    async fn get_horse_by_uid(
+        &mut self,
+        uid: u32,
+    ) -> Result<Option<Horse>, Box<dyn Error + Send + Sync>> {
+        Ok(self.tables.horses.get(&uid).map(|h| h.horse.clone()))
+    }
+
+    async fn get_horses_by_uids(
+        &mut self,
+        uids: &[u32],
+    ) -> Result<Vec<Horse>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .tables
+            .horses
+            .values()
+            .filter(|h| uids.contains(&h.horse.uid))
+            .map(|h| h.horse.clone())
+            .collect())
+    }
+
+    async fn insert_horse(
+        &mut self,
+        character_id: u32,
+        horse: &mut Horse,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let tables = &mut self.tables;
+        tables.check_character(character_id)?;
+        horse.uid = tables.next_uid();
+        tables.horses.insert(
+            horse.uid,
+            HorseRow {
+                character_id,
+                horse: horse.clone(),
+            },
+        );
+        Ok(())
+    }
+
+    async fn update_horse(
+        &mut self,
+        horse: &mut Horse,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let row = self
+            .tables
+            .horses
+            .get_mut(&horse.uid)
+            .ok_or("Unexpected number of affected rows: 0")?;
+        row.horse = horse.clone();
+        Ok(())
+    }
+
+    async fn remove_horse(&mut self, horse_uid: u32) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let tables = &mut self.tables;
+        tables
+            .horses
+            .remove(&horse_uid)
+            .ok_or("More than one row affected: 0")?;
+        // Stallions and lineages go along with the horse, and wishlists along with the stallion
+        tables.stallions.remove(&horse_uid);
+        tables.lineages.remove(&horse_uid);
+        tables.wishlists.retain(|(_, uid)| *uid != horse_uid);
+        Ok(())
+    }
+
+    async fn get_stallion(
+        &mut self,
+        horse_uid: u32,
+    ) -> Result<Option<Stallion>, Box<dyn Error + Send + Sync>> {
+        Ok(self.tables.stallions.get(&horse_uid).cloned())
+    }
+
+    async fn insert_stallion(
+        &mut self,
+        stallion: &Stallion,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let tables = &mut self.tables;
+        if !tables.horses.contains_key(&stallion.horse_uid) {
+            return Err(format!("Horse {} doesn't exist", stallion.horse_uid).into());
+        }
+        if tables.stallions.contains_key(&stallion.horse_uid) {
+            return Err(format!("Horse {} is already a stallion", stallion.horse_uid).into());
+        }
+        tables
+            .stallions
+            .insert(stallion.horse_uid, stallion.clone());
+        Ok(())
+    }
+
+    async fn remove_stallion(
+        &mut self,
+        horse_uid: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let tables = &mut self.tables;
+        tables
+            .stallions
+            .remove(&horse_uid)
+            .ok_or("Unexpected number of affected rows: 0")?;
+        tables.wishlists.retain(|(_, uid)| *uid != horse_uid);
+        Ok(())
+    }
+
+    async fn get_stallion_listing(
+        &mut self,
+        horse_uid: u32,
+    ) -> Result<Option<StallionListing>, Box<dyn Error + Send + Sync>> {
+        let tables = &self.tables;
+        Ok(tables
+            .stallions
+            .get(&horse_uid)
+            .and_then(|stallion| tables.stallion_listing(stallion)))
+    }
+
+    async fn record_stallion_mating(
+        &mut self,
+        horse_uid: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let stallion = self
+            .tables
+            .stallions
+            .get_mut(&horse_uid)
+            .ok_or("Unexpected number of affected rows: 0")?;
+        stallion.times_mated += 1;
+        Ok(())
+    }
+
+    async fn search_stallions(
+        &mut self,
+        filter: &StallionFilter,
+    ) -> Result<Vec<StallionListing>, Box<dyn Error + Send + Sync>> {
+        let matches = |ids: &Vec<u32>, id: u8| ids.is_empty() || ids.contains(&(id as u32));
+        let tables = &self.tables;
+        Ok(tables
+            .stallions
+            .values()
+            .filter_map(|stallion| tables.stallion_listing(stallion))
+            .filter(|listing| {
+                let parts = &listing.horse.parts;
+                listing.owner_character_id != filter.exclude_character_id
+                    && matches(&filter.skin_ids, parts.skin_id)
+                    && matches(&filter.mane_ids, parts.mane_id)
+                    && matches(&filter.tail_ids, parts.tail_id)
+            })
+            .take(filter.limit as usize)
+            .collect())
+    }
+
+    async fn get_lineage(
+        &mut self,
+        horse_uid: u32,
+    ) -> Result<Option<Lineage>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .tables
+            .lineages
+            .get(&horse_uid)
+            .map(|row| row.lineage.clone()))
+    }
+
+    async fn get_breeding_records(
+        &mut self,
+        uids: &[u32],
+    ) -> Result<Vec<BreedingRecord>, Box<dyn Error + Send + Sync>> {
+        let tables = &self.tables;
+        Ok(tables
+            .horses
+            .keys()
+            .filter(|uid| uids.contains(uid))
+            .map(|uid| BreedingRecord {
+                horse_uid: *uid,
+                breeding_count: tables
+                    .lineages
+                    .values()
+                    .filter(|row| row.lineage.sire_uid == *uid || row.lineage.dam_uid == *uid)
+                    .count() as u32,
+                coat_bonus: tables.lineages.get(uid).map_or(0, |row| row.coat_bonus),
+                is_stallion: tables.stallions.contains_key(uid),
+            })
+            .collect())
+    }
+
+    async fn insert_lineage(&mut self, foal: &Foal) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let tables = &mut self.tables;
+        let horse_uid = foal.horse.uid;
+        if !tables.horses.contains_key(&horse_uid) {
+            return Err(format!("Horse {} doesn't exist", horse_uid).into());
+        }
+        if tables.lineages.contains_key(&horse_uid) {
+            return Err(format!("Horse {} already has a lineage", horse_uid).into());
+        }
+        let parents_of = |uid: u32| {
+            tables
+                .lineages
+                .get(&uid)
+                .map_or((0, 0), |row| (row.lineage.sire_uid, row.lineage.dam_uid))
+        };
+        let (sire_sire_uid, sire_dam_uid) = parents_of(foal.parentage.sire_uid);
+        let (dam_sire_uid, dam_dam_uid) = parents_of(foal.parentage.dam_uid);
+        let lineage = Lineage {
+            sire_uid: foal.parentage.sire_uid,
+            dam_uid: foal.parentage.dam_uid,
+            sire_sire_uid,
+            sire_dam_uid,
+            dam_sire_uid,
+            dam_dam_uid,
+        };
+        tables.lineages.insert(
+            horse_uid,
+            LineageRow {
+                lineage,
+                coat_bonus: foal.coat_bonus,
+            },
+        );
+        Ok(())
+    }
+
+    async fn get_failure_cards(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Vec<FailureReward>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .tables
+            .breeding_failure_cards
+            .get(&character_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn set_failure_cards(
+        &mut self,
+        character_id: u32,
+        cards: &[FailureReward],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let tables = &mut self.tables;
+        tables.check_character(character_id)?;
+        if cards.is_empty() {
+            tables.breeding_failure_cards.remove(&character_id);
+        } else {
+            tables
+                .breeding_failure_cards
+                .insert(character_id, cards.to_vec());
+        }
+        Ok(())
+    }
+
+    async fn take_failure_cards(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Vec<FailureReward>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .tables
+            .breeding_failure_cards
+            .remove(&character_id)
+            .unwrap_or_default())
+    }
+}
+
+#[async_trait]
+impl ItemRepository for MemoryTransaction<'_> {
+    async fn add_item(
+        &mut self,
+        character_id: u32,
+        tid: u32,
+        count: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let tables = &mut self.tables;
+        tables.check_character(character_id)?;
+        let item_count = tables.items.entry((character_id, tid)).or_default();
+        *item_count = item_count.checked_add(count).ok_or("Item count overflow")?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RanchRepository for MemoryTransaction<'_> {
+    async fn get_ranch_by_id(
+        &mut self,
+        ranch_id: u32,
+    ) -> Result<Option<Ranch>, Box<dyn Error + Send + Sync>> {
+        Ok(self.tables.ranches.get(&ranch_id).cloned())
+    }
+
+    async fn get_or_create_ranch_by_owner(
+        &mut self,
+        owner_character_id: u32,
+    ) -> Result<Option<Ranch>, Box<dyn Error + Send + Sync>> {
+        let tables = &mut self.tables;
+        let Some(owner) = tables.characters.get(&owner_character_id) else {
+            return Ok(None);
+        };
+        if let Some(ranch) = tables
+            .ranches
+            .values()
+            .find(|r| r.owner_character_id == owner_character_id)
+        {
+            return Ok(Some(ranch.clone()));
+        }
+        let name = format!("{}'s Ranch", owner.character.nickname);
+        let ranch = Ranch {
+            ranch_id: tables.next_uid(),
+            owner_character_id,
+            name,
+            visibility: RanchVisibility::Public as u8,
+            max_visitors: 0,
+            visitor_count: 0,
+        };
+        tables.ranches.insert(ranch.ranch_id, ranch.clone());
+        Ok(Some(ranch))
+    }
+
+    async fn get_random_ranch(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Option<Ranch>, Box<dyn Error + Send + Sync>> {
+        let candidates = self
+            .tables
+            .ranches
+            .values()
+            .filter(|r| {
+                r.owner_character_id != character_id
+                    && r.visibility == RanchVisibility::Public as u8
+                    && (r.max_visitors == 0 || r.visitor_count < r.max_visitors as u32)
+            })
+            .collect::<Vec<&Ranch>>();
+        let visited = candidates
+            .iter()
+            .filter(|r| r.visitor_count > 0)
+            .copied()
+            .collect::<Vec<&Ranch>>();
+        let pool = if visited.is_empty() {
+            candidates
+        } else {
+            visited
+        };
+        Ok(pool.choose(&mut rand::rng()).map(|r| (*r).clone()))
+    }
+
+    async fn update_ranch_options(
+        &mut self,
+        owner_character_id: u32,
+        visibility: RanchVisibility,
+        max_visitors: u8,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let ranch = self
+            .tables
+            .ranches
+            .values_mut()
+            .find(|r| r.owner_character_id == owner_character_id)
+            .ok_or("Unexpected number of affected rows: 0")?;
+        ranch.visibility = visibility as u8;
+        ranch.max_visitors = max_visitors;
+        Ok(())
+    }
+
+    async fn update_visitor_count(
+        &mut self,
+        ranch_id: u32,
+        visitor_count: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if let Some(ranch) = self.tables.ranches.get_mut(&ranch_id) {
+            ranch.visitor_count = visitor_count;
+        }
+        Ok(())
+    }
+
+    async fn reset_visitor_counts(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for ranch in self.tables.ranches.values_mut() {
+            ranch.visitor_count = 0;
+        }
+        Ok(())
+    }
+
+    async fn ban_from_ranch(
+        &mut self,
+        ranch_id: u32,
+        character_id: u32,
+        duration_secs: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let tables = &mut self.tables;
+        if !tables.ranches.contains_key(&ranch_id) {
+            return Err(format!("Ranch {} doesn't exist", ranch_id).into());
+        }
+        tables.check_character(character_id)?;
+        let expires_at = Instant::now() + Duration::from_secs(duration_secs);
+        tables
+            .ranch_bans
+            .insert((ranch_id, character_id), expires_at);
+        Ok(())
+    }
+
+    async fn is_banned_from_ranch(
+        &mut self,
+        ranch_id: u32,
+        character_id: u32,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .tables
+            .ranch_bans
+            .get(&(ranch_id, character_id))
+            .is_some_and(|expires_at| *expires_at > Instant::now()))
+    }
+
+    async fn issue_ranch_entry_code(
+        &mut self,
+        character_id: u32,
+        ranch_id: u32,
+        code: u32,
+        valid_secs: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let tables = &mut self.tables;
+        if !tables.ranches.contains_key(&ranch_id) {
+            return Err(format!("Ranch {} doesn't exist", ranch_id).into());
+        }
+        tables.check_character(character_id)?;
+        let expires_at = Instant::now() + Duration::from_secs(valid_secs);
+        tables
+            .ranch_entry_codes
+            .insert(character_id, (ranch_id, code, expires_at));
+        Ok(())
+    }
+
+    async fn redeem_ranch_entry_code(
+        &mut self,
+        character_id: u32,
+        code: u32,
+    ) -> Result<Option<u32>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .tables
+            .ranch_entry_codes
+            .remove(&character_id)
+            .filter(|(_, issued_code, expires_at)| {
+                *issued_code == code && *expires_at > Instant::now()
+            })
+            .map(|(ranch_id, _, _)| ranch_id))
+    }
+}
+
+#[async_trait]
+impl WalletRepository for MemoryTransaction<'_> {
+    async fn get_wallet_for_update(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Wallet, Box<dyn Error + Send + Sync>> {
+        let tables = &mut self.tables;
+        tables.check_character(character_id)?;
+        Ok(tables
+            .wallets
+            .entry(character_id)
+            .or_insert(Wallet {
+                character_id,
+                carrots: INITIAL_CARROTS,
+                breeding_earnings: 0,
+            })
+            .clone())
+    }
+
+    async fn update_wallet(&mut self, wallet: &Wallet) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let stored = self
+            .tables
+            .wallets
+            .get_mut(&wallet.character_id)
+            .ok_or("Unexpected number of affected rows: 0")?;
+        *stored = wallet.clone();
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl WishlistRepository for MemoryTransaction<'_> {
+    async fn get_wishlist(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Vec<StallionListing>, Box<dyn Error + Send + Sync>> {
+        let tables = &self.tables;
+        Ok(tables
+            .wishlists
+            .iter()
+            .filter(|(c, _)| *c == character_id)
+            .filter_map(|(_, uid)| tables.stallions.get(uid))
+            .filter_map(|stallion| tables.stallion_listing(stallion))
+            .collect())
+    }
+
+    async fn count_wishlist(
+        &mut self,
+        character_id: u32,
+    ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .tables
+            .wishlists
+            .iter()
+            .filter(|(c, _)| *c == character_id)
+            .count())
+    }
+
+    async fn add_to_wishlist(
+        &mut self,
+        character_id: u32,
+        stallion_uid: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let tables = &mut self.tables;
+        tables.check_character(character_id)?;
+        if !tables.stallions.contains_key(&stallion_uid) {
+            return Err(format!("Horse {} is not a stallion", stallion_uid).into());
+        }
+        if !tables.wishlists.insert((character_id, stallion_uid)) {
+            return Err(format!("Stallion {} is already in the wishlist", stallion_uid).into());
+        }
+        Ok(())
+    }
+
+    async fn remove_from_wishlist(
+        &mut self,
+        character_id: u32,
+        stallion_uid: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.tables.wishlists.remove(&(character_id, stallion_uid)) {
+            Ok(())
+        } else {
+            Err("Unexpected number of affected rows: 0".into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{Database, wallet::transfer_breeding_fee};
+
+    #[tokio::test]
+    async fn test_transactions_only_keep_committed_changes() {
+        let database = Database::in_memory();
+        let (breeder_id, owner_id) = database
+            .run_in_transaction(async |transaction| {
+                let mut ids = Vec::new();
+                for login_id in ["breeder", "owner"] {
+                    let mut account = Account {
+                        member_no: 0,
+                        login_id: login_id.to_owned(),
+                        auth_key: String::new(),
+                    };
+                    transaction.add_account(&mut account).await?;
+                    let mut character = Character {
+                        character_id: 0,
+                        nickname: login_id.to_owned(),
+                        mount_uid: account.member_no,
+                        mount_slots: 3,
+                        character: Default::default(),
+                        create_character_unk0: 0,
+                    };
+                    transaction
+                        .insert_character(account.member_no, &mut character)
+                        .await?;
+                    ids.push(character.character_id);
+                }
+                Ok((ids[0], ids[1]))
+            })
+            .await
+            .unwrap();
+
+        // Failing halfway through leaves nothing behind
+        let result = database
+            .run_in_transaction(async |transaction| {
+                transfer_breeding_fee(transaction, breeder_id, owner_id, 100).await?;
+                transfer_breeding_fee(transaction, breeder_id, owner_id, INITIAL_CARROTS).await
+            })
+            .await;
+        assert!(result.is_err());
+        let wallet = database
+            .run_in_transaction(async |transaction| {
+                transaction.get_wallet_for_update(breeder_id).await
+            })
+            .await
+            .unwrap();
+        assert_eq!(wallet.carrots, INITIAL_CARROTS);
+
+        let breeder = database
+            .run_in_transaction(async |transaction| {
+                transfer_breeding_fee(transaction, breeder_id, owner_id, 100).await
+            })
+            .await
+            .unwrap();
+        assert_eq!(breeder.carrots, INITIAL_CARROTS - 100);
+        let owner = database
+            .run_in_transaction(async |transaction| {
+                transaction.get_wallet_for_update(owner_id).await
+            })
+            .await
+            .unwrap();
+        assert_eq!(owner.breeding_earnings, 100);
+    }
+}
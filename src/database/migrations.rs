@@ -0,0 +1,522 @@
+//! Versioned schema migrations, applied at startup.
+//!
+//! Postgres databases created from `res/schema.sql`, before there were migrations, have no
+//! record of what was applied to them. They're brought up to the first migration by
+//! [`LEGACY_SCHEMA_UPGRADE_PATH`], which works with any version of that file, and the first
+//! migration is then recorded as applied instead of being run. The rest are applied on top as
+//! usual. If the tables still don't match what the first migration creates, such as after
+//! changing them by hand, startup fails listing the differences, and they have to be brought in
+//! line with `res/migrations/0001_initial_schema.sql` by hand (or started over with
+//! `wipe_on_startup`, after backing up the data).
+
+use std::{collections::BTreeSet, error::Error, path::Path};
+
+use sha2::{Digest, Sha256};
+use sqlx::{Row, SqlitePool};
+use tokio_postgres::Client;
+
+/// Directory with the migration files, named `<version>_<name>.sql`
+pub const MIGRATIONS_PATH: &str = "res/migrations";
+/// Same as [`MIGRATIONS_PATH`], in the SQLite dialect
+pub const SQLITE_MIGRATIONS_PATH: &str = "res/migrations/sqlite";
+
+/// Brings a Postgres database created from any version of `res/schema.sql` up to the first
+/// migration
+pub const LEGACY_SCHEMA_UPGRADE_PATH: &str = "res/legacy_schema_upgrade.sql";
+
+/// Schema the first migration is applied to, to compare it against a database created before
+/// there were migrations. Dropped right after.
+const BASELINE_SCHEMA: &str = "alicia_migration_baseline";
+
+/// Arbitrary key for the advisory lock taken while migrating, so that servers starting
+/// at the same time don't migrate the same database at once
+const MIGRATIONS_LOCK_KEY: i64 = 0x616c69636961;
+
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub version: i64,
+    pub name: String,
+    pub sql: String,
+    pub checksum: String,
+}
+impl Migration {
+    pub fn new(file_name: &str, sql: String) -> Result<Migration, String> {
+        let (version, name) = file_name
+            .strip_suffix(".sql")
+            .and_then(|stem| stem.split_once('_'))
+            .ok_or(format!(
+                "Migration file {} isn't named <version>_<name>.sql",
+                file_name
+            ))?;
+        let version = version
+            .parse::<i64>()
+            .map_err(|e| format!("Invalid version in migration file {}: {}", file_name, e))?;
+        Ok(Migration {
+            version,
+            name: name.to_owned(),
+            checksum: checksum(&sql),
+            sql,
+        })
+    }
+}
+
+/// SHA-256 of the migration, ignoring line ending differences between platforms
+pub fn checksum(sql: &str) -> String {
+    Sha256::digest(sql.replace("\r\n", "\n").as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub checksum: String,
+}
+
+/// Reads every migration in the directory, sorted by version.
+pub async fn load_migrations(path: &Path) -> Result<Vec<Migration>, Box<dyn Error>> {
+    let mut migrations = Vec::new();
+    let mut entries = tokio::fs::read_dir(path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if !file_name.ends_with(".sql") {
+            continue;
+        }
+        let sql = tokio::fs::read_to_string(entry.path()).await?;
+        migrations.push(Migration::new(&file_name, sql)?);
+    }
+    migrations.sort_by_key(|m| m.version);
+    if let Some(pair) = migrations.windows(2).find(|w| w[0].version == w[1].version) {
+        return Err(format!(
+            "Migrations {} and {} share version {}",
+            pair[0].name, pair[1].name, pair[0].version
+        )
+        .into());
+    }
+    Ok(migrations)
+}
+
+/// Works out which migrations are still to be applied, in order. Fails if the applied
+/// ones don't match the files anymore, since the schema would no longer be what they describe.
+pub fn pending_migrations<'a>(
+    migrations: &'a [Migration],
+    applied: &[AppliedMigration],
+) -> Result<Vec<&'a Migration>, String> {
+    for applied_migration in applied {
+        let migration = migrations
+            .iter()
+            .find(|m| m.version == applied_migration.version)
+            .ok_or(format!(
+                "Migration {} was applied but its file is missing",
+                applied_migration.version
+            ))?;
+        if migration.checksum != applied_migration.checksum {
+            return Err(format!(
+                "Migration {}_{} was modified after being applied",
+                migration.version, migration.name
+            ));
+        }
+    }
+
+    let pending = migrations
+        .iter()
+        .filter(|m| !applied.iter().any(|a| a.version == m.version))
+        .collect::<Vec<&Migration>>();
+    // Applying an older migration late would leave this database with a different
+    // history than the rest
+    let latest_applied = applied.iter().map(|a| a.version).max();
+    if let (Some(latest_applied), Some(first_pending)) = (latest_applied, pending.first())
+        && first_pending.version < latest_applied
+    {
+        return Err(format!(
+            "Migration {}_{} is older than the latest applied one ({})",
+            first_pending.version, first_pending.name, latest_applied
+        ));
+    }
+    Ok(pending)
+}
+
+/// Applies the pending migrations in a single transaction. On a dry run they're only listed
+/// and nothing is changed. Returns how many migrations were pending.
+pub async fn run_migrations(
+    client: &mut Client,
+    migrations: &[Migration],
+    dry_run: bool,
+) -> Result<usize, Box<dyn Error>> {
+    let transaction = client.transaction().await?;
+    transaction
+        .execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATIONS_LOCK_KEY])
+        .await?;
+    transaction
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version BIGINT PRIMARY KEY NOT NULL,
+                name TEXT NOT NULL,
+                checksum TEXT NOT NULL,
+                applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            )",
+        )
+        .await?;
+    let mut applied = transaction
+        .query("SELECT version, checksum FROM schema_migrations", &[])
+        .await?
+        .iter()
+        .map(|row| AppliedMigration {
+            version: row.get("version"),
+            checksum: row.get("checksum"),
+        })
+        .collect::<Vec<AppliedMigration>>();
+    if applied.is_empty()
+        && let Some(baseline) = baseline_existing_schema(&transaction, migrations).await?
+    {
+        applied.push(baseline);
+    }
+
+    let pending = pending_migrations(migrations, &applied)?;
+    for migration in pending.iter() {
+        if dry_run {
+            println!(
+                "Would apply migration {}_{}",
+                migration.version, migration.name
+            );
+            continue;
+        }
+        println!(
+            "Applying migration {}_{}",
+            migration.version, migration.name
+        );
+        transaction.batch_execute(&migration.sql).await?;
+        transaction
+            .execute(
+                "INSERT INTO schema_migrations (version, name, checksum) VALUES ($1, $2, $3)",
+                &[&migration.version, &migration.name, &migration.checksum],
+            )
+            .await?;
+    }
+
+    if dry_run {
+        transaction.rollback().await?;
+    } else {
+        transaction.commit().await?;
+    }
+    Ok(pending.len())
+}
+
+/// Records the first migration as applied if the database was created from `res/schema.sql`,
+/// before there were migrations, upgrading it to match first. Fails if its tables still don't
+/// match the first migration.
+async fn baseline_existing_schema(
+    transaction: &tokio_postgres::Transaction<'_>,
+    migrations: &[Migration],
+) -> Result<Option<AppliedMigration>, Box<dyn Error>> {
+    let schema: String = transaction
+        .query_one("SELECT current_schema()::TEXT", &[])
+        .await?
+        .get(0);
+    let Some(first) = migrations.first() else {
+        return Ok(None);
+    };
+    if schema_columns(transaction, &schema).await?.is_empty() {
+        return Ok(None);
+    }
+
+    println!(
+        "Upgrading schema {}, created before there were migrations",
+        schema
+    );
+    let upgrade = tokio::fs::read_to_string(LEGACY_SCHEMA_UPGRADE_PATH).await?;
+    transaction.batch_execute(&upgrade).await?;
+    let existing = schema_columns(transaction, &schema).await?;
+
+    // Whatever the first migration creates ends up in the scratch schema, being the first
+    // one in the search path
+    let search_path: String = transaction.query_one("SHOW search_path", &[]).await?.get(0);
+    transaction
+        .batch_execute(&format!(
+            "DROP SCHEMA IF EXISTS {0} CASCADE;
+            CREATE SCHEMA {0};
+            SET LOCAL search_path TO {0};",
+            BASELINE_SCHEMA
+        ))
+        .await?;
+    transaction.batch_execute(&first.sql).await?;
+    let expected = schema_columns(transaction, BASELINE_SCHEMA).await?;
+    transaction
+        .batch_execute(&format!(
+            "SET LOCAL search_path TO {};
+            DROP SCHEMA {} CASCADE;",
+            search_path, BASELINE_SCHEMA
+        ))
+        .await?;
+    if existing != expected {
+        let differences = expected
+            .symmetric_difference(&existing)
+            .map(|column| {
+                let side = if expected.contains(column) {
+                    "missing"
+                } else {
+                    "unexpected"
+                };
+                format!("\n\t{} column {}", side, column)
+            })
+            .collect::<String>();
+        return Err(format!(
+            "Schema {} predates migrations but doesn't match migration {}_{}:{}",
+            schema, first.version, first.name, differences
+        )
+        .into());
+    }
+
+    println!(
+        "Recording the existing schema as migration {}_{}",
+        first.version, first.name
+    );
+    transaction
+        .execute(
+            "INSERT INTO schema_migrations (version, name, checksum) VALUES ($1, $2, $3)",
+            &[&first.version, &first.name, &first.checksum],
+        )
+        .await?;
+    Ok(Some(AppliedMigration {
+        version: first.version,
+        checksum: first.checksum.clone(),
+    }))
+}
+
+/// Every column of the schema's tables, other than the migration records, as
+/// `table.column type [NOT NULL]`.
+async fn schema_columns(
+    transaction: &tokio_postgres::Transaction<'_>,
+    schema: &str,
+) -> Result<BTreeSet<String>, Box<dyn Error>> {
+    Ok(transaction
+        .query(
+            "SELECT table_name::TEXT, column_name::TEXT, data_type::TEXT, is_nullable::TEXT
+            FROM information_schema.columns
+            WHERE table_schema = $1 AND table_name <> 'schema_migrations'",
+            &[&schema],
+        )
+        .await?
+        .iter()
+        .map(|row| {
+            let not_null = if row.get::<_, String>(3) == "NO" {
+                " NOT NULL"
+            } else {
+                ""
+            };
+            format!(
+                "{}.{} {}{}",
+                row.get::<_, String>(0),
+                row.get::<_, String>(1),
+                row.get::<_, String>(2),
+                not_null
+            )
+        })
+        .collect())
+}
+
+/// [`run_migrations`] for the SQLite backend. The transaction takes the database's write lock
+/// right away, which keeps other servers from migrating it at the same time.
+pub async fn run_sqlite_migrations(
+    pool: &SqlitePool,
+    migrations: &[Migration],
+    dry_run: bool,
+) -> Result<usize, Box<dyn Error>> {
+    let mut transaction = pool.begin_with("BEGIN IMMEDIATE").await?;
+    sqlx::raw_sql(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY NOT NULL,
+            name TEXT NOT NULL,
+            checksum TEXT NOT NULL,
+            applied_at INTEGER NOT NULL DEFAULT (unixepoch())
+        )",
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let applied = sqlx::query("SELECT version, checksum FROM schema_migrations")
+        .fetch_all(&mut *transaction)
+        .await?
+        .iter()
+        .map(|row| {
+            Ok(AppliedMigration {
+                version: row.try_get("version")?,
+                checksum: row.try_get("checksum")?,
+            })
+        })
+        .collect::<Result<Vec<AppliedMigration>, sqlx::Error>>()?;
+
+    let pending = pending_migrations(migrations, &applied)?;
+    for migration in pending.iter() {
+        if dry_run {
+            println!(
+                "Would apply migration {}_{}",
+                migration.version, migration.name
+            );
+            continue;
+        }
+        println!(
+            "Applying migration {}_{}",
+            migration.version, migration.name
+        );
+        sqlx::raw_sql(&migration.sql)
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query("INSERT INTO schema_migrations (version, name, checksum) VALUES (?1, ?2, ?3)")
+            .bind(migration.version)
+            .bind(&migration.name)
+            .bind(&migration.checksum)
+            .execute(&mut *transaction)
+            .await?;
+    }
+
+    if dry_run {
+        transaction.rollback().await?;
+    } else {
+        transaction.commit().await?;
+    }
+    Ok(pending.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migration(file_name: &str, sql: &str) -> Migration {
+        Migration::new(file_name, sql.to_owned()).unwrap()
+    }
+
+    fn applied(migration: &Migration) -> AppliedMigration {
+        AppliedMigration {
+            version: migration.version,
+            checksum: migration.checksum.clone(),
+        }
+    }
+
+    #[test]
+    fn test_pending_migrations() {
+        let first = migration("0001_initial.sql", "CREATE TABLE a ();");
+        let second = migration("0002_more.sql", "CREATE TABLE b ();\r\n");
+        assert_eq!((first.version, first.name.as_str()), (1, "initial"));
+        assert_eq!(second.checksum, checksum("CREATE TABLE b ();\n"));
+        assert!(Migration::new("initial.sql", String::new()).is_err());
+
+        let migrations = vec![first.clone(), second.clone()];
+        let pending = pending_migrations(&migrations, &[applied(&first)]).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].version, 2);
+        let all_applied = [applied(&first), applied(&second)];
+        assert!(
+            pending_migrations(&migrations, &all_applied)
+                .unwrap()
+                .is_empty()
+        );
+
+        // Edited after being applied
+        let edited = migration("0001_initial.sql", "CREATE TABLE c ();");
+        assert!(pending_migrations(&[edited, second.clone()], &all_applied).is_err());
+        // Removed after being applied
+        assert!(pending_migrations(std::slice::from_ref(&second), &all_applied).is_err());
+        // Added before an already applied migration
+        assert!(pending_migrations(&migrations, &[applied(&second)]).is_err());
+    }
+
+    /// Runs against the database in `ALICIA_TEST_POSTGRES_URL`, in a schema of its own
+    #[tokio::test]
+    #[ignore = "needs a Postgres database in ALICIA_TEST_POSTGRES_URL"]
+    async fn test_schema_from_before_migrations_is_adopted() {
+        let url = std::env::var("ALICIA_TEST_POSTGRES_URL").unwrap();
+        let (mut client, connection) = tokio_postgres::connect(&url, tokio_postgres::NoTls)
+            .await
+            .unwrap();
+        tokio::spawn(connection);
+        let migrations = load_migrations(Path::new(MIGRATIONS_PATH)).await.unwrap();
+        // Set up the way res/schema.sql used to be applied, with no migration records
+        let old_schema = async |client: &mut Client, sql: &str| {
+            client
+                .batch_execute(&format!(
+                    "DROP SCHEMA IF EXISTS alicia_test CASCADE;
+                    CREATE SCHEMA alicia_test;
+                    SET search_path TO alicia_test;
+                    {}",
+                    sql
+                ))
+                .await
+                .unwrap();
+        };
+
+        old_schema(&mut client, &migrations[0].sql).await;
+        let pending = run_migrations(&mut client, &migrations, false)
+            .await
+            .unwrap();
+        assert_eq!(pending, migrations.len() - 1);
+        let versions = client
+            .query(
+                "SELECT version FROM schema_migrations ORDER BY version",
+                &[],
+            )
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0))
+            .collect::<Vec<i64>>();
+        assert_eq!(
+            versions,
+            migrations.iter().map(|m| m.version).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            run_migrations(&mut client, &migrations, false)
+                .await
+                .unwrap(),
+            0
+        );
+
+        // Older versions of the schema lack tables and columns added since
+        let older_sql = format!(
+            "{}
+            DROP TABLE ranch_bans;
+            ALTER TABLE characters DROP COLUMN mount_slots;
+            INSERT INTO accounts (login_id) VALUES ('login');
+            INSERT INTO characters (member_no, mount_uid, nickname, char_id, mouth_serial_id,
+                face_serial_id, parts_val0, appearance_val0, head_size, height, thigh_volume,
+                leg_volume, appearance_val1, create_character_unk0)
+            SELECT member_no, 0, 'Nickname', 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0 FROM accounts;",
+            migrations[0].sql
+        );
+        old_schema(&mut client, &older_sql).await;
+        run_migrations(&mut client, &migrations, false)
+            .await
+            .unwrap();
+        let mount_slots: i32 = client
+            .query_one("SELECT mount_slots FROM characters", &[])
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(mount_slots, 3);
+        client
+            .batch_execute("SELECT character_id FROM ranch_bans")
+            .await
+            .unwrap();
+
+        // Changed by hand in ways the upgrade can't know about
+        let changed_sql = format!(
+            "{}\nALTER TABLE horses ADD COLUMN nickname TEXT;",
+            migrations[0].sql
+        );
+        old_schema(&mut client, &changed_sql).await;
+        let error = run_migrations(&mut client, &migrations, false)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("unexpected column horses.nickname"),
+            "{}",
+            error
+        );
+
+        client
+            .batch_execute("DROP SCHEMA alicia_test CASCADE")
+            .await
+            .unwrap();
+    }
+}
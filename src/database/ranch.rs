@@ -0,0 +1,266 @@
+use std::error::Error;
+
+use async_trait::async_trait;
+use postgres_from_row::FromRow;
+use tokio_postgres::Transaction;
+
+use crate::{
+    database::{U8Sql, U32Sql},
+    entities::ranch::{Ranch, RanchVisibility},
+};
+
+#[async_trait]
+pub trait RanchRepository {
+    async fn get_ranch_by_id(
+        &mut self,
+        ranch_id: u32,
+    ) -> Result<Option<Ranch>, Box<dyn Error + Send + Sync>>;
+    /// Fetches the ranch owned by a character, creating it with the default name and options
+    /// if it doesn't exist yet. Returns `None` if the character doesn't exist.
+    async fn get_or_create_ranch_by_owner(
+        &mut self,
+        owner_character_id: u32,
+    ) -> Result<Option<Ranch>, Box<dyn Error + Send + Sync>>;
+    /// Picks a random public ranch with room for more visitors, not owned by `character_id`.
+    /// Ranches with visitors are preferred over the ones of offline owners.
+    async fn get_random_ranch(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Option<Ranch>, Box<dyn Error + Send + Sync>>;
+    async fn update_ranch_options(
+        &mut self,
+        owner_character_id: u32,
+        visibility: RanchVisibility,
+        max_visitors: u8,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+    async fn update_visitor_count(
+        &mut self,
+        ranch_id: u32,
+        visitor_count: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+    /// Visitor counts left over from a previous run are meaningless, as nobody is connected yet.
+    async fn reset_visitor_counts(&mut self) -> Result<(), Box<dyn Error + Send + Sync>>;
+    /// Bars a character from entering a ranch for the given amount of seconds,
+    /// replacing any previous ban.
+    async fn ban_from_ranch(
+        &mut self,
+        ranch_id: u32,
+        character_id: u32,
+        duration_secs: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+    async fn is_banned_from_ranch(
+        &mut self,
+        ranch_id: u32,
+        character_id: u32,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>>;
+    /// Stores the code the character has to present to enter the ranch within the given
+    /// amount of seconds, replacing any previous one.
+    async fn issue_ranch_entry_code(
+        &mut self,
+        character_id: u32,
+        ranch_id: u32,
+        code: u32,
+        valid_secs: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+    /// Uses up the character's entry code, returning the ranch it was issued for if it's
+    /// `code` and still valid. Codes are gone after the first attempt, even a failed one.
+    async fn redeem_ranch_entry_code(
+        &mut self,
+        character_id: u32,
+        code: u32,
+    ) -> Result<Option<u32>, Box<dyn Error + Send + Sync>>;
+}
+
+#[async_trait]
+impl RanchRepository for Transaction<'_> {
+    async fn get_ranch_by_id(
+        &mut self,
+        ranch_id: u32,
+    ) -> Result<Option<Ranch>, Box<dyn Error + Send + Sync>> {
+        let row_opt = self
+            .query_opt(
+                "SELECT * FROM ranches WHERE ranch_id = $1",
+                &[&U32Sql::from(ranch_id)],
+            )
+            .await?;
+        if let Some(row) = row_opt {
+            Ok(Some(Ranch::try_from_row(&row)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn get_or_create_ranch_by_owner(
+        &mut self,
+        owner_character_id: u32,
+    ) -> Result<Option<Ranch>, Box<dyn Error + Send + Sync>> {
+        self.execute(
+            "INSERT INTO ranches (owner_character_id, name)
+            SELECT character_id, nickname || '''s Ranch' FROM characters WHERE character_id = $1
+            ON CONFLICT (owner_character_id) DO NOTHING",
+            &[&U32Sql::from(owner_character_id)],
+        )
+        .await?;
+        let row_opt = self
+            .query_opt(
+                "SELECT * FROM ranches WHERE owner_character_id = $1",
+                &[&U32Sql::from(owner_character_id)],
+            )
+            .await?;
+        if let Some(row) = row_opt {
+            Ok(Some(Ranch::try_from_row(&row)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn get_random_ranch(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Option<Ranch>, Box<dyn Error + Send + Sync>> {
+        let row_opt = self
+            .query_opt(
+                "SELECT * FROM ranches
+                WHERE owner_character_id <> $1
+                    AND visibility = $2
+                    AND (max_visitors = 0 OR visitor_count < max_visitors)
+                ORDER BY visitor_count > 0 DESC, random()
+                LIMIT 1",
+                &[
+                    &U32Sql::from(character_id),
+                    &U8Sql::from(RanchVisibility::Public as u8),
+                ],
+            )
+            .await?;
+        if let Some(row) = row_opt {
+            Ok(Some(Ranch::try_from_row(&row)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn update_ranch_options(
+        &mut self,
+        owner_character_id: u32,
+        visibility: RanchVisibility,
+        max_visitors: u8,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let rows_affected = self
+            .execute(
+                "UPDATE ranches SET visibility = $1, max_visitors = $2 WHERE owner_character_id = $3",
+                &[
+                    &U8Sql::from(visibility as u8),
+                    &U8Sql::from(max_visitors),
+                    &U32Sql::from(owner_character_id),
+                ],
+            )
+            .await?;
+        if rows_affected != 1 {
+            Err(format!("Unexpected number of affected rows: {}", rows_affected).into())
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn update_visitor_count(
+        &mut self,
+        ranch_id: u32,
+        visitor_count: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.execute(
+            "UPDATE ranches SET visitor_count = $1 WHERE ranch_id = $2",
+            &[&U32Sql::from(visitor_count), &U32Sql::from(ranch_id)],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn reset_visitor_counts(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.execute("UPDATE ranches SET visitor_count = 0", &[])
+            .await?;
+        Ok(())
+    }
+
+    async fn ban_from_ranch(
+        &mut self,
+        ranch_id: u32,
+        character_id: u32,
+        duration_secs: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self
+            .execute(
+                "INSERT INTO ranch_bans (ranch_id, character_id, expires_at)
+                VALUES ($1, $2, NOW() + make_interval(secs => $3))
+                ON CONFLICT (ranch_id, character_id) DO UPDATE SET expires_at = EXCLUDED.expires_at",
+                &[
+                    &U32Sql::from(ranch_id),
+                    &U32Sql::from(character_id),
+                    &(duration_secs as f64),
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn is_banned_from_ranch(
+        &mut self,
+        ranch_id: u32,
+        character_id: u32,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let row = self
+            .query_one(
+                "SELECT EXISTS (
+                    SELECT 1 FROM ranch_bans
+                    WHERE ranch_id = $1 AND character_id = $2 AND expires_at > NOW()
+                )",
+                &[&U32Sql::from(ranch_id), &U32Sql::from(character_id)],
+            )
+            .await?;
+        Ok(row.try_get(0)?)
+    }
+
+    async fn issue_ranch_entry_code(
+        &mut self,
+        character_id: u32,
+        ranch_id: u32,
+        code: u32,
+        valid_secs: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self
+            .execute(
+                "INSERT INTO ranch_entry_codes (character_id, ranch_id, code, expires_at)
+                VALUES ($1, $2, $3, NOW() + make_interval(secs => $4))
+                ON CONFLICT (character_id) DO UPDATE
+                SET ranch_id = EXCLUDED.ranch_id, code = EXCLUDED.code, expires_at = EXCLUDED.expires_at",
+                &[
+                    &U32Sql::from(character_id),
+                    &U32Sql::from(ranch_id),
+                    &U32Sql::from(code),
+                    &(valid_secs as f64),
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn redeem_ranch_entry_code(
+        &mut self,
+        character_id: u32,
+        code: u32,
+    ) -> Result<Option<u32>, Box<dyn Error + Send + Sync>> {
+        let row_opt = self
+            .query_opt(
+                "DELETE FROM ranch_entry_codes WHERE character_id = $1
+                RETURNING ranch_id, code, expires_at > NOW()",
+                &[&U32Sql::from(character_id)],
+            )
+            .await?;
+        let Some(row) = row_opt else {
+            return Ok(None);
+        };
+        let ranch_id: U32Sql = row.try_get(0)?;
+        let issued_code: U32Sql = row.try_get(1)?;
+        let valid: bool = row.try_get(2)?;
+        Ok((valid && u32::from(issued_code) == code).then(|| ranch_id.into()))
+    }
+}
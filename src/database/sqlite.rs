@@ -0,0 +1,1082 @@
+//! SQLite backend, for machines that can't run or download Postgres.
+//! SQLite integers are 64 bits wide, so unsigned values are stored as they are instead of being
+//! reinterpreted as signed like the `U*Sql` wrappers do for Postgres, and reading back a value
+//! that doesn't fit its type fails rather than wrapping around.
+
+use std::{error::Error, ffi::CString};
+
+use async_trait::async_trait;
+use sqlx::{
+    Row, Sqlite, TypeInfo, ValueRef,
+    query::Query,
+    sqlite::{SqliteArguments, SqliteRow},
+};
+
+use crate::{
+    commands::shared::{
+        character::{Appearance as CharacterAppearance, Parts as CharacterParts},
+        horse::Horse,
+    },
+    database::{
+        SqlColumns, SqlField, SqlValue,
+        account::AccountRepository,
+        character::CharacterRepository,
+        horse::{
+            HorseRepository, STALLION_LISTING_SELECT, StallionFilter, failure_card_columns,
+            failure_card_from_columns, horse_columns,
+        },
+        insert_sql,
+        item::ItemRepository,
+        ranch::RanchRepository,
+        update_sql,
+        wallet::{INITIAL_CARROTS, WalletRepository},
+        wishlist::WishlistRepository,
+    },
+    entities::{
+        account::Account,
+        character::Character,
+        lineage::{BreedingRecord, Lineage},
+        ranch::{Ranch, RanchVisibility},
+        stallion::{Stallion, StallionListing},
+        wallet::Wallet,
+    },
+    genetics::{FailureReward, Foal},
+};
+
+type Transaction<'a> = sqlx::Transaction<'a, Sqlite>;
+
+/// Draws the next value of the uid shared by characters, horses, items and ranches
+async fn next_uid(transaction: &mut Transaction<'_>) -> Result<u32, Box<dyn Error + Send + Sync>> {
+    let uid =
+        sqlx::query_scalar("UPDATE uid_sequence SET last_uid = last_uid + 1 RETURNING last_uid")
+            .fetch_one(&mut **transaction)
+            .await?;
+    Ok(uid)
+}
+
+fn sqlite_placeholder(index: usize) -> String {
+    format!("?{}", index)
+}
+
+/// Id lists are passed as JSON arrays, to be expanded with `json_each`
+fn to_json_array(ids: &[u32]) -> Result<String, Box<dyn Error + Send + Sync>> {
+    Ok(serde_json::to_string(ids)?)
+}
+
+fn expect_one_row(rows_affected: u64) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if rows_affected != 1 {
+        Err(format!("Unexpected number of affected rows: {}", rows_affected).into())
+    } else {
+        Ok(())
+    }
+}
+
+fn account_from_row(row: &SqliteRow) -> Result<Account, sqlx::Error> {
+    Ok(Account {
+        member_no: row.try_get("member_no")?,
+        login_id: row.try_get("login_id")?,
+        auth_key: row.try_get("auth_key")?,
+    })
+}
+
+fn character_from_row(row: &SqliteRow) -> Result<Character, sqlx::Error> {
+    Ok(Character {
+        character_id: row.try_get("character_id")?,
+        nickname: row.try_get("nickname")?,
+        mount_uid: row.try_get("mount_uid")?,
+        mount_slots: row.try_get("mount_slots")?,
+        character: crate::commands::shared::character::Character {
+            parts: CharacterParts {
+                char_id: row.try_get("char_id")?,
+                mouth_serial_id: row.try_get("mouth_serial_id")?,
+                face_serial_id: row.try_get("face_serial_id")?,
+                val0: row.try_get("parts_val0")?,
+            },
+            appearance: CharacterAppearance {
+                val0: row.try_get("appearance_val0")?,
+                head_size: row.try_get("head_size")?,
+                height: row.try_get("height")?,
+                thigh_volume: row.try_get("thigh_volume")?,
+                leg_volume: row.try_get("leg_volume")?,
+                val1: row.try_get("appearance_val1")?,
+            },
+        },
+        create_character_unk0: row.try_get("create_character_unk0")?,
+    })
+}
+
+/// Value of a column, typed after what SQLite actually stored in it
+fn column_value(row: &SqliteRow, column: &str) -> Result<SqlValue, Box<dyn Error + Send + Sync>> {
+    if row.try_get_raw(column)?.type_info().name() == "TEXT" {
+        Ok(SqlValue::Text(CString::new(
+            row.try_get::<String, _>(column)?,
+        )?))
+    } else {
+        Ok(SqlValue::Integer(row.try_get(column)?))
+    }
+}
+
+fn horse_from_row(row: &SqliteRow) -> Result<Horse, Box<dyn Error + Send + Sync>> {
+    Horse::read_columns(&mut |column| column_value(row, column))
+}
+
+fn bind_values<'q>(
+    mut query: Query<'q, Sqlite, SqliteArguments<'q>>,
+    values: Vec<SqlValue>,
+) -> Result<Query<'q, Sqlite, SqliteArguments<'q>>, Box<dyn Error + Send + Sync>> {
+    for value in values {
+        query = match value {
+            SqlValue::Integer(value) => query.bind(value),
+            SqlValue::Text(value) => query.bind(value.into_string()?),
+        };
+    }
+    Ok(query)
+}
+
+fn stallion_from_row(row: &SqliteRow) -> Result<Stallion, sqlx::Error> {
+    Ok(Stallion {
+        horse_uid: row.try_get("horse_uid")?,
+        price: row.try_get("price")?,
+        times_mated: row.try_get("times_mated")?,
+    })
+}
+
+fn stallion_listing_from_row(
+    row: &SqliteRow,
+) -> Result<StallionListing, Box<dyn Error + Send + Sync>> {
+    Ok(StallionListing {
+        stallion: stallion_from_row(row)?,
+        horse: horse_from_row(row)?,
+        owner_character_id: row.try_get("owner_character_id")?,
+        owner_nickname: row.try_get("owner_nickname")?,
+    })
+}
+
+fn lineage_from_row(row: &SqliteRow) -> Result<Lineage, sqlx::Error> {
+    Ok(Lineage {
+        sire_uid: row.try_get("sire_uid")?,
+        dam_uid: row.try_get("dam_uid")?,
+        sire_sire_uid: row.try_get("sire_sire_uid")?,
+        sire_dam_uid: row.try_get("sire_dam_uid")?,
+        dam_sire_uid: row.try_get("dam_sire_uid")?,
+        dam_dam_uid: row.try_get("dam_dam_uid")?,
+    })
+}
+
+fn ranch_from_row(row: &SqliteRow) -> Result<Ranch, sqlx::Error> {
+    Ok(Ranch {
+        ranch_id: row.try_get("ranch_id")?,
+        owner_character_id: row.try_get("owner_character_id")?,
+        name: row.try_get("name")?,
+        visibility: row.try_get("visibility")?,
+        max_visitors: row.try_get("max_visitors")?,
+        visitor_count: row.try_get("visitor_count")?,
+    })
+}
+
+fn wallet_from_row(row: &SqliteRow) -> Result<Wallet, sqlx::Error> {
+    Ok(Wallet {
+        character_id: row.try_get("character_id")?,
+        carrots: row.try_get("carrots")?,
+        breeding_earnings: row.try_get("breeding_earnings")?,
+    })
+}
+
+#[async_trait]
+impl AccountRepository for Transaction<'_> {
+    async fn get_accounts(&mut self) -> Result<Vec<Account>, Box<dyn Error + Send + Sync>> {
+        let rows = sqlx::query("SELECT * FROM accounts")
+            .fetch_all(&mut **self)
+            .await?;
+        Ok(rows
+            .iter()
+            .map(account_from_row)
+            .collect::<Result<_, _>>()?)
+    }
+
+    async fn get_account(
+        &mut self,
+        member_no: u32,
+    ) -> Result<Option<Account>, Box<dyn Error + Send + Sync>> {
+        let row = sqlx::query("SELECT * FROM accounts WHERE member_no = ?1")
+            .bind(member_no)
+            .fetch_optional(&mut **self)
+            .await?;
+        Ok(row.as_ref().map(account_from_row).transpose()?)
+    }
+
+    async fn add_account(
+        &mut self,
+        new_account: &mut Account,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        new_account.member_no = sqlx::query_scalar(
+            "INSERT INTO accounts (login_id, auth_key) VALUES (?1, ?2) RETURNING member_no",
+        )
+        .bind(&new_account.login_id)
+        .bind(&new_account.auth_key)
+        .fetch_one(&mut **self)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_account(&mut self, member_no: u32) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let result = sqlx::query("DELETE FROM accounts WHERE member_no = ?1")
+            .bind(member_no)
+            .execute(&mut **self)
+            .await?;
+        expect_one_row(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl CharacterRepository for Transaction<'_> {
+    async fn get_character_by_member_no(
+        &mut self,
+        member_no: u32,
+    ) -> Result<Option<Character>, Box<dyn Error + Send + Sync>> {
+        let row = sqlx::query("SELECT * FROM characters WHERE member_no = ?1")
+            .bind(member_no)
+            .fetch_optional(&mut **self)
+            .await?;
+        Ok(row.as_ref().map(character_from_row).transpose()?)
+    }
+
+    async fn get_character_by_id(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Option<Character>, Box<dyn Error + Send + Sync>> {
+        let row = sqlx::query("SELECT * FROM characters WHERE character_id = ?1")
+            .bind(character_id)
+            .fetch_optional(&mut **self)
+            .await?;
+        Ok(row.as_ref().map(character_from_row).transpose()?)
+    }
+
+    async fn insert_character(
+        &mut self,
+        member_no: u32,
+        character: &mut Character,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let character_id = next_uid(self).await?;
+        sqlx::query(
+            "INSERT INTO characters (
+                character_id,
+                member_no,
+                nickname,
+                mount_uid,
+                mount_slots,
+                char_id,
+                mouth_serial_id,
+                face_serial_id,
+                parts_val0,
+                appearance_val0,
+                head_size,
+                height,
+                thigh_volume,
+                leg_volume,
+                appearance_val1,
+                create_character_unk0
+            ) VALUES (?1,?2,?3,?4,?5,?6,?7,?8,?9,?10,?11,?12,?13,?14,?15,?16)",
+        )
+        .bind(character_id)
+        .bind(member_no)
+        .bind(&character.nickname)
+        .bind(character.mount_uid)
+        .bind(character.mount_slots)
+        .bind(character.character.parts.char_id)
+        .bind(character.character.parts.mouth_serial_id)
+        .bind(character.character.parts.face_serial_id)
+        .bind(character.character.parts.val0)
+        .bind(character.character.appearance.val0)
+        .bind(character.character.appearance.head_size)
+        .bind(character.character.appearance.height)
+        .bind(character.character.appearance.thigh_volume)
+        .bind(character.character.appearance.leg_volume)
+        .bind(character.character.appearance.val1)
+        .bind(character.create_character_unk0)
+        .execute(&mut **self)
+        .await?;
+        character.character_id = character_id;
+        Ok(())
+    }
+
+    async fn update_character(
+        &mut self,
+        character: &Character,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let result = sqlx::query(
+            "UPDATE characters SET
+                nickname = ?1,
+                mount_uid = ?2,
+                mount_slots = ?3,
+                char_id = ?4,
+                mouth_serial_id = ?5,
+                face_serial_id = ?6,
+                parts_val0 = ?7,
+                appearance_val0 = ?8,
+                head_size = ?9,
+                height = ?10,
+                thigh_volume = ?11,
+                leg_volume = ?12,
+                appearance_val1 = ?13,
+                create_character_unk0 = ?14
+            WHERE character_id = ?15",
+        )
+        .bind(&character.nickname)
+        .bind(character.mount_uid)
+        .bind(character.mount_slots)
+        .bind(character.character.parts.char_id)
+        .bind(character.character.parts.mouth_serial_id)
+        .bind(character.character.parts.face_serial_id)
+        .bind(character.character.parts.val0)
+        .bind(character.character.appearance.val0)
+        .bind(character.character.appearance.head_size)
+        .bind(character.character.appearance.height)
+        .bind(character.character.appearance.thigh_volume)
+        .bind(character.character.appearance.leg_volume)
+        .bind(character.character.appearance.val1)
+        .bind(character.create_character_unk0)
+        .bind(character.character_id)
+        .execute(&mut **self)
+        .await?;
+        expect_one_row(result.rows_affected())
+    }
+
+    async fn delete_character(
+        &mut self,
+        character_id: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let result = sqlx::query("DELETE FROM characters WHERE character_id = ?1")
+            .bind(character_id)
+            .execute(&mut **self)
+            .await?;
+        expect_one_row(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl HorseRepository for Transaction<'_> {
+    async fn get_horses_by_character_id(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Vec<Horse>, Box<dyn Error + Send + Sync>> {
+        let rows = sqlx::query("SELECT * FROM horses WHERE character_id = ?1")
+            .bind(character_id)
+            .fetch_all(&mut **self)
+            .await?;
+        rows.iter().map(horse_from_row).collect()
+    }
+
+    async fn count_horses_by_character_id(
+        &mut self,
+        character_id: u32,
+    ) -> Result<u32, Box<dyn Error + Send + Sync>> {
+        let count = sqlx::query_scalar("SELECT COUNT(*) FROM horses WHERE character_id = ?1")
+            .bind(character_id)
+            .fetch_one(&mut **self)
+            .await?;
+        Ok(count)
+    }
+
+    async fn get_horse_by_uid(
+        &mut self,
+        uid: u32,
+    ) -> Result<Option<Horse>, Box<dyn Error + Send + Sync>> {
+        let row = sqlx::query("SELECT * FROM horses WHERE uid = ?1")
+            .bind(uid)
+            .fetch_optional(&mut **self)
+            .await?;
+        row.as_ref().map(horse_from_row).transpose()
+    }
+
+    async fn get_horses_by_uids(
+        &mut self,
+        uids: &[u32],
+    ) -> Result<Vec<Horse>, Box<dyn Error + Send + Sync>> {
+        let rows =
+            sqlx::query("SELECT * FROM horses WHERE uid IN (SELECT value FROM json_each(?1))")
+                .bind(to_json_array(uids)?)
+                .fetch_all(&mut **self)
+                .await?;
+        rows.iter().map(horse_from_row).collect()
+    }
+
+    async fn insert_horse(
+        &mut self,
+        character_id: u32,
+        horse: &mut Horse,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let uid = next_uid(self).await?;
+        let (mut columns, mut values) = horse_columns(horse);
+        columns.extend(["uid", "character_id"]);
+        values.extend([uid.to_sql_value(), character_id.to_sql_value()]);
+        let query = insert_sql("horses", &columns, sqlite_placeholder);
+        bind_values(sqlx::query(&query), values)?
+            .execute(&mut **self)
+            .await?;
+        horse.uid = uid;
+        Ok(())
+    }
+
+    async fn update_horse(
+        &mut self,
+        horse: &mut Horse,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let (columns, mut values) = horse_columns(horse);
+        values.push(horse.uid.to_sql_value());
+        let query = update_sql("horses", &columns, "uid", sqlite_placeholder);
+        let result = bind_values(sqlx::query(&query), values)?
+            .execute(&mut **self)
+            .await?;
+        expect_one_row(result.rows_affected())
+    }
+
+    async fn remove_horse(&mut self, horse_uid: u32) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let result = sqlx::query("DELETE FROM horses WHERE uid = ?1")
+            .bind(horse_uid)
+            .execute(&mut **self)
+            .await?;
+        expect_one_row(result.rows_affected())
+    }
+
+    async fn get_stallion(
+        &mut self,
+        horse_uid: u32,
+    ) -> Result<Option<Stallion>, Box<dyn Error + Send + Sync>> {
+        let row = sqlx::query("SELECT * FROM stallions WHERE horse_uid = ?1")
+            .bind(horse_uid)
+            .fetch_optional(&mut **self)
+            .await?;
+        Ok(row.as_ref().map(stallion_from_row).transpose()?)
+    }
+
+    async fn insert_stallion(
+        &mut self,
+        stallion: &Stallion,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        sqlx::query("INSERT INTO stallions (horse_uid, price, times_mated) VALUES (?1, ?2, ?3)")
+            .bind(stallion.horse_uid)
+            .bind(stallion.price)
+            .bind(stallion.times_mated)
+            .execute(&mut **self)
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_stallion(
+        &mut self,
+        horse_uid: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let result = sqlx::query("DELETE FROM stallions WHERE horse_uid = ?1")
+            .bind(horse_uid)
+            .execute(&mut **self)
+            .await?;
+        expect_one_row(result.rows_affected())
+    }
+
+    async fn get_stallion_listing(
+        &mut self,
+        horse_uid: u32,
+    ) -> Result<Option<StallionListing>, Box<dyn Error + Send + Sync>> {
+        let query = format!("{} WHERE stallions.horse_uid = ?1", STALLION_LISTING_SELECT);
+        let row = sqlx::query(&query)
+            .bind(horse_uid)
+            .fetch_optional(&mut **self)
+            .await?;
+        row.as_ref().map(stallion_listing_from_row).transpose()
+    }
+
+    async fn record_stallion_mating(
+        &mut self,
+        horse_uid: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let result =
+            sqlx::query("UPDATE stallions SET times_mated = times_mated + 1 WHERE horse_uid = ?1")
+                .bind(horse_uid)
+                .execute(&mut **self)
+                .await?;
+        expect_one_row(result.rows_affected())
+    }
+
+    async fn search_stallions(
+        &mut self,
+        filter: &StallionFilter,
+    ) -> Result<Vec<StallionListing>, Box<dyn Error + Send + Sync>> {
+        let query = format!(
+            "{}
+            WHERE horses.character_id <> ?1
+                AND (json_array_length(?2) = 0 OR horses.skin_id IN (SELECT value FROM json_each(?2)))
+                AND (json_array_length(?3) = 0 OR horses.mane_id IN (SELECT value FROM json_each(?3)))
+                AND (json_array_length(?4) = 0 OR horses.tail_id IN (SELECT value FROM json_each(?4)))
+            ORDER BY stallions.horse_uid
+            LIMIT ?5",
+            STALLION_LISTING_SELECT
+        );
+        let rows = sqlx::query(&query)
+            .bind(filter.exclude_character_id)
+            .bind(to_json_array(&filter.skin_ids)?)
+            .bind(to_json_array(&filter.mane_ids)?)
+            .bind(to_json_array(&filter.tail_ids)?)
+            .bind(filter.limit)
+            .fetch_all(&mut **self)
+            .await?;
+        rows.iter().map(stallion_listing_from_row).collect()
+    }
+
+    async fn get_lineage(
+        &mut self,
+        horse_uid: u32,
+    ) -> Result<Option<Lineage>, Box<dyn Error + Send + Sync>> {
+        let row = sqlx::query(
+            "SELECT
+                sire_uid, dam_uid,
+                sire_sire_uid, sire_dam_uid, dam_sire_uid, dam_dam_uid
+            FROM horse_lineage WHERE horse_uid = ?1",
+        )
+        .bind(horse_uid)
+        .fetch_optional(&mut **self)
+        .await?;
+        Ok(row.as_ref().map(lineage_from_row).transpose()?)
+    }
+
+    async fn get_breeding_records(
+        &mut self,
+        uids: &[u32],
+    ) -> Result<Vec<BreedingRecord>, Box<dyn Error + Send + Sync>> {
+        let rows = sqlx::query(
+            "SELECT
+                horses.uid AS horse_uid,
+                (
+                    SELECT COUNT(*) FROM horse_lineage AS offspring
+                    WHERE offspring.sire_uid = horses.uid OR offspring.dam_uid = horses.uid
+                ) AS breeding_count,
+                COALESCE(own.coat_bonus, 0) AS coat_bonus,
+                EXISTS (
+                    SELECT 1 FROM stallions WHERE stallions.horse_uid = horses.uid
+                ) AS is_stallion
+            FROM horses
+            LEFT JOIN horse_lineage AS own ON own.horse_uid = horses.uid
+            WHERE horses.uid IN (SELECT value FROM json_each(?1))",
+        )
+        .bind(to_json_array(uids)?)
+        .fetch_all(&mut **self)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(BreedingRecord {
+                    horse_uid: row.try_get("horse_uid")?,
+                    breeding_count: row.try_get("breeding_count")?,
+                    coat_bonus: row.try_get("coat_bonus")?,
+                    is_stallion: row.try_get("is_stallion")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn insert_lineage(&mut self, foal: &Foal) -> Result<(), Box<dyn Error + Send + Sync>> {
+        sqlx::query(
+            "INSERT INTO horse_lineage (
+                horse_uid, sire_uid, dam_uid,
+                sire_sire_uid, sire_dam_uid, dam_sire_uid, dam_dam_uid,
+                coat_bonus
+            )
+            SELECT
+                ?1, ?2, ?3,
+                COALESCE(sire.sire_uid, 0), COALESCE(sire.dam_uid, 0),
+                COALESCE(dam.sire_uid, 0), COALESCE(dam.dam_uid, 0),
+                ?4
+            FROM (SELECT 1) AS foal
+            LEFT JOIN horse_lineage AS sire ON sire.horse_uid = ?2
+            LEFT JOIN horse_lineage AS dam ON dam.horse_uid = ?3",
+        )
+        .bind(foal.horse.uid)
+        .bind(foal.parentage.sire_uid)
+        .bind(foal.parentage.dam_uid)
+        .bind(foal.coat_bonus)
+        .execute(&mut **self)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_failure_cards(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Vec<FailureReward>, Box<dyn Error + Send + Sync>> {
+        let rows = sqlx::query(
+            "SELECT item_tid, amount FROM breeding_failure_cards
+            WHERE character_id = ?1 ORDER BY position",
+        )
+        .bind(character_id)
+        .fetch_all(&mut **self)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(failure_card_from_columns(
+                    row.try_get("item_tid")?,
+                    row.try_get("amount")?,
+                ))
+            })
+            .collect()
+    }
+
+    async fn set_failure_cards(
+        &mut self,
+        character_id: u32,
+        cards: &[FailureReward],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        sqlx::query("DELETE FROM breeding_failure_cards WHERE character_id = ?1")
+            .bind(character_id)
+            .execute(&mut **self)
+            .await?;
+        for (position, card) in cards.iter().enumerate() {
+            let (item_tid, amount) = failure_card_columns(card);
+            sqlx::query(
+                "INSERT INTO breeding_failure_cards (character_id, position, item_tid, amount)
+                VALUES (?1, ?2, ?3, ?4)",
+            )
+            .bind(character_id)
+            .bind(position as u32)
+            .bind(item_tid)
+            .bind(amount)
+            .execute(&mut **self)
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn take_failure_cards(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Vec<FailureReward>, Box<dyn Error + Send + Sync>> {
+        let cards = self.get_failure_cards(character_id).await?;
+        sqlx::query("DELETE FROM breeding_failure_cards WHERE character_id = ?1")
+            .bind(character_id)
+            .execute(&mut **self)
+            .await?;
+        Ok(cards)
+    }
+}
+
+#[async_trait]
+impl ItemRepository for Transaction<'_> {
+    async fn add_item(
+        &mut self,
+        character_id: u32,
+        tid: u32,
+        count: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        // Like Postgres' column default, a uid is drawn even if the item ends up stacked
+        let uid = next_uid(self).await?;
+        sqlx::query(
+            "INSERT INTO items (uid, character_id, tid, count) VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (character_id, tid) DO UPDATE SET count = items.count + excluded.count",
+        )
+        .bind(uid)
+        .bind(character_id)
+        .bind(tid)
+        .bind(count)
+        .execute(&mut **self)
+        .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RanchRepository for Transaction<'_> {
+    async fn get_ranch_by_id(
+        &mut self,
+        ranch_id: u32,
+    ) -> Result<Option<Ranch>, Box<dyn Error + Send + Sync>> {
+        let row = sqlx::query("SELECT * FROM ranches WHERE ranch_id = ?1")
+            .bind(ranch_id)
+            .fetch_optional(&mut **self)
+            .await?;
+        Ok(row.as_ref().map(ranch_from_row).transpose()?)
+    }
+
+    async fn get_or_create_ranch_by_owner(
+        &mut self,
+        owner_character_id: u32,
+    ) -> Result<Option<Ranch>, Box<dyn Error + Send + Sync>> {
+        let row = sqlx::query("SELECT * FROM ranches WHERE owner_character_id = ?1")
+            .bind(owner_character_id)
+            .fetch_optional(&mut **self)
+            .await?;
+        if let Some(row) = row {
+            return Ok(Some(ranch_from_row(&row)?));
+        }
+        let ranch_id = next_uid(self).await?;
+        let row = sqlx::query(
+            "INSERT INTO ranches (ranch_id, owner_character_id, name)
+            SELECT ?1, character_id, nickname || '''s Ranch' FROM characters WHERE character_id = ?2
+            RETURNING *",
+        )
+        .bind(ranch_id)
+        .bind(owner_character_id)
+        .fetch_optional(&mut **self)
+        .await?;
+        Ok(row.as_ref().map(ranch_from_row).transpose()?)
+    }
+
+    async fn get_random_ranch(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Option<Ranch>, Box<dyn Error + Send + Sync>> {
+        let row = sqlx::query(
+            "SELECT * FROM ranches
+            WHERE owner_character_id <> ?1
+                AND visibility = ?2
+                AND (max_visitors = 0 OR visitor_count < max_visitors)
+            ORDER BY visitor_count > 0 DESC, random()
+            LIMIT 1",
+        )
+        .bind(character_id)
+        .bind(RanchVisibility::Public as u8)
+        .fetch_optional(&mut **self)
+        .await?;
+        Ok(row.as_ref().map(ranch_from_row).transpose()?)
+    }
+
+    async fn update_ranch_options(
+        &mut self,
+        owner_character_id: u32,
+        visibility: RanchVisibility,
+        max_visitors: u8,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let result = sqlx::query(
+            "UPDATE ranches SET visibility = ?1, max_visitors = ?2 WHERE owner_character_id = ?3",
+        )
+        .bind(visibility as u8)
+        .bind(max_visitors)
+        .bind(owner_character_id)
+        .execute(&mut **self)
+        .await?;
+        expect_one_row(result.rows_affected())
+    }
+
+    async fn update_visitor_count(
+        &mut self,
+        ranch_id: u32,
+        visitor_count: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        sqlx::query("UPDATE ranches SET visitor_count = ?1 WHERE ranch_id = ?2")
+            .bind(visitor_count)
+            .bind(ranch_id)
+            .execute(&mut **self)
+            .await?;
+        Ok(())
+    }
+
+    async fn reset_visitor_counts(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        sqlx::query("UPDATE ranches SET visitor_count = 0")
+            .execute(&mut **self)
+            .await?;
+        Ok(())
+    }
+
+    async fn ban_from_ranch(
+        &mut self,
+        ranch_id: u32,
+        character_id: u32,
+        duration_secs: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        sqlx::query(
+            "INSERT INTO ranch_bans (ranch_id, character_id, expires_at)
+            VALUES (?1, ?2, unixepoch() + ?3)
+            ON CONFLICT (ranch_id, character_id) DO UPDATE SET expires_at = excluded.expires_at",
+        )
+        .bind(ranch_id)
+        .bind(character_id)
+        .bind(i64::try_from(duration_secs)?)
+        .execute(&mut **self)
+        .await?;
+        Ok(())
+    }
+
+    async fn is_banned_from_ranch(
+        &mut self,
+        ranch_id: u32,
+        character_id: u32,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let banned = sqlx::query_scalar(
+            "SELECT EXISTS (
+                SELECT 1 FROM ranch_bans
+                WHERE ranch_id = ?1 AND character_id = ?2 AND expires_at > unixepoch()
+            )",
+        )
+        .bind(ranch_id)
+        .bind(character_id)
+        .fetch_one(&mut **self)
+        .await?;
+        Ok(banned)
+    }
+
+    async fn issue_ranch_entry_code(
+        &mut self,
+        character_id: u32,
+        ranch_id: u32,
+        code: u32,
+        valid_secs: u64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        sqlx::query(
+            "INSERT INTO ranch_entry_codes (character_id, ranch_id, code, expires_at)
+            VALUES (?1, ?2, ?3, unixepoch() + ?4)
+            ON CONFLICT (character_id) DO UPDATE
+            SET ranch_id = excluded.ranch_id, code = excluded.code, expires_at = excluded.expires_at",
+        )
+        .bind(character_id)
+        .bind(ranch_id)
+        .bind(code)
+        .bind(i64::try_from(valid_secs)?)
+        .execute(&mut **self)
+        .await?;
+        Ok(())
+    }
+
+    async fn redeem_ranch_entry_code(
+        &mut self,
+        character_id: u32,
+        code: u32,
+    ) -> Result<Option<u32>, Box<dyn Error + Send + Sync>> {
+        let redeemed: Option<(u32, u32, bool)> = sqlx::query_as(
+            "DELETE FROM ranch_entry_codes WHERE character_id = ?1
+            RETURNING ranch_id, code, expires_at > unixepoch()",
+        )
+        .bind(character_id)
+        .fetch_optional(&mut **self)
+        .await?;
+        Ok(redeemed
+            .filter(|(_, issued_code, valid)| *valid && *issued_code == code)
+            .map(|(ranch_id, _, _)| ranch_id))
+    }
+}
+
+#[async_trait]
+impl WalletRepository for Transaction<'_> {
+    async fn get_wallet_for_update(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Wallet, Box<dyn Error + Send + Sync>> {
+        // Transactions hold the database's write lock from the start, so there's no row to lock
+        sqlx::query(
+            "INSERT INTO wallets (character_id, carrots) VALUES (?1, ?2)
+            ON CONFLICT (character_id) DO NOTHING",
+        )
+        .bind(character_id)
+        .bind(INITIAL_CARROTS)
+        .execute(&mut **self)
+        .await?;
+        let row = sqlx::query("SELECT * FROM wallets WHERE character_id = ?1")
+            .bind(character_id)
+            .fetch_one(&mut **self)
+            .await?;
+        Ok(wallet_from_row(&row)?)
+    }
+
+    async fn update_wallet(&mut self, wallet: &Wallet) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let result = sqlx::query(
+            "UPDATE wallets SET carrots = ?1, breeding_earnings = ?2 WHERE character_id = ?3",
+        )
+        .bind(wallet.carrots)
+        .bind(wallet.breeding_earnings)
+        .bind(wallet.character_id)
+        .execute(&mut **self)
+        .await?;
+        expect_one_row(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl WishlistRepository for Transaction<'_> {
+    async fn get_wishlist(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Vec<StallionListing>, Box<dyn Error + Send + Sync>> {
+        let query = format!(
+            "{}
+            JOIN breeding_wishlist ON breeding_wishlist.stallion_uid = stallions.horse_uid
+            WHERE breeding_wishlist.character_id = ?1
+            ORDER BY stallions.horse_uid",
+            STALLION_LISTING_SELECT
+        );
+        let rows = sqlx::query(&query)
+            .bind(character_id)
+            .fetch_all(&mut **self)
+            .await?;
+        rows.iter().map(stallion_listing_from_row).collect()
+    }
+
+    async fn count_wishlist(
+        &mut self,
+        character_id: u32,
+    ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let count: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM breeding_wishlist WHERE character_id = ?1")
+                .bind(character_id)
+                .fetch_one(&mut **self)
+                .await?;
+        Ok(count as usize)
+    }
+
+    async fn add_to_wishlist(
+        &mut self,
+        character_id: u32,
+        stallion_uid: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let result = sqlx::query(
+            "INSERT INTO breeding_wishlist (character_id, stallion_uid) VALUES (?1, ?2)
+            ON CONFLICT DO NOTHING",
+        )
+        .bind(character_id)
+        .bind(stallion_uid)
+        .execute(&mut **self)
+        .await?;
+        if result.rows_affected() != 1 {
+            Err(format!("Stallion {} is already in the wishlist", stallion_uid).into())
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn remove_from_wishlist(
+        &mut self,
+        character_id: u32,
+        stallion_uid: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let result = sqlx::query(
+            "DELETE FROM breeding_wishlist WHERE character_id = ?1 AND stallion_uid = ?2",
+        )
+        .bind(character_id)
+        .bind(stallion_uid)
+        .execute(&mut **self)
+        .await?;
+        expect_one_row(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+    use crate::database::migrations::{
+        SQLITE_MIGRATIONS_PATH, load_migrations, run_sqlite_migrations,
+    };
+
+    #[tokio::test]
+    async fn test_unsigned_values_round_trip() {
+        // A single connection, as every connection to `:memory:` opens a database of its own
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let migrations = load_migrations(&PathBuf::from(SQLITE_MIGRATIONS_PATH))
+            .await
+            .unwrap();
+        run_sqlite_migrations(&pool, &migrations, false)
+            .await
+            .unwrap();
+
+        let mut columns: Vec<String> =
+            sqlx::query_scalar("SELECT name FROM pragma_table_info('horses')")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        columns.sort();
+        let mut expected = Horse::columns();
+        expected.push("character_id");
+        expected.sort();
+        assert_eq!(columns, expected);
+
+        let mut transaction = pool.begin().await.unwrap();
+        let mut account = Account {
+            member_no: 0,
+            login_id: "login".to_owned(),
+            auth_key: "key".to_owned(),
+        };
+        transaction.add_account(&mut account).await.unwrap();
+        let mut character = Character {
+            character_id: 0,
+            nickname: "Nickname".to_owned(),
+            mount_uid: 0,
+            mount_slots: 3,
+            character: Default::default(),
+            create_character_unk0: u32::MAX,
+        };
+        transaction
+            .insert_character(account.member_no, &mut character)
+            .await
+            .unwrap();
+        let mut horse = Horse {
+            name: CString::new("Horse").unwrap(),
+            val16: 3097585636,
+            growth_points: u16::MAX,
+            class: u8::MAX,
+            ..Default::default()
+        };
+        transaction
+            .insert_horse(character.character_id, &mut horse)
+            .await
+            .unwrap();
+
+        let stored = transaction
+            .get_character_by_id(character.character_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.create_character_unk0, u32::MAX);
+        let stored = transaction
+            .get_horse_by_uid(horse.uid)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.name, horse.name);
+        assert_eq!(stored.val16, 3097585636);
+        assert_eq!(stored.growth_points, u16::MAX);
+        assert_eq!(stored.class, u8::MAX);
+
+        let ranch = transaction
+            .get_or_create_ranch_by_owner(character.character_id)
+            .await
+            .unwrap()
+            .unwrap();
+        for (code, redeemed) in [(u32::MAX - 1, None), (u32::MAX, Some(ranch.ranch_id))] {
+            transaction
+                .issue_ranch_entry_code(character.character_id, ranch.ranch_id, u32::MAX, 60)
+                .await
+                .unwrap();
+            let ranch_id = transaction
+                .redeem_ranch_entry_code(character.character_id, code)
+                .await
+                .unwrap();
+            assert_eq!(ranch_id, redeemed);
+        }
+
+        let cards = vec![
+            FailureReward::Item {
+                tid: u32::MAX,
+                count: 1,
+            },
+            FailureReward::Carrots(u32::MAX),
+        ];
+        transaction
+            .set_failure_cards(character.character_id, &cards)
+            .await
+            .unwrap();
+        let taken = transaction
+            .take_failure_cards(character.character_id)
+            .await
+            .unwrap();
+        assert_eq!(taken, cards);
+        assert!(
+            transaction
+                .get_failure_cards(character.character_id)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
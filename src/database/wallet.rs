@@ -0,0 +1,113 @@
+use std::error::Error;
+
+use async_trait::async_trait;
+use postgres_from_row::FromRow;
+
+use crate::{
+    database::{Transaction, U32Sql},
+    entities::wallet::Wallet,
+};
+
+/// Carrots given to a character the first time its wallet is accessed.
+pub const INITIAL_CARROTS: u32 = 5000;
+
+#[async_trait]
+pub trait WalletRepository {
+    /// Fetches the wallet of a character, creating it if it doesn't exist yet.
+    /// The row stays locked until the end of the transaction.
+    async fn get_wallet_for_update(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Wallet, Box<dyn Error + Send + Sync>>;
+    async fn update_wallet(&mut self, wallet: &Wallet) -> Result<(), Box<dyn Error + Send + Sync>>;
+}
+
+#[async_trait]
+impl WalletRepository for tokio_postgres::Transaction<'_> {
+    async fn get_wallet_for_update(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Wallet, Box<dyn Error + Send + Sync>> {
+        self.execute(
+            "INSERT INTO wallets (character_id, carrots) VALUES ($1, $2)
+            ON CONFLICT (character_id) DO NOTHING",
+            &[&U32Sql::from(character_id), &U32Sql::from(INITIAL_CARROTS)],
+        )
+        .await?;
+        let row = self
+            .query_one(
+                "SELECT * FROM wallets WHERE character_id = $1 FOR UPDATE",
+                &[&U32Sql::from(character_id)],
+            )
+            .await?;
+        Ok(Wallet::try_from_row(&row)?)
+    }
+
+    async fn update_wallet(&mut self, wallet: &Wallet) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let rows_affected = self
+            .execute(
+                "UPDATE wallets SET carrots = $1, breeding_earnings = $2 WHERE character_id = $3",
+                &[
+                    &U32Sql::from(wallet.carrots),
+                    &U32Sql::from(wallet.breeding_earnings),
+                    &U32Sql::from(wallet.character_id),
+                ],
+            )
+            .await?;
+        if rows_affected != 1 {
+            Err(format!("Unexpected number of affected rows: {}", rows_affected).into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Charges `amount` carrots to the breeder and credits them to the stallion owner's
+/// breeding earnings. Returns the breeder's updated wallet.
+pub async fn transfer_breeding_fee(
+    transaction: &mut Transaction<'_>,
+    breeder_id: u32,
+    stallion_owner_id: u32,
+    amount: u32,
+) -> Result<Wallet, Box<dyn Error + Send + Sync>> {
+    // Always lock both wallets in the same order to avoid deadlocks between concurrent transfers
+    let (mut breeder, mut owner) = if breeder_id < stallion_owner_id {
+        let breeder = transaction.get_wallet_for_update(breeder_id).await?;
+        let owner = transaction.get_wallet_for_update(stallion_owner_id).await?;
+        (breeder, owner)
+    } else {
+        let owner = transaction.get_wallet_for_update(stallion_owner_id).await?;
+        let breeder = transaction.get_wallet_for_update(breeder_id).await?;
+        (breeder, owner)
+    };
+
+    breeder.carrots = breeder.carrots.checked_sub(amount).ok_or(format!(
+        "Character {} can't afford {} carrots",
+        breeder_id, amount
+    ))?;
+    owner.breeding_earnings = owner
+        .breeding_earnings
+        .checked_add(amount)
+        .ok_or("Breeding earnings overflow")?;
+
+    transaction.update_wallet(&breeder).await?;
+    transaction.update_wallet(&owner).await?;
+    Ok(breeder)
+}
+
+/// Moves every pending breeding earning into the character's carrots.
+/// Returns the amount collected and the updated wallet.
+pub async fn take_breeding_earnings(
+    transaction: &mut Transaction<'_>,
+    character_id: u32,
+) -> Result<(u32, Wallet), Box<dyn Error + Send + Sync>> {
+    let mut wallet = transaction.get_wallet_for_update(character_id).await?;
+    let collected = wallet.breeding_earnings;
+    wallet.carrots = wallet
+        .carrots
+        .checked_add(collected)
+        .ok_or("Carrot count overflow")?;
+    wallet.breeding_earnings = 0;
+    transaction.update_wallet(&wallet).await?;
+    Ok((collected, wallet))
+}
@@ -0,0 +1,108 @@
+use std::error::Error;
+
+use async_trait::async_trait;
+use postgres_from_row::FromRow;
+use tokio_postgres::Transaction;
+
+use crate::{
+    database::{U32Sql, horse::STALLION_LISTING_SELECT},
+    entities::stallion::StallionListing,
+};
+
+#[async_trait]
+pub trait WishlistRepository {
+    /// Registered stallions bookmarked by a character.
+    async fn get_wishlist(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Vec<StallionListing>, Box<dyn Error + Send + Sync>>;
+    async fn count_wishlist(
+        &mut self,
+        character_id: u32,
+    ) -> Result<usize, Box<dyn Error + Send + Sync>>;
+    async fn add_to_wishlist(
+        &mut self,
+        character_id: u32,
+        stallion_uid: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+    async fn remove_from_wishlist(
+        &mut self,
+        character_id: u32,
+        stallion_uid: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+}
+
+#[async_trait]
+impl WishlistRepository for Transaction<'_> {
+    async fn get_wishlist(
+        &mut self,
+        character_id: u32,
+    ) -> Result<Vec<StallionListing>, Box<dyn Error + Send + Sync>> {
+        let rows = self
+            .query(
+                &format!(
+                    "{}
+                JOIN breeding_wishlist ON breeding_wishlist.stallion_uid = stallions.horse_uid
+                WHERE breeding_wishlist.character_id = $1
+                ORDER BY stallions.horse_uid",
+                    STALLION_LISTING_SELECT
+                ),
+                &[&U32Sql::from(character_id)],
+            )
+            .await?;
+        rows.iter()
+            .map(|row| StallionListing::try_from_row(row).map_err(|e| e.into()))
+            .collect()
+    }
+
+    async fn count_wishlist(
+        &mut self,
+        character_id: u32,
+    ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let row = self
+            .query_one(
+                "SELECT COUNT(*) FROM breeding_wishlist WHERE character_id = $1",
+                &[&U32Sql::from(character_id)],
+            )
+            .await?;
+        let count: i64 = row.try_get(0)?;
+        Ok(count as usize)
+    }
+
+    async fn add_to_wishlist(
+        &mut self,
+        character_id: u32,
+        stallion_uid: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let rows_affected = self
+            .execute(
+                "INSERT INTO breeding_wishlist (character_id, stallion_uid) VALUES ($1, $2)
+                ON CONFLICT DO NOTHING",
+                &[&U32Sql::from(character_id), &U32Sql::from(stallion_uid)],
+            )
+            .await?;
+        if rows_affected != 1 {
+            Err(format!("Stallion {} is already in the wishlist", stallion_uid).into())
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn remove_from_wishlist(
+        &mut self,
+        character_id: u32,
+        stallion_uid: u32,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let rows_affected = self
+            .execute(
+                "DELETE FROM breeding_wishlist WHERE character_id = $1 AND stallion_uid = $2",
+                &[&U32Sql::from(character_id), &U32Sql::from(stallion_uid)],
+            )
+            .await?;
+        if rows_affected != 1 {
+            Err(format!("Unexpected number of affected rows: {}", rows_affected).into())
+        } else {
+            Ok(())
+        }
+    }
+}
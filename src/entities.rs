@@ -1,2 +1,7 @@
 pub mod account;
 pub mod character;
+pub mod lineage;
+pub mod ranch;
+pub mod stallion;
+pub mod tracked;
+pub mod wallet;
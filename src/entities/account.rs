@@ -2,7 +2,7 @@ use postgres_from_row::FromRow;
 
 use crate::database::U32Sql;
 
-#[derive(FromRow)]
+#[derive(Clone, FromRow)]
 pub struct Account {
     #[from_row(from = "U32Sql")]
     pub member_no: u32,
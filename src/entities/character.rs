@@ -9,6 +9,8 @@ pub struct Character {
     pub nickname: String,
     #[from_row(from = "U32Sql")]
     pub mount_uid: u32,
+    #[from_row(from = "U32Sql")]
+    pub mount_slots: u32,
     #[from_row(flatten)]
     pub character: crate::commands::shared::character::Character,
     #[from_row(from = "U32Sql")]
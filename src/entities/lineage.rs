@@ -0,0 +1,48 @@
+use postgres_from_row::FromRow;
+
+use crate::database::{U8Sql, U32Sql};
+
+/// Known ancestors of a horse. The value 0 is understood as "unknown ancestor".
+#[derive(Debug, Clone, FromRow)]
+pub struct Lineage {
+    #[from_row(from = "U32Sql")]
+    pub sire_uid: u32,
+    #[from_row(from = "U32Sql")]
+    pub dam_uid: u32,
+    #[from_row(from = "U32Sql")]
+    pub sire_sire_uid: u32,
+    #[from_row(from = "U32Sql")]
+    pub sire_dam_uid: u32,
+    #[from_row(from = "U32Sql")]
+    pub dam_sire_uid: u32,
+    #[from_row(from = "U32Sql")]
+    pub dam_dam_uid: u32,
+}
+impl Lineage {
+    /// Ancestors ordered generation by generation: sire and dam first, then the sire's
+    /// parents followed by the dam's parents.
+    pub fn ancestors(&self) -> [u32; 6] {
+        [
+            self.sire_uid,
+            self.dam_uid,
+            self.sire_sire_uid,
+            self.sire_dam_uid,
+            self.dam_sire_uid,
+            self.dam_dam_uid,
+        ]
+    }
+}
+
+/// Breeding history of a horse, used to tell whether it can still be bred.
+#[derive(Debug, Clone, FromRow)]
+pub struct BreedingRecord {
+    #[from_row(from = "U32Sql")]
+    pub horse_uid: u32,
+    /// Number of foals the horse has parented
+    #[from_row(from = "U32Sql")]
+    pub breeding_count: u32,
+    /// Coat bonus the horse was born with, if it was bred
+    #[from_row(from = "U8Sql")]
+    pub coat_bonus: u8,
+    pub is_stallion: bool,
+}
@@ -0,0 +1,81 @@
+use postgres_from_row::FromRow;
+
+use crate::database::{U8Sql, U32Sql};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RanchVisibility {
+    Public = 0,
+    /// Meant to let in only the owner's friends. Until there are friend lists the owner has no
+    /// friends, which leaves it working like [RanchVisibility::Locked].
+    FriendsOnly = 1,
+    Locked = 2,
+}
+impl TryFrom<u8> for RanchVisibility {
+    type Error = String;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(RanchVisibility::Public),
+            1 => Ok(RanchVisibility::FriendsOnly),
+            2 => Ok(RanchVisibility::Locked),
+            _ => Err(format!("Unknown ranch visibility {}", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct Ranch {
+    #[from_row(from = "U32Sql")]
+    pub ranch_id: u32,
+    #[from_row(from = "U32Sql")]
+    pub owner_character_id: u32,
+    pub name: String,
+    #[from_row(from = "U8Sql")]
+    pub visibility: u8,
+    #[from_row(from = "U8Sql")]
+    pub max_visitors: u8,
+    #[from_row(from = "U32Sql")]
+    pub visitor_count: u32,
+}
+impl Ranch {
+    /// Checks whether `character_id` can join the `visitor_count` characters already in the
+    /// ranch, returning the reason why it can't otherwise. Owners can always enter.
+    pub fn admits(&self, character_id: u32, visitor_count: usize) -> Result<(), String> {
+        if character_id == self.owner_character_id {
+            return Ok(());
+        }
+        match RanchVisibility::try_from(self.visibility)? {
+            RanchVisibility::Public => {}
+            // TODO: Let friends in once there are friend lists
+            RanchVisibility::FriendsOnly => return Err("Ranch is friends only".to_owned()),
+            RanchVisibility::Locked => return Err("Ranch is locked".to_owned()),
+        }
+        if self.max_visitors != 0 && visitor_count >= self.max_visitors as usize {
+            return Err("Ranch is full".to_owned());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_public_ranches_let_visitors_in() {
+        let ranch = |visibility: RanchVisibility| Ranch {
+            ranch_id: 1,
+            owner_character_id: 2,
+            name: "Ranch".to_owned(),
+            visibility: visibility as u8,
+            max_visitors: 0,
+            visitor_count: 0,
+        };
+        assert!(ranch(RanchVisibility::Public).admits(3, 1).is_ok());
+        assert!(ranch(RanchVisibility::FriendsOnly).admits(3, 1).is_err());
+        assert!(ranch(RanchVisibility::Locked).admits(3, 1).is_err());
+        // Owners get into their ranch regardless
+        for visibility in [RanchVisibility::FriendsOnly, RanchVisibility::Locked] {
+            assert!(ranch(visibility).admits(2, 1).is_ok());
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use postgres_from_row::FromRow;
+
+use crate::{commands::shared::horse::Horse, database::U32Sql};
+
+#[derive(Clone, FromRow)]
+pub struct Stallion {
+    #[from_row(from = "U32Sql")]
+    pub horse_uid: u32,
+    #[from_row(from = "U32Sql")]
+    pub price: u32,
+    #[from_row(from = "U32Sql")]
+    pub times_mated: u32,
+}
+
+/// A registered stallion together with the horse and the name of its owner,
+/// as shown in the breeding market.
+#[derive(Clone, FromRow)]
+pub struct StallionListing {
+    #[from_row(flatten)]
+    pub stallion: Stallion,
+    #[from_row(flatten)]
+    pub horse: Horse,
+    #[from_row(from = "U32Sql")]
+    pub owner_character_id: u32,
+    pub owner_nickname: String,
+}
@@ -0,0 +1,47 @@
+use std::ops::{Deref, DerefMut};
+
+/// Entity held by a session, remembering whether it changed since it was last stored.
+/// Any mutable access counts as a change.
+#[derive(Debug, Clone)]
+pub struct Tracked<T> {
+    value: T,
+    /// Bumped on every change, so that storing a copy only counts for the changes it has
+    version: u64,
+    stored_version: u64,
+}
+impl<T> Tracked<T> {
+    /// Wraps a value that is the same as the stored one
+    pub fn stored(value: T) -> Self {
+        Tracked {
+            value,
+            version: 0,
+            stored_version: 0,
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.version != self.stored_version
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Records that the value as it was at `version` is stored. Changes made since then
+    /// still need storing.
+    pub fn mark_stored(&mut self, version: u64) {
+        self.stored_version = self.stored_version.max(version);
+    }
+}
+impl<T> Deref for Tracked<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+impl<T> DerefMut for Tracked<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.version += 1;
+        &mut self.value
+    }
+}
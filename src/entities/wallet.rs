@@ -0,0 +1,13 @@
+use postgres_from_row::FromRow;
+
+use crate::database::U32Sql;
+
+#[derive(Debug, Clone, FromRow)]
+pub struct Wallet {
+    #[from_row(from = "U32Sql")]
+    pub character_id: u32,
+    #[from_row(from = "U32Sql")]
+    pub carrots: u32,
+    #[from_row(from = "U32Sql")]
+    pub breeding_earnings: u32,
+}
@@ -0,0 +1,481 @@
+use rand::{Rng, seq::IndexedRandom};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    commands::shared::{
+        alicia_time::AliciaTime,
+        horse::{Appearance, Horse, Mastery, Parts, Stats, Vals0, Vals1},
+    },
+    entities::lineage::BreedingRecord,
+};
+
+pub const MIN_GRADE: u8 = 1;
+pub const MAX_GRADE: u8 = 8;
+
+/// Tunable knobs used when deriving a foal from its parents.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InheritanceRules {
+    /// Probability of each part (skin, mane, tail) being copied from one of the parents
+    /// instead of being rolled at random.
+    pub part_inheritance_chance: f64,
+    /// Maximum deviation from the parents' average for every appearance value.
+    pub appearance_variation: u8,
+    /// Percentage of the parents' average stats passed down to the foal.
+    pub stat_inheritance_percent: u32,
+    /// Maximum random amount added on top of every inherited stat.
+    pub stat_variation: u32,
+    /// Probability of the foal being one grade above the parents' average.
+    pub grade_up_chance: f64,
+    /// Probability of the foal being one grade below the parents' average.
+    pub grade_down_chance: f64,
+    /// Coat bonus awarded when the foal inherits a coat shared by both parents.
+    pub coat_bonus: u8,
+    /// Probability of the foal having potential when none of its parents has it.
+    pub potential_chance: f64,
+    /// Probability of the foal having potential for each parent that has it.
+    pub inherited_potential_chance: f64,
+}
+impl Default for InheritanceRules {
+    fn default() -> Self {
+        InheritanceRules {
+            part_inheritance_chance: 0.8,
+            appearance_variation: 2,
+            stat_inheritance_percent: 50,
+            stat_variation: 5,
+            grade_up_chance: 0.1,
+            grade_down_chance: 0.2,
+            coat_bonus: 1,
+            potential_chance: 0.05,
+            inherited_potential_chance: 0.25,
+        }
+    }
+}
+
+/// Requirements a horse has to meet to be taken to the breeding market.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EligibilityRules {
+    pub min_grade: u8,
+    /// Minimum number of days since the horse was born.
+    pub min_age_days: u32,
+    /// Maximum number of foals a horse can parent.
+    pub max_breeding_count: u32,
+}
+impl Default for EligibilityRules {
+    fn default() -> Self {
+        EligibilityRules {
+            min_grade: 4,
+            min_age_days: 1,
+            max_breeding_count: 10,
+        }
+    }
+}
+
+/// Checks whether `horse` can be bred, returning the reason why it can't otherwise.
+pub fn check_eligibility(
+    horse: &Horse,
+    record: &BreedingRecord,
+    mount_uid: u32,
+    rules: &EligibilityRules,
+    now: &AliciaTime,
+) -> Result<(), String> {
+    if horse.uid == mount_uid {
+        return Err(format!("Horse {} is currently mounted", horse.uid));
+    }
+    if record.is_stallion {
+        return Err(format!("Horse {} is registered as a stallion", horse.uid));
+    }
+    check_fitness(horse, record, rules, now)
+}
+
+/// Same as [check_eligibility], for the stallion `horse` is bred with.
+pub fn check_sire_eligibility(
+    horse: &Horse,
+    record: &BreedingRecord,
+    rules: &EligibilityRules,
+    now: &AliciaTime,
+) -> Result<(), String> {
+    if !record.is_stallion {
+        return Err(format!(
+            "Horse {} is not registered as a stallion",
+            horse.uid
+        ));
+    }
+    check_fitness(horse, record, rules, now)
+}
+
+/// Requirements shared by both parents.
+fn check_fitness(
+    horse: &Horse,
+    record: &BreedingRecord,
+    rules: &EligibilityRules,
+    now: &AliciaTime,
+) -> Result<(), String> {
+    if horse.grade < rules.min_grade {
+        return Err(format!("Horse {} has grade {}", horse.uid, horse.grade));
+    }
+    let age_days = now.days_since(AliciaTime::from(horse.vals1.date_of_birth));
+    if age_days < rules.min_age_days as i64 {
+        return Err(format!("Horse {} is {} days old", horse.uid, age_days));
+    }
+    if record.breeding_count >= rules.max_breeding_count {
+        return Err(format!(
+            "Horse {} has already been bred {} times",
+            horse.uid, record.breeding_count
+        ));
+    }
+    Ok(())
+}
+
+/// Reward printed on one of the cards offered after a failed breeding attempt.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FailureReward {
+    Carrots(u32),
+    Item { tid: u32, count: u32 },
+}
+
+/// Tunable knobs for failed breeding attempts and the cards offered as compensation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FailureCardRules {
+    /// Probability of a breeding attempt yielding no foal.
+    pub failure_chance: f64,
+    /// Number of cards the player gets to choose from.
+    pub card_count: usize,
+    /// Pool the cards are drawn from. Rewards may show up more than once.
+    pub rewards: Vec<FailureReward>,
+}
+impl Default for FailureCardRules {
+    fn default() -> Self {
+        FailureCardRules {
+            failure_chance: 0.1,
+            card_count: 3,
+            rewards: vec![
+                FailureReward::Carrots(100),
+                FailureReward::Carrots(300),
+                FailureReward::Carrots(500),
+                FailureReward::Carrots(1000),
+            ],
+        }
+    }
+}
+
+/// Rolls the cards offered to a player whose breeding attempt failed.
+pub fn draw_failure_cards<R: Rng + ?Sized>(
+    rules: &FailureCardRules,
+    rng: &mut R,
+) -> Vec<FailureReward> {
+    (0..rules.card_count)
+        .filter_map(|_| rules.rewards.choose(rng).cloned())
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Parentage {
+    pub sire_uid: u32,
+    pub dam_uid: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Foal {
+    pub horse: Horse,
+    pub coat_bonus: u8,
+    pub parentage: Parentage,
+}
+
+/// Derives a foal from a mare (`dam`) and a stallion (`sire`).
+/// All the randomness comes from `rng`, so a seeded generator always yields the same foal.
+pub fn breed<R: Rng + ?Sized>(
+    dam: &Horse,
+    sire: &Horse,
+    rules: &InheritanceRules,
+    rng: &mut R,
+) -> Foal {
+    let parts = inherit_parts(&dam.parts, &sire.parts, rules, rng);
+    let coat_bonus =
+        if dam.parts.skin_id == sire.parts.skin_id && parts.skin_id == dam.parts.skin_id {
+            rules.coat_bonus
+        } else {
+            0
+        };
+
+    let mut horse = foal_template();
+    horse.parts = parts;
+    horse.appearance = inherit_appearance(&dam.appearance, &sire.appearance, rules, rng);
+    horse.stats = inherit_stats(&dam.stats, &sire.stats, rules, rng);
+    horse.grade = inherit_grade(dam.grade, sire.grade, rules, rng);
+
+    let parents_with_potential = [dam, sire]
+        .iter()
+        .filter(|h| h.vals1.has_potential != 0)
+        .count() as i32;
+    let potential_chance = if parents_with_potential == 0 {
+        rules.potential_chance
+    } else {
+        1.0 - (1.0 - rules.inherited_potential_chance).powi(parents_with_potential)
+    };
+    if rng.random_bool(potential_chance.clamp(0.0, 1.0)) {
+        horse.vals1.has_potential = 1;
+        horse.vals1.potential_level = 0;
+        horse.vals1.potential_value = rng.random_range(0..=5);
+    }
+
+    Foal {
+        horse,
+        coat_bonus,
+        parentage: Parentage {
+            sire_uid: sire.uid,
+            dam_uid: dam.uid,
+        },
+    }
+}
+
+fn inherit_parts<R: Rng + ?Sized>(
+    dam: &Parts,
+    sire: &Parts,
+    rules: &InheritanceRules,
+    rng: &mut R,
+) -> Parts {
+    let random = Parts::random_with(rng);
+    let chance = rules.part_inheritance_chance.clamp(0.0, 1.0);
+    let mut pick = |dam_value: u8, sire_value: u8, random_value: u8| {
+        if rng.random_bool(chance) {
+            if rng.random_bool(0.5) {
+                dam_value
+            } else {
+                sire_value
+            }
+        } else {
+            random_value
+        }
+    };
+    Parts {
+        skin_id: pick(dam.skin_id, sire.skin_id, random.skin_id),
+        mane_id: pick(dam.mane_id, sire.mane_id, random.mane_id),
+        tail_id: pick(dam.tail_id, sire.tail_id, random.tail_id),
+        face_id: pick(dam.face_id, sire.face_id, random.face_id),
+    }
+}
+
+fn inherit_appearance<R: Rng + ?Sized>(
+    dam: &Appearance,
+    sire: &Appearance,
+    rules: &InheritanceRules,
+    rng: &mut R,
+) -> Appearance {
+    let variation = rules.appearance_variation as i16;
+    let mut mix = |dam_value: u8, sire_value: u8| {
+        let average = (dam_value as i16 + sire_value as i16) / 2;
+        let offset = rng.random_range(-variation..=variation);
+        (average + offset).clamp(0, u8::MAX as i16) as u8
+    };
+    Appearance {
+        scale: mix(dam.scale, sire.scale),
+        leg_length: mix(dam.leg_length, sire.leg_length),
+        leg_volume: mix(dam.leg_volume, sire.leg_volume),
+        body_length: mix(dam.body_length, sire.body_length),
+        body_volume: mix(dam.body_volume, sire.body_volume),
+    }
+}
+
+fn inherit_stats<R: Rng + ?Sized>(
+    dam: &Stats,
+    sire: &Stats,
+    rules: &InheritanceRules,
+    rng: &mut R,
+) -> Stats {
+    let mut mix = |dam_value: u32, sire_value: u32| {
+        let average = (dam_value as u64 + sire_value as u64) / 2;
+        let inherited = average * rules.stat_inheritance_percent as u64 / 100;
+        let bonus = rng.random_range(0..=rules.stat_variation as u64);
+        (inherited + bonus).min(u32::MAX as u64) as u32
+    };
+    Stats {
+        agility: mix(dam.agility, sire.agility),
+        control: mix(dam.control, sire.control),
+        speed: mix(dam.speed, sire.speed),
+        strength: mix(dam.strength, sire.strength),
+        spirit: mix(dam.spirit, sire.spirit),
+    }
+}
+
+fn inherit_grade<R: Rng + ?Sized>(
+    dam_grade: u8,
+    sire_grade: u8,
+    rules: &InheritanceRules,
+    rng: &mut R,
+) -> u8 {
+    let average = (dam_grade as u16 + sire_grade as u16).div_ceil(2) as u8;
+    let roll: f64 = rng.random();
+    let grade = if roll < rules.grade_up_chance {
+        average.saturating_add(1)
+    } else if roll < rules.grade_up_chance + rules.grade_down_chance {
+        average.saturating_sub(1)
+    } else {
+        average
+    };
+    grade.clamp(MIN_GRADE, MAX_GRADE)
+}
+
+/// Values every newborn foal starts with, before applying anything inherited from its parents.
+fn foal_template() -> Horse {
+    Horse {
+        uid: 0, // Will be set by the database
+        tid: 20001,
+        name: c"".to_owned(), // Will be set in the update mount nickname handler
+        parts: Parts::default(),
+        appearance: Appearance::default(),
+        stats: Stats::default(),
+        rating: 0,
+        class: 21,
+        class_progress: 1,
+        grade: 5,
+        growth_points: 0,
+        vals0: Vals0 {
+            stamina: 65535,
+            attractiveness: 65535,
+            hunger: 65535,
+            val0: 0,
+            val1: 1000,
+            val2: 0,
+            val3: 0,
+            val4: 0,
+            val5: 1000,
+            val6: 30,
+            val7: 10,
+            val8: 10,
+            val9: 10,
+            val10: 0,
+        },
+        vals1: Vals1 {
+            val0: 0,
+            val1: 0,
+            date_of_birth: 3097585636,
+            val3: 2,
+            val4: 0,
+            class_progression: 255,
+            val5: 0,
+            potential_level: 0,
+            has_potential: 0,
+            potential_value: 255,
+            val9: 0,
+            luck: 4,
+            has_luck: 0,
+            val12: 0,
+            fatigue: 0,
+            val14: 0,
+            emblem: 1,
+        },
+        mastery: Mastery {
+            spur_magic_count: 510,
+            jump_count: 1057,
+            sliding_time: 1528,
+            gliding_distance: 53156,
+        },
+        val16: 3097585636,
+        val17: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    fn parent(uid: u32, skin_id: u8, grade: u8, speed: u32) -> Horse {
+        Horse {
+            uid,
+            parts: Parts {
+                skin_id,
+                mane_id: 3,
+                tail_id: 4,
+                face_id: 0,
+            },
+            grade,
+            stats: Stats {
+                speed,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_breeding_is_reproducible_with_seed() {
+        let dam = parent(1, 2, 4, 20);
+        let sire = parent(2, 7, 6, 40);
+        let rules = InheritanceRules::default();
+
+        let first = breed(&dam, &sire, &rules, &mut StdRng::seed_from_u64(1234));
+        let second = breed(&dam, &sire, &rules, &mut StdRng::seed_from_u64(1234));
+
+        assert_eq!(
+            first.parentage,
+            Parentage {
+                sire_uid: 2,
+                dam_uid: 1
+            }
+        );
+        assert_eq!(first.horse.parts.skin_id, second.horse.parts.skin_id);
+        assert_eq!(first.horse.parts.mane_id, second.horse.parts.mane_id);
+        assert_eq!(first.horse.parts.tail_id, second.horse.parts.tail_id);
+        assert_eq!(first.horse.stats.speed, second.horse.stats.speed);
+        assert_eq!(first.horse.grade, second.horse.grade);
+        assert_eq!(
+            first.horse.vals1.has_potential,
+            second.horse.vals1.has_potential
+        );
+    }
+
+    #[test]
+    fn test_breeding_follows_rules() {
+        let dam = parent(1, 9, 8, 20);
+        let sire = parent(2, 9, 8, 40);
+        let rules = InheritanceRules {
+            part_inheritance_chance: 1.0,
+            stat_variation: 0,
+            grade_up_chance: 1.0,
+            grade_down_chance: 0.0,
+            ..Default::default()
+        };
+
+        let foal = breed(&dam, &sire, &rules, &mut StdRng::seed_from_u64(42));
+
+        assert_eq!(foal.horse.parts.skin_id, 9);
+        assert_eq!(foal.horse.parts.mane_id, 3);
+        assert_eq!(foal.horse.parts.tail_id, 4);
+        assert_eq!(foal.coat_bonus, rules.coat_bonus);
+        assert_eq!(foal.horse.stats.speed, 15);
+        assert_eq!(foal.horse.grade, MAX_GRADE);
+    }
+
+    #[test]
+    fn test_both_parents_are_checked() {
+        let rules = EligibilityRules::default();
+        let now = AliciaTime::now();
+        let record = |horse_uid, is_stallion| BreedingRecord {
+            horse_uid,
+            breeding_count: 0,
+            coat_bonus: 0,
+            is_stallion,
+        };
+
+        let dam = parent(1, 2, 4, 20);
+        let sire = parent(2, 7, 6, 40);
+        assert!(check_eligibility(&dam, &record(1, false), 0, &rules, &now).is_ok());
+        assert!(check_sire_eligibility(&sire, &record(2, true), &rules, &now).is_ok());
+        // Each parent has to be on its side of the market
+        assert!(check_eligibility(&dam, &record(1, true), 0, &rules, &now).is_err());
+        assert!(check_sire_eligibility(&sire, &record(2, false), &rules, &now).is_err());
+
+        let worn_out = BreedingRecord {
+            breeding_count: rules.max_breeding_count,
+            ..record(2, true)
+        };
+        assert!(check_sire_eligibility(&sire, &worn_out, &rules, &now).is_err());
+        let low_grade = parent(2, 7, rules.min_grade - 1, 40);
+        assert!(check_sire_eligibility(&low_grade, &record(2, true), &rules, &now).is_err());
+    }
+}
@@ -13,22 +13,23 @@ pub mod ranch;
 
 pub trait PacketHandler {
     const COMMAND_ID: CommandId;
-    async fn handle_packet(
-        server: Arc<Mutex<Server>>,
+    // Spelled out instead of `async fn` so handlers can be run as regular tokio tasks
+    fn handle_packet(
+        server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         packet: &Packet,
-    ) -> Result<(), String>;
+    ) -> impl Future<Output = Result<(), String>> + Send;
 }
 
 // TODO: Log packet bytes
 
 pub trait CommandHandler {
     type CommandType: Command;
-    async fn handle_command(
-        server: Arc<Mutex<Server>>,
+    fn handle_command(
+        server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         command: &Self::CommandType,
-    ) -> Result<(), String>;
+    ) -> impl Future<Output = Result<(), String>> + Send;
 }
 
 #[macro_export]
@@ -38,7 +39,7 @@ macro_rules! impl_packet_handler {
         impl crate::handlers::PacketHandler for $t {
             const COMMAND_ID: crate::packet::CommandId = <Self as CommandHandler>::CommandType::ID;
             async fn handle_packet(
-                server: std::sync::Arc<tokio::sync::Mutex<crate::server::Server>>,
+                server: std::sync::Arc<crate::server::Server>,
                 session: std::sync::Arc<tokio::sync::Mutex<crate::server::Session>>,
                 packet: &crate::packet::Packet,
             ) -> Result<(), String> {
@@ -1,7 +1,10 @@
 pub mod achievement_complete_list;
+pub mod change_ranch_option;
 pub mod create_nickname;
 pub mod enter_ranch;
+pub mod enter_ranch_randomly;
 pub mod get_messenger_info;
+pub mod heartbeat;
 pub mod login;
 pub mod request_daily_quest_list;
 pub mod request_league_info;
@@ -16,7 +16,7 @@ pub struct AchievementCompleteListHandler {}
 impl CommandHandler for AchievementCompleteListHandler {
     type CommandType = AchievementCompleteList;
     async fn handle_command(
-        _server: Arc<Mutex<Server>>,
+        _server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         command: &Self::CommandType,
     ) -> Result<(), String> {
@@ -0,0 +1,68 @@
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::{
+    commands::lobby::change_ranch_option::{
+        ChangeRanchOption, ChangeRanchOptionCancel, ChangeRanchOptionOk,
+    },
+    entities::ranch::RanchVisibility,
+    handlers::CommandHandler,
+    impl_packet_handler,
+    server::{Server, Session},
+};
+
+/// Highest visitor cap owners can set. Without a cap, ranches take any number of visitors.
+pub const MAX_RANCH_VISITORS: u8 = 20;
+
+pub struct ChangeRanchOptionHandler {}
+impl CommandHandler for ChangeRanchOptionHandler {
+    type CommandType = ChangeRanchOption;
+    async fn handle_command(
+        server: Arc<Server>,
+        session: Arc<Mutex<Session>>,
+        command: &Self::CommandType,
+    ) -> Result<(), String> {
+        let mut session = session.lock().await;
+        let character_id = session
+            .character
+            .as_ref()
+            .map(|c| c.character_id)
+            .ok_or("Character not found")?;
+
+        let database = Arc::clone(&server.database);
+        let result = database
+            .run_in_transaction(async |transaction| {
+                let visibility = RanchVisibility::try_from(command.visibility)?;
+                if command.max_visitors > MAX_RANCH_VISITORS {
+                    return Err(format!("Visitor cap {} is too high", command.max_visitors).into());
+                }
+                transaction
+                    .get_or_create_ranch_by_owner(character_id)
+                    .await?
+                    .ok_or(format!("Character {} has no ranch", character_id))?;
+                transaction
+                    .update_ranch_options(character_id, visibility, command.max_visitors)
+                    .await
+            })
+            .await;
+
+        match result {
+            Ok(()) => session
+                .send_command(ChangeRanchOptionOk {
+                    visibility: command.visibility,
+                    max_visitors: command.max_visitors,
+                })
+                .await
+                .map_err(|e| format!("Failed to send response: {:?}", e)),
+            Err(e) => {
+                session
+                    .send_command(ChangeRanchOptionCancel {})
+                    .await
+                    .map_err(|e| format!("Failed to send response: {:?}", e))?;
+                Err(format!("Failed to change ranch options: {}", e))
+            }
+        }
+    }
+}
+impl_packet_handler!(ChangeRanchOptionHandler);
@@ -7,8 +7,8 @@ use crate::{
         create_nickname::{CreateNickname, CreateNicknameCancel},
         show_inventory::ShowInventoryOk,
     }, shared::horse::{self, Horse, Mastery, Stats, Vals0, Vals1}, LengthPrefixedVec},
-    database::{character::{insert_character, update_character}, horse::insert_horse},
-    entities::character::Character,
+    database::character::DEFAULT_MOUNT_SLOTS,
+    entities::{character::Character, tracked::Tracked},
     handlers::CommandHandler,
     impl_packet_handler,
     server::{Server, Session},
@@ -18,12 +18,12 @@ pub struct CreateNicknameHandler {}
 impl CommandHandler for CreateNicknameHandler {
     type CommandType = CreateNickname;
     async fn handle_command(
-        server: Arc<Mutex<Server>>,
+        server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         command: &Self::CommandType,
     ) -> Result<(), String> {
         let server = Arc::clone(&server);
-        let database = Arc::clone(&server.lock().await.database);
+        let database = Arc::clone(&server.database);
         let mut session = session.lock().await;
 
         let account = session
@@ -32,8 +32,6 @@ impl CommandHandler for CreateNicknameHandler {
             .ok_or("Attempted to create a character while not logged in")?;
 
         let result = database
-            .lock()
-            .await
             .run_in_transaction(async |transaction| {
                 let mut character = 
                     Character {
@@ -44,11 +42,11 @@ impl CommandHandler for CreateNicknameHandler {
                             .into_string()
                             .map_err(|e| format!("Failed to convert nickname to String: {}", e))?,
                         mount_uid: 0, // Will be set later when the horse is created
+                        mount_slots: DEFAULT_MOUNT_SLOTS,
                         character: command.character.clone(),
                         create_character_unk0: command.unk0,
                     };
-                insert_character(
-                    transaction,
+                transaction.insert_character(
                     account.member_no,
                     &mut character
                 )
@@ -111,10 +109,10 @@ impl CommandHandler for CreateNicknameHandler {
                     val16: 3097585636,
                     val17: 0,
                 };
-                insert_horse(transaction, character.character_id, &mut mount).await?;
+                transaction.insert_horse(character.character_id, &mut mount).await?;
 
                 character.mount_uid = mount.uid;
-                update_character(transaction, &character).await?;
+                transaction.update_character(&character).await?;
 
                 Ok((mount, character))
             })
@@ -122,8 +120,8 @@ impl CommandHandler for CreateNicknameHandler {
 
         match result {
             Ok((mount, character)) => {
-                session.character = Some(character);
-                session.horses = Some(vec![mount.clone()]);
+                session.character = Some(Tracked::stored(character));
+                session.horses = Some(vec![Tracked::stored(mount.clone())]);
                 session
                     .send_command(ShowInventoryOk {
                         horses: LengthPrefixedVec {
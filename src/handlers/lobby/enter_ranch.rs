@@ -3,7 +3,10 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 
 use crate::{
-    commands::lobby::enter_ranch::{EnterRanch, EnterRanchOk},
+    commands::{
+        lobby::enter_ranch::{EnterRanch, EnterRanchCancel, EnterRanchOk},
+        shared::address::Address,
+    },
     handlers::CommandHandler,
     impl_packet_handler,
     server::{Server, Session},
@@ -13,7 +16,7 @@ pub struct EnterRanchHandler {}
 impl CommandHandler for EnterRanchHandler {
     type CommandType = EnterRanch;
     async fn handle_command(
-        server: Arc<Mutex<Server>>,
+        server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         command: &Self::CommandType,
     ) -> Result<(), String> {
@@ -23,32 +26,139 @@ impl CommandHandler for EnterRanchHandler {
             .as_ref()
             .map(|c| c.character_id)
             .ok_or("Character not found")?;
-        if command.character_id != character_id {
-            return Err(format!(
-                "Character ID mismatch: expected {}, got {}",
-                character_id, command.character_id
-            ));
-        }
 
-        // TODO: Fetch ranch from the server
-
-        let ranch_address = server
-            .lock()
+        // The requested character is the owner of the ranch to visit, which is the
+        // player's own character when going home
+        let (database, ranch_address) = (
+            Arc::clone(&server.database),
+            server.settings.ranch_server.announce_address.clone(),
+        );
+        let code = rand::random();
+        let ranch = database
+            .run_in_transaction(async |transaction| {
+                let Some(ranch) = transaction
+                    .get_or_create_ranch_by_owner(command.character_id)
+                    .await?
+                else {
+                    return Ok(Err(format!(
+                        "Character {} doesn't exist and has no ranch",
+                        command.character_id
+                    )));
+                };
+                // The ranch server checks again, the ranch may have filled up in the meantime
+                if let Err(e) = ranch.admits(character_id, ranch.visitor_count as usize) {
+                    return Ok(Err(format!("Can't enter ranch {}: {}", ranch.ranch_id, e)));
+                }
+                transaction
+                    .issue_ranch_entry_code(
+                        character_id,
+                        ranch.ranch_id,
+                        code,
+                        RANCH_ENTRY_CODE_VALID_SECS,
+                    )
+                    .await?;
+                Ok(Ok(ranch))
+            })
             .await
-            .settings
-            .ranch_server
-            .announce_address
-            .clone();
-
-        let response = EnterRanchOk {
-            ranch_uid: 1234, // TODO: Send the ranch uid for the player
-            code: 0,         // This should probably be the packet scrambler code. TODO: Change
-            address: ranch_address,
+            .map_err(|e| format!("Failed to fetch ranch: {}", e))?;
+        let ranch = match ranch {
+            Ok(ranch) => ranch,
+            Err(e) => {
+                session
+                    .send_command(EnterRanchCancel::default())
+                    .await
+                    .map_err(|e| format!("Failed to send response: {:?}", e))?;
+                return Err(e);
+            }
         };
+
         session
-            .send_command(response)
+            .send_command(enter_ranch_ok(ranch.ranch_id, code, ranch_address))
             .await
             .map_err(|e| format!("Failed to send response: {:?}", e))
     }
 }
 impl_packet_handler!(EnterRanchHandler);
+
+/// Seconds the client has to present its entry code to the ranch server
+pub const RANCH_ENTRY_CODE_VALID_SECS: u64 = 60;
+
+/// Hands the client over to the ranch server, which will be asked to enter `ranch_id` with
+/// the entry `code` issued for it.
+pub fn enter_ranch_ok(ranch_id: u32, code: u32, ranch_address: Address) -> EnterRanchOk {
+    EnterRanchOk {
+        ranch_uid: ranch_id,
+        code,
+        address: ranch_address,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::shared::horse::Horse,
+        entities::{character::Character, ranch::RanchVisibility},
+        server::{
+            ServerType,
+            testing::{create_character, log_in, received_packets, test_server, test_session},
+        },
+    };
+
+    #[tokio::test]
+    async fn test_ranches_that_turn_visitors_away_get_no_code() {
+        let server = test_server(ServerType::Lobby).await;
+        let (owner, owner_mount) = create_character(&server.database, "Owner").await;
+        let (visitor, visitor_mount) = create_character(&server.database, "Visitor").await;
+        let ranch = server
+            .database
+            .run_in_transaction(async |transaction| {
+                let ranch = transaction
+                    .get_or_create_ranch_by_owner(owner.character_id)
+                    .await?
+                    .unwrap();
+                transaction
+                    .update_ranch_options(owner.character_id, RanchVisibility::Public, 1)
+                    .await?;
+                transaction.update_visitor_count(ranch.ranch_id, 1).await?;
+                Ok(ranch)
+            })
+            .await
+            .unwrap();
+        let enter = async |character: &Character, mount: &Horse| {
+            let (session, client) = test_session().await;
+            log_in(&session, character, std::slice::from_ref(mount)).await;
+            let result = EnterRanchHandler::handle_command(
+                Arc::clone(&server),
+                session,
+                &EnterRanch {
+                    character_id: owner.character_id,
+                    ..Default::default()
+                },
+            )
+            .await;
+            let (mut client, _) = client.into_split();
+            let packets = received_packets(&mut client).await;
+            assert_eq!(packets.len(), 1);
+            match result {
+                Ok(()) => Ok(EnterRanchOk::try_from(&packets[0]).unwrap()),
+                Err(_) => Err(EnterRanchCancel::try_from(&packets[0]).unwrap()),
+            }
+        };
+
+        // The ranch is full, but owners always get into their own
+        assert!(enter(&visitor, &visitor_mount).await.is_err());
+        let response = enter(&owner, &owner_mount).await.unwrap();
+        assert_eq!(response.ranch_uid, ranch.ranch_id);
+        let redeemed = server
+            .database
+            .run_in_transaction(async |transaction| {
+                transaction
+                    .redeem_ranch_entry_code(owner.character_id, response.code)
+                    .await
+            })
+            .await
+            .unwrap();
+        assert_eq!(redeemed, Some(ranch.ranch_id));
+    }
+}
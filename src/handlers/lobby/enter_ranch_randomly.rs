@@ -0,0 +1,66 @@
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::{
+    commands::lobby::enter_ranch::{EnterRanchCancel, EnterRanchRandomly},
+    handlers::{
+        CommandHandler,
+        lobby::enter_ranch::{RANCH_ENTRY_CODE_VALID_SECS, enter_ranch_ok},
+    },
+    impl_packet_handler,
+    server::{Server, Session},
+};
+
+pub struct EnterRanchRandomlyHandler {}
+impl CommandHandler for EnterRanchRandomlyHandler {
+    type CommandType = EnterRanchRandomly;
+    async fn handle_command(
+        server: Arc<Server>,
+        session: Arc<Mutex<Session>>,
+        _command: &Self::CommandType,
+    ) -> Result<(), String> {
+        let mut session = session.lock().await;
+        let character_id = session
+            .character
+            .as_ref()
+            .map(|c| c.character_id)
+            .ok_or("Character not found")?;
+
+        let (database, ranch_address) = (
+            Arc::clone(&server.database),
+            server.settings.ranch_server.announce_address.clone(),
+        );
+        let code = rand::random();
+        let ranch = database
+            .run_in_transaction(async |transaction| {
+                let ranch = transaction.get_random_ranch(character_id).await?;
+                if let Some(ranch) = &ranch {
+                    transaction
+                        .issue_ranch_entry_code(
+                            character_id,
+                            ranch.ranch_id,
+                            code,
+                            RANCH_ENTRY_CODE_VALID_SECS,
+                        )
+                        .await?;
+                }
+                Ok(ranch)
+            })
+            .await
+            .map_err(|e| format!("Failed to fetch a random ranch: {}", e))?;
+        let Some(ranch) = ranch else {
+            session
+                .send_command(EnterRanchCancel::default())
+                .await
+                .map_err(|e| format!("Failed to send response: {:?}", e))?;
+            return Err("No ranch available to visit".to_owned());
+        };
+
+        session
+            .send_command(enter_ranch_ok(ranch.ranch_id, code, ranch_address))
+            .await
+            .map_err(|e| format!("Failed to send response: {:?}", e))
+    }
+}
+impl_packet_handler!(EnterRanchRandomlyHandler);
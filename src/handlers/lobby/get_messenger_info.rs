@@ -13,17 +13,11 @@ pub struct GetMessengerInfoHandler {}
 impl CommandHandler for GetMessengerInfoHandler {
     type CommandType = GetMessengerInfo;
     async fn handle_command(
-        server: Arc<Mutex<Server>>,
+        server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         _command: &Self::CommandType,
     ) -> Result<(), String> {
-        let messenger_address = server
-            .lock()
-            .await
-            .settings
-            .messenger_server
-            .announce_address
-            .clone();
+        let messenger_address = server.settings.messenger_server.announce_address.clone();
 
         let response = GetMessengerInfoOk {
             code: 0, // This is likely for the packet scrambler. TODO: Use
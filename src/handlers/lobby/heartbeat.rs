@@ -0,0 +1,25 @@
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::{
+    commands::lobby::heartbeat::Heartbeat,
+    handlers::CommandHandler,
+    impl_packet_handler,
+    server::{Server, Session},
+};
+
+pub struct HeartbeatHandler {}
+impl CommandHandler for HeartbeatHandler {
+    type CommandType = Heartbeat;
+    async fn handle_command(
+        _server: Arc<Server>,
+        _session: Arc<Mutex<Session>>,
+        _command: &Self::CommandType,
+    ) -> Result<(), String> {
+        // There's no response, the client only lets us know it's still there. Like any other
+        // packet, it already counted as a sign of life when it arrived
+        Ok(())
+    }
+}
+impl_packet_handler!(HeartbeatHandler);
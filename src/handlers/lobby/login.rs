@@ -21,12 +21,7 @@ use crate::{
             win_file_time::WinFileTime,
         },
     },
-    database::{
-        account::{add_account, get_account},
-        character::get_character_by_member_no,
-        horse::{get_horse_by_uid, get_horses_by_character_id},
-    },
-    entities::account::Account,
+    entities::{account::Account, tracked::Tracked},
     handlers::CommandHandler,
     impl_packet_handler,
     server::{Server, Session},
@@ -36,7 +31,7 @@ pub struct LoginHandler {}
 impl CommandHandler for LoginHandler {
     type CommandType = Login;
     async fn handle_command(
-        server: Arc<Mutex<Server>>,
+        server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         command: &Self::CommandType,
     ) -> Result<(), String> {
@@ -52,13 +47,11 @@ impl CommandHandler for LoginHandler {
             .to_owned();
 
         let server = Arc::clone(&server);
-        let database = Arc::clone(&server.lock().await.database);
+        let database = Arc::clone(&server.database);
 
-        let account: Result<Account, Box<dyn Error>> = database
-            .lock()
-            .await
+        let account: Result<Account, Box<dyn Error + Send + Sync>> = database
             .run_in_transaction(async |transaction| {
-                let candidate_account = get_account(transaction, command.member_no).await?;
+                let candidate_account = transaction.get_account(command.member_no).await?;
                 if let Some(candidate_account) = candidate_account {
                     if candidate_account.auth_key == auth_key
                         && candidate_account.login_id == login_id
@@ -73,7 +66,8 @@ impl CommandHandler for LoginHandler {
                         login_id: login_id.clone(),
                         auth_key: auth_key.clone(),
                     };
-                    add_account(transaction, &mut new_account)
+                    transaction
+                        .add_account(&mut new_account)
                         .await
                         .map_err(|err| {
                             format!("Failed to insert account in the database:\n\t{}", err)
@@ -109,17 +103,26 @@ impl CommandHandler for LoginHandler {
             }
         }
 
-        let (character, horses) = database
-            .lock()
-            .await
+        let (character, horses, wallet) = database
             .run_in_transaction(async |transaction| {
-                let character = get_character_by_member_no(transaction, command.member_no).await?;
-                let horses = if let Some(character) = character.as_ref() {
-                    get_horses_by_character_id(transaction, character.character_id).await?
+                let character = transaction
+                    .get_character_by_member_no(command.member_no)
+                    .await?;
+                let (horses, wallet) = if let Some(character) = character.as_ref() {
+                    (
+                        transaction
+                            .get_horses_by_character_id(character.character_id)
+                            .await?,
+                        Some(
+                            transaction
+                                .get_wallet_for_update(character.character_id)
+                                .await?,
+                        ),
+                    )
                 } else {
-                    vec![]
+                    (vec![], None)
                 };
-                Ok((character, horses))
+                Ok((character, horses, wallet))
             })
             .await
             .map_err(|e| format!("Failed to fetch character: {}", e))?;
@@ -132,13 +135,7 @@ impl CommandHandler for LoginHandler {
         let xor_key = rand::random();
         session.scrambler.xor_key = xor_key;
 
-        let lobby_address = server
-            .lock()
-            .await
-            .settings
-            .lobby_server
-            .announce_address
-            .clone();
+        let lobby_address = server.settings.lobby_server.announce_address.clone();
 
         session
             .send_command(LoginOk {
@@ -180,7 +177,7 @@ impl CommandHandler for LoginHandler {
                     }],
                 },
                 level: 161,
-                carrots: 255,
+                carrots: wallet.map(|w| w.carrots).unwrap_or_default(),
                 val1: 24880,
                 val2: 255,
                 val3: 255,
@@ -397,8 +394,8 @@ impl CommandHandler for LoginHandler {
                 .map_err(|e| format!("Failed to send create nickname notify: {}", e))?;
         }
 
-        session.character = character;
-        session.horses = Some(horses);
+        session.character = character.map(Tracked::stored);
+        session.horses = Some(horses.into_iter().map(Tracked::stored).collect());
 
         Ok(())
     }
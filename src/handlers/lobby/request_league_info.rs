@@ -14,7 +14,7 @@ pub struct RequestLeagueInfoHandler {}
 impl CommandHandler for RequestLeagueInfoHandler {
     type CommandType = RequestLeagueInfo;
     async fn handle_command(
-        _server: Arc<Mutex<Server>>,
+        _server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         _command: &Self::CommandType,
     ) -> Result<(), String> {
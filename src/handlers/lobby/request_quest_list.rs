@@ -14,7 +14,7 @@ pub struct RequestQuestListHandler {}
 impl CommandHandler for RequestQuestListHandler {
     type CommandType = RequestQuestList;
     async fn handle_command(
-        _server: Arc<Mutex<Server>>,
+        _server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         command: &Self::CommandType,
     ) -> Result<(), String> {
@@ -15,7 +15,7 @@ pub struct RequestSpecialEventListHandler {}
 impl CommandHandler for RequestSpecialEventListHandler {
     type CommandType = RequestSpecialEventList;
     async fn handle_command(
-        _server: Arc<Mutex<Server>>,
+        _server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         _command: &Self::CommandType,
     ) -> Result<(), String> {
@@ -4,7 +4,10 @@ use deku::DekuContainerRead;
 use tokio::sync::Mutex;
 
 use crate::{
-    commands::lobby::show_inventory::{ShowInventory, ShowInventoryOk},
+    commands::{
+        lobby::show_inventory::{ShowInventory, ShowInventoryOk},
+        shared::horse::Horse,
+    },
     handlers::CommandHandler,
     impl_packet_handler,
     server::{Server, Session},
@@ -14,7 +17,7 @@ pub struct ShowInventoryHandler {}
 impl CommandHandler for ShowInventoryHandler {
     type CommandType = ShowInventory;
     async fn handle_command(
-        _server: Arc<Mutex<Server>>,
+        _server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         _command: &Self::CommandType,
     ) -> Result<(), String> {
@@ -65,7 +68,9 @@ impl CommandHandler for ShowInventoryHandler {
             .horses
             .as_ref()
             .ok_or("Player has no horses")?
-            .clone();
+            .iter()
+            .map(|horse| Horse::clone(horse))
+            .collect();
 
         session
             .send_command(pcap)
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::{
+    commands::ranch::breeding_abandon::{
+        BreedingAbandon, BreedingAbandonCancel, BreedingAbandonOk,
+    },
+    handlers::CommandHandler,
+    impl_packet_handler,
+    server::{Server, Session},
+};
+
+pub struct BreedingAbandonHandler {}
+impl CommandHandler for BreedingAbandonHandler {
+    type CommandType = BreedingAbandon;
+    async fn handle_command(
+        server: Arc<Server>,
+        session: Arc<Mutex<Session>>,
+        command: &Self::CommandType,
+    ) -> Result<(), String> {
+        let mut session = session.lock().await;
+        // Only the foal that was just bred can be abandoned, any other horse is there to stay
+        if session.pending_foal_uid != Some(command.horse_uid) {
+            session
+                .send_command(BreedingAbandonCancel {})
+                .await
+                .map_err(|e| format!("Failed to send response: {:?}", e))?;
+            return Err(format!("Horse {} is not a pending foal", command.horse_uid));
+        }
+
+        let database = Arc::clone(&server.database);
+        let result = database
+            .run_in_transaction(async |transaction| {
+                transaction.remove_horse(command.horse_uid).await
+            })
+            .await;
+        if let Err(e) = result {
+            session
+                .send_command(BreedingAbandonCancel {})
+                .await
+                .map_err(|e| format!("Failed to send response: {:?}", e))?;
+            return Err(format!("Failed to abandon foal: {}", e));
+        }
+
+        session.pending_foal_uid = None;
+        if let Some(horses) = session.horses.as_mut() {
+            horses.retain(|h| h.uid != command.horse_uid);
+        }
+        session
+            .send_command(BreedingAbandonOk {})
+            .await
+            .map_err(|e| format!("Failed to send response: {:?}", e))
+    }
+}
+impl_packet_handler!(BreedingAbandonHandler);
@@ -3,7 +3,9 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 
 use crate::{
-    commands::ranch::breeding_failure_card::{BreedingFailureCard, BreedingFailureCardCancel},
+    commands::ranch::breeding_failure_card::{
+        BreedingFailureCard, BreedingFailureCardCancel, BreedingFailureCardOk,
+    },
     handlers::CommandHandler,
     impl_packet_handler,
     server::{Server, Session},
@@ -13,15 +15,40 @@ pub struct BreedingFailureCardHandler {}
 impl CommandHandler for BreedingFailureCardHandler {
     type CommandType = BreedingFailureCard;
     async fn handle_command(
-        _server: Arc<Mutex<Server>>,
+        server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         _command: &Self::CommandType,
     ) -> Result<(), String> {
-        // TODO: The thing
-        let response = BreedingFailureCardCancel {};
-        session
+        let character_id = session
             .lock()
             .await
+            .character
+            .as_ref()
+            .ok_or("Player has no character")?
+            .character_id;
+        let database = Arc::clone(&server.database);
+        let cards = database
+            .run_in_transaction(async |transaction| {
+                transaction.get_failure_cards(character_id).await
+            })
+            .await
+            .map_err(|e| format!("Failed to fetch failure cards: {}", e))?;
+
+        let mut session = session.lock().await;
+        // Cards are only rolled by a failed breeding attempt
+        if cards.is_empty() {
+            session
+                .send_command(BreedingFailureCardCancel {})
+                .await
+                .map_err(|e| format!("Failed to send response: {:?}", e))?;
+            return Err("No failure cards to choose from".to_owned());
+        }
+
+        // TODO: unk0 guessed to be the number of cards to show
+        let response = BreedingFailureCardOk {
+            unk0: cards.len() as u8,
+        };
+        session
             .send_command(response)
             .await
             .map_err(|e| format!("Failed to send response: {:?}", e))
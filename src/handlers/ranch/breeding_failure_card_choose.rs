@@ -0,0 +1,156 @@
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::{
+    commands::ranch::breeding_failure_card_choose::{
+        BreedingFailureCardChoose, BreedingFailureCardChooseCancel, BreedingFailureCardChooseOk,
+    },
+    genetics::FailureReward,
+    handlers::CommandHandler,
+    impl_packet_handler,
+    server::{Server, Session},
+};
+
+pub struct BreedingFailureCardChooseHandler {}
+impl CommandHandler for BreedingFailureCardChooseHandler {
+    type CommandType = BreedingFailureCardChoose;
+    async fn handle_command(
+        server: Arc<Server>,
+        session: Arc<Mutex<Session>>,
+        command: &Self::CommandType,
+    ) -> Result<(), String> {
+        let character_id = session
+            .lock()
+            .await
+            .character
+            .as_ref()
+            .ok_or("Player has no character")?
+            .character_id;
+
+        // Cards are used up by the first choice, so the same reward can't be claimed twice.
+        // They stay if the reward can't be granted
+        let database = Arc::clone(&server.database);
+        let result = database
+            .run_in_transaction(async |transaction| {
+                let cards = transaction.take_failure_cards(character_id).await?;
+                let reward = cards
+                    .get(command.position as usize)
+                    .cloned()
+                    .ok_or(format!(
+                        "No failure card to choose at position {}",
+                        command.position
+                    ))?;
+                let mut wallet = transaction.get_wallet_for_update(character_id).await?;
+                match reward {
+                    FailureReward::Carrots(carrots) => {
+                        wallet.carrots = wallet
+                            .carrots
+                            .checked_add(carrots)
+                            .ok_or("Carrot count overflow")?;
+                        transaction.update_wallet(&wallet).await?;
+                    }
+                    FailureReward::Item { tid, count } => {
+                        transaction.add_item(character_id, tid, count).await?;
+                    }
+                }
+                Ok((reward, wallet))
+            })
+            .await;
+
+        let mut session = session.lock().await;
+        match result {
+            Ok((reward, wallet)) => {
+                let (item_tid, item_count) = match reward {
+                    FailureReward::Carrots(_) => (0, 0),
+                    FailureReward::Item { tid, count } => (tid, count),
+                };
+                session
+                    .send_command(BreedingFailureCardChooseOk {
+                        position: command.position,
+                        item_tid,
+                        item_count,
+                        updated_carrot_count: wallet.carrots,
+                    })
+                    .await
+                    .map_err(|e| format!("Failed to send response: {:?}", e))
+            }
+            Err(e) => {
+                session
+                    .send_command(BreedingFailureCardChooseCancel {})
+                    .await
+                    .map_err(|e| format!("Failed to send response: {:?}", e))?;
+                Err(format!("Failed to grant failure card reward: {}", e))
+            }
+        }
+    }
+}
+impl_packet_handler!(BreedingFailureCardChooseHandler);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::wallet::INITIAL_CARROTS,
+        server::{
+            ServerType,
+            testing::{create_character, log_in, test_server, test_session},
+        },
+    };
+
+    #[tokio::test]
+    async fn test_invalid_position_keeps_cards() {
+        let server = test_server(ServerType::Ranch).await;
+        let (session, _client) = test_session().await;
+        let (character, horse) = create_character(&server.database, "Breeder").await;
+        log_in(&session, &character, &[horse]).await;
+        let cards = vec![
+            FailureReward::Carrots(100),
+            FailureReward::Item { tid: 1, count: 1 },
+        ];
+        let get_cards = async || {
+            server
+                .database
+                .run_in_transaction(async |transaction| {
+                    transaction.get_failure_cards(character.character_id).await
+                })
+                .await
+                .unwrap()
+        };
+        server
+            .database
+            .run_in_transaction(async |transaction| {
+                transaction
+                    .set_failure_cards(character.character_id, &cards)
+                    .await
+            })
+            .await
+            .unwrap();
+
+        let choose = async |position| {
+            BreedingFailureCardChooseHandler::handle_command(
+                Arc::clone(&server),
+                Arc::clone(&session),
+                &BreedingFailureCardChoose { position },
+            )
+            .await
+        };
+        assert!(choose(2).await.is_err());
+        assert_eq!(get_cards().await, cards);
+
+        choose(0).await.unwrap();
+        assert!(get_cards().await.is_empty());
+        // Nor can another card be picked afterwards
+        assert!(choose(1).await.is_err());
+        let wallet = server
+            .database
+            .run_in_transaction(async |transaction| {
+                transaction
+                    .get_wallet_for_update(character.character_id)
+                    .await
+            })
+            .await
+            .unwrap();
+        assert_eq!(wallet.carrots, INITIAL_CARROTS + 100);
+    }
+}
@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::{
+    commands::ranch::breeding_take_money::{
+        BreedingTakeMoney, BreedingTakeMoneyCancel, BreedingTakeMoneyOk,
+    },
+    database::wallet::take_breeding_earnings,
+    handlers::CommandHandler,
+    impl_packet_handler,
+    server::{Server, Session},
+};
+
+pub struct BreedingTakeMoneyHandler {}
+impl CommandHandler for BreedingTakeMoneyHandler {
+    type CommandType = BreedingTakeMoney;
+    async fn handle_command(
+        server: Arc<Server>,
+        session: Arc<Mutex<Session>>,
+        _command: &Self::CommandType,
+    ) -> Result<(), String> {
+        let character_id = session
+            .lock()
+            .await
+            .character
+            .as_ref()
+            .ok_or("Player has no character")?
+            .character_id;
+
+        let database = Arc::clone(&server.database);
+        let result = database
+            .run_in_transaction(async |transaction| {
+                take_breeding_earnings(transaction, character_id).await
+            })
+            .await;
+
+        let mut session = session.lock().await;
+        match result {
+            Ok((collected, wallet)) => session
+                .send_command(BreedingTakeMoneyOk {
+                    collected_carrots: collected,
+                    updated_carrot_count: wallet.carrots,
+                })
+                .await
+                .map_err(|e| format!("Failed to send response: {:?}", e)),
+            Err(e) => {
+                session
+                    .send_command(BreedingTakeMoneyCancel {})
+                    .await
+                    .map_err(|e| format!("Failed to send response: {:?}", e))?;
+                Err(format!("Failed to collect breeding earnings: {}", e))
+            }
+        }
+    }
+}
+impl_packet_handler!(BreedingTakeMoneyHandler);
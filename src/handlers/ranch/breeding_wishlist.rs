@@ -1,9 +1,12 @@
-use std::sync::Arc;
+use std::{ffi::CString, sync::Arc};
 
 use tokio::sync::Mutex;
 
 use crate::{
-    commands::ranch::breeding_wishlist::{BreedingWishlist, BreedingWishlistOk},
+    commands::{
+        LengthPrefixedVec,
+        ranch::breeding_wishlist::{BreedingWishlist, BreedingWishlistOk, WishlistElement},
+    },
     handlers::CommandHandler,
     impl_packet_handler,
     server::{Server, Session},
@@ -13,12 +16,46 @@ pub struct BreedingWishlistHandler {}
 impl CommandHandler for BreedingWishlistHandler {
     type CommandType = BreedingWishlist;
     async fn handle_command(
-        _server: Arc<Mutex<Server>>,
+        server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         _command: &Self::CommandType,
     ) -> Result<(), String> {
-        // TODO: Implement
-        let response = BreedingWishlistOk::default();
+        let character_id = session
+            .lock()
+            .await
+            .character
+            .as_ref()
+            .ok_or("Player has no character")?
+            .character_id;
+
+        // Unregistered stallions are dropped from the wishlist by the database
+        let database = Arc::clone(&server.database);
+        let listings = database
+            .run_in_transaction(async |transaction| transaction.get_wishlist(character_id).await)
+            .await
+            .map_err(|e| format!("Failed to fetch wishlist: {}", e))?;
+
+        let mut wishlist = Vec::with_capacity(listings.len());
+        for listing in listings {
+            // TODO: Unknown fields guessed from the search stallion layout
+            wishlist.push(WishlistElement {
+                unk0: CString::new(listing.owner_nickname)
+                    .map_err(|e| format!("Failed to convert nickname to CString: {}", e))?,
+                uid: listing.horse.uid,
+                tid: listing.horse.tid,
+                unk2: listing.horse.name,
+                unk3: listing.horse.grade,
+                unk4: listing.stallion.price,
+                stats: listing.horse.stats,
+                parts: listing.horse.parts,
+                appearance: listing.horse.appearance,
+                ..Default::default()
+            });
+        }
+
+        let response = BreedingWishlistOk {
+            wishlist: LengthPrefixedVec { vec: wishlist },
+        };
         session
             .lock()
             .await
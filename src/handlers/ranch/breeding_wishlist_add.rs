@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::{
+    commands::ranch::breeding_wishlist_add::{
+        BreedingWishlistAdd, BreedingWishlistAddCancel, BreedingWishlistAddOk,
+    },
+    handlers::CommandHandler,
+    impl_packet_handler,
+    server::{Server, Session},
+};
+
+pub const BREEDING_WISHLIST_MAX_SIZE: usize = 20;
+
+pub struct BreedingWishlistAddHandler {}
+impl CommandHandler for BreedingWishlistAddHandler {
+    type CommandType = BreedingWishlistAdd;
+    async fn handle_command(
+        server: Arc<Server>,
+        session: Arc<Mutex<Session>>,
+        command: &Self::CommandType,
+    ) -> Result<(), String> {
+        let character_id = session
+            .lock()
+            .await
+            .character
+            .as_ref()
+            .ok_or("Player has no character")?
+            .character_id;
+
+        let database = Arc::clone(&server.database);
+        let result = database
+            .run_in_transaction(async |transaction| {
+                if transaction.get_stallion(command.uid).await?.is_none() {
+                    return Err(format!("Horse {} is not a stallion", command.uid).into());
+                }
+                if transaction.count_wishlist(character_id).await? >= BREEDING_WISHLIST_MAX_SIZE {
+                    return Err("Wishlist is full".into());
+                }
+                transaction.add_to_wishlist(character_id, command.uid).await
+            })
+            .await;
+
+        let mut session = session.lock().await;
+        match result {
+            Ok(()) => session
+                .send_command(BreedingWishlistAddOk {})
+                .await
+                .map_err(|e| format!("Failed to send response: {:?}", e)),
+            Err(e) => {
+                session
+                    .send_command(BreedingWishlistAddCancel {})
+                    .await
+                    .map_err(|e| format!("Failed to send response: {:?}", e))?;
+                Err(format!("Failed to add stallion to the wishlist: {}", e))
+            }
+        }
+    }
+}
+impl_packet_handler!(BreedingWishlistAddHandler);
@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::{
+    commands::ranch::breeding_wishlist_del::{
+        BreedingWishlistDel, BreedingWishlistDelCancel, BreedingWishlistDelOk,
+    },
+    handlers::CommandHandler,
+    impl_packet_handler,
+    server::{Server, Session},
+};
+
+pub struct BreedingWishlistDelHandler {}
+impl CommandHandler for BreedingWishlistDelHandler {
+    type CommandType = BreedingWishlistDel;
+    async fn handle_command(
+        server: Arc<Server>,
+        session: Arc<Mutex<Session>>,
+        command: &Self::CommandType,
+    ) -> Result<(), String> {
+        let character_id = session
+            .lock()
+            .await
+            .character
+            .as_ref()
+            .ok_or("Player has no character")?
+            .character_id;
+
+        let database = Arc::clone(&server.database);
+        let result = database
+            .run_in_transaction(async |transaction| {
+                transaction
+                    .remove_from_wishlist(character_id, command.uid)
+                    .await
+            })
+            .await;
+
+        let mut session = session.lock().await;
+        match result {
+            Ok(()) => session
+                .send_command(BreedingWishlistDelOk {})
+                .await
+                .map_err(|e| format!("Failed to send response: {:?}", e)),
+            Err(e) => {
+                session
+                    .send_command(BreedingWishlistDelCancel {})
+                    .await
+                    .map_err(|e| format!("Failed to send response: {:?}", e))?;
+                Err(format!(
+                    "Failed to remove stallion from the wishlist: {}",
+                    e
+                ))
+            }
+        }
+    }
+}
+impl_packet_handler!(BreedingWishlistDelHandler);
@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::{
+    commands::ranch::check_stallion_charge::{CheckStallionCharge, CheckStallionChargeOk},
+    handlers::CommandHandler,
+    impl_packet_handler,
+    server::{Server, Session},
+};
+
+pub struct CheckStallionChargeHandler {}
+impl CommandHandler for CheckStallionChargeHandler {
+    type CommandType = CheckStallionCharge;
+    async fn handle_command(
+        server: Arc<Server>,
+        session: Arc<Mutex<Session>>,
+        command: &Self::CommandType,
+    ) -> Result<(), String> {
+        let database = Arc::clone(&server.database);
+        let stallion = database
+            .run_in_transaction(async |transaction| {
+                transaction.get_stallion(command.horse_uid).await
+            })
+            .await
+            .map_err(|e| format!("Failed to fetch stallion: {}", e))?
+            .ok_or(format!("Horse {} is not a stallion", command.horse_uid))?;
+
+        let response = CheckStallionChargeOk {
+            horse_uid: stallion.horse_uid,
+            charge: stallion.price,
+        };
+        session
+            .lock()
+            .await
+            .send_command(response)
+            .await
+            .map_err(|e| format!("Failed to send response: {:?}", e))
+    }
+}
+impl_packet_handler!(CheckStallionChargeHandler);
@@ -8,7 +8,9 @@ use crate::{
         ranch::enter_breeding_market::{
             AvailableHorse, EnterBreedingMarket, EnterBreedingMarketOk,
         },
+        shared::alicia_time::AliciaTime,
     },
+    genetics::check_eligibility,
     handlers::CommandHandler,
     impl_packet_handler,
     server::{Server, Session},
@@ -18,26 +20,51 @@ pub struct EnterBreedingMarketHandler {}
 impl CommandHandler for EnterBreedingMarketHandler {
     type CommandType = EnterBreedingMarket;
     async fn handle_command(
-        _server: Arc<Mutex<Server>>,
+        server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         _command: &Self::CommandType,
     ) -> Result<(), String> {
-        // TODO: Filter only valid horses
+        let (mount_uid, horses) = {
+            let session = session.lock().await;
+            let mount_uid = session
+                .character
+                .as_ref()
+                .ok_or("Player has no character")?
+                .mount_uid;
+            let horses = session.horses.clone().ok_or("Character has no horses")?;
+            (mount_uid, horses)
+        };
+
+        let (database, rules) = (
+            Arc::clone(&server.database),
+            server.settings.breeding_eligibility.clone(),
+        );
+        let uids = horses.iter().map(|h| h.uid).collect::<Vec<u32>>();
+        let records = database
+            .run_in_transaction(async |transaction| transaction.get_breeding_records(&uids).await)
+            .await
+            .map_err(|e| format!("Failed to fetch breeding records: {}", e))?;
+
+        let now = AliciaTime::now();
+        let available_horses = horses
+            .iter()
+            .filter_map(|horse| {
+                let record = records.iter().find(|r| r.horse_uid == horse.uid)?;
+                check_eligibility(horse, record, mount_uid, &rules, &now).ok()?;
+                // TODO: Find out what success, unk1 and unk2 stand for, they're left at 0
+                // until then
+                Some(AvailableHorse {
+                    uid: horse.uid,
+                    tid: horse.tid,
+                    coat_bonus: record.coat_bonus,
+                    ..Default::default()
+                })
+            })
+            .collect();
+
         let response = EnterBreedingMarketOk {
             available_horses: LengthPrefixedVec {
-                vec: session
-                    .lock()
-                    .await
-                    .horses
-                    .as_ref()
-                    .ok_or("Character has no horses")?
-                    .iter()
-                    .map(|h| AvailableHorse {
-                        uid: h.uid,
-                        tid: h.tid,
-                        ..Default::default()
-                    })
-                    .collect(),
+                vec: available_horses,
             },
         };
         session
@@ -1,4 +1,4 @@
-use std::{collections::hash_map::Entry, ffi::CString, sync::Arc};
+use std::{ffi::CString, sync::Arc};
 
 use tokio::sync::Mutex;
 
@@ -7,16 +7,19 @@ use crate::{
         LengthPrefixedVec,
         ranch::{
             RanchCharacter, RanchHorse, RanchUnk11,
-            enter_ranch::{EnterRanch, EnterRanchNotify, EnterRanchOk},
+            enter_ranch::{EnterRanch, EnterRanchCancel, EnterRanchNotify, EnterRanchOk},
         },
-        shared::character::{
-            AnotherPlayerRelatedThing, PlayerRelatedThing, YetAnotherPlayerRelatedThing,
+        shared::{
+            character::{
+                AnotherPlayerRelatedThing, PlayerRelatedThing, YetAnotherPlayerRelatedThing,
+            },
+            horse::Horse,
         },
     },
-    database::{character::get_character_by_id, horse::get_horses_by_character_id},
+    entities::tracked::Tracked,
     handlers::CommandHandler,
     impl_packet_handler,
-    ranch::Ranch,
+    ranch::{broadcast, close_ranch_if_empty, leave_ranch, lock_or_open_ranch},
     server::{Server, Session},
 };
 
@@ -24,145 +27,200 @@ pub struct EnterRanchHandler {}
 impl CommandHandler for EnterRanchHandler {
     type CommandType = EnterRanch;
     async fn handle_command(
-        server: Arc<Mutex<Server>>,
+        server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         command: &Self::CommandType,
     ) -> Result<(), String> {
-        // TODO: Validate command.otp
-
         let server = Arc::clone(&server);
 
+        // Only the client the lobby sent here with this ranch's entry code gets in. Codes are
+        // issued to characters, which makes the code the proof of who is logging in
+        let database = Arc::clone(&server.database);
+        let redeemed_ranch_id = database
+            .run_in_transaction(async |transaction| {
+                transaction
+                    .redeem_ranch_entry_code(command.character_uid, command.otp)
+                    .await
+            })
+            .await
+            .map_err(|e| format!("Failed to check entry code: {}", e))?;
+        if redeemed_ranch_id != Some(command.ranch_uid) {
+            session
+                .lock()
+                .await
+                .send_command(EnterRanchCancel {})
+                .await
+                .map_err(|e| format!("Failed to send response: {:?}", e))?;
+            return Err(format!(
+                "Character {} has no valid entry code for ranch {}",
+                command.character_uid, command.ranch_uid
+            ));
+        }
+
         // Load player data from DB if just logging in
         {
-            let database = Arc::clone(&server.lock().await.database);
             let mut session = session.lock().await;
             if session.character.is_none() || session.horses.is_none() {
+                let character_id = session
+                    .character
+                    .as_ref()
+                    .map_or(command.character_uid, |c| c.character_id);
                 let (character, horses) = database
-                    .lock()
-                    .await
                     .run_in_transaction(async |transaction| {
-                        let character = get_character_by_id(transaction, command.character_uid)
+                        let character = transaction
+                            .get_character_by_id(character_id)
                             .await
                             .map_err(|e| {
-                                format!(
-                                    "Failed to fetch character with id {}: {}",
-                                    command.character_uid, e
-                                )
+                                format!("Failed to fetch character with id {}: {}", character_id, e)
                             })?
                             .ok_or("Character not found".to_owned())?;
-                        let horses = get_horses_by_character_id(transaction, command.character_uid)
+                        let horses = transaction
+                            .get_horses_by_character_id(character_id)
                             .await
                             .map_err(|e| {
                                 format!(
                                     "Failed to fetch horses for character {}: {}",
-                                    command.character_uid, e
+                                    character_id, e
                                 )
                             })?;
                         Ok((character, horses))
                     })
                     .await
                     .map_err(|e| format!("Failed to load character and horses: {}", e))?;
-                session.character = Some(character.clone());
-                session.horses = Some(horses.clone());
+                session.character = Some(Tracked::stored(character.clone()));
+                session.horses = Some(horses.iter().cloned().map(Tracked::stored).collect());
             }
         }
 
-        let mut server = server.lock().await;
-        let ranch = match server.ranches.entry(command.ranch_uid) {
-            Entry::Occupied(ranch) => ranch.into_mut(),
-            Entry::Vacant(entry) => entry.insert({
-                let nickname = session
-                    .lock()
+        // A session that's already logged in can't switch to another character
+        let mut new_ranch_character = {
+            let mut session = session.lock().await;
+            let character_id = session
+                .character
+                .as_ref()
+                .ok_or("Player doesn't have a character")?
+                .character_id;
+            if character_id != command.character_uid {
+                session
+                    .send_command(EnterRanchCancel {})
                     .await
-                    .character
-                    .as_ref()
-                    .map(|c| c.nickname.to_owned())
-                    .ok_or("Session has no character loaded")?;
-                Ranch {
-                    name: format!("{}'s Ranch", nickname),
-                    owner: Arc::clone(&session),
-                    character_sessions: vec![],
-                }
-            }),
+                    .map_err(|e| format!("Failed to send response: {:?}", e))?;
+                return Err(format!(
+                    "Character {} tried to enter ranch {} as character {}",
+                    character_id, command.ranch_uid, command.character_uid
+                ));
+            }
+            // The ranch index is only known once the character is in the ranch
+            ranch_character(&session, 0)?
         };
+        let character_id = new_ranch_character.uid;
 
-        // Add new player to ranch
-        ranch.character_sessions.push(Arc::clone(&session));
+        // Players can only be in one ranch at a time
+        leave_ranch(&server, &session).await?;
 
-        let mut ranch_index = 0;
+        // The ranch belongs to whoever owns it in the database, regardless of who arrives first
+        let ranch_info = database
+            .run_in_transaction(async |transaction| {
+                let Some(ranch) = transaction.get_ranch_by_id(command.ranch_uid).await? else {
+                    return Ok(None);
+                };
+                let owner = transaction
+                    .get_character_by_id(ranch.owner_character_id)
+                    .await?
+                    .ok_or(format!(
+                        "Couldn't find owner of ranch {}",
+                        command.ranch_uid
+                    ))?;
+                let horses = transaction
+                    .get_horses_by_character_id(ranch.owner_character_id)
+                    .await?;
+                let horses = horses
+                    .into_iter()
+                    .filter(|h| h.uid != owner.mount_uid)
+                    .collect::<Vec<Horse>>();
+                let is_banned = transaction
+                    .is_banned_from_ranch(ranch.ranch_id, character_id)
+                    .await?;
+                Ok(Some((ranch, horses, is_banned)))
+            })
+            .await
+            .map_err(|e| format!("Failed to load ranch {}: {}", command.ranch_uid, e))?;
+        let Some((ranch_info, owner_horses, is_banned)) = ranch_info else {
+            session
+                .lock()
+                .await
+                .send_command(EnterRanchCancel {})
+                .await
+                .map_err(|e| format!("Failed to send response: {:?}", e))?;
+            return Err(format!("Ranch {} doesn't exist", command.ranch_uid));
+        };
+        let ranch_name = CString::new(ranch_info.name.clone())
+            .map_err(|_| "Failed to convert ranch name to CString")?;
 
-        let ranch_horses = {
-            let ranch_owner = ranch.owner.lock().await;
-            let ranch_owner_horses = ranch_owner
-                .horses
-                .as_ref()
-                .ok_or("Ranch owner has no horses".to_owned())?;
-            let ranch_owner_mount = ranch_owner
-                .get_mount()
-                .ok_or("Ranch owner has no mount".to_owned())?;
-            let mut ranch_horses = Vec::new();
-            for horse in ranch_owner_horses {
-                if horse.uid != ranch_owner_mount.uid {
-                    ranch_index = ranch_index + 1;
-                    ranch_horses.push(RanchHorse {
-                        ranch_index,
-                        horse: horse.clone(),
-                    });
-                }
-            }
-            ranch_horses
+        let mut ranch = lock_or_open_ranch(&server, &ranch_info).await;
+        let visitor_count = ranch.character_sessions.len();
+        let admission = if is_banned {
+            Err("Character was kicked out recently".to_owned())
+        } else {
+            ranch_info.admits(character_id, visitor_count)
         };
+        if let Err(reason) = admission {
+            // Don't leave the ranch open if it was only opened for this attempt
+            close_ranch_if_empty(&server, &mut ranch);
+            session
+                .lock()
+                .await
+                .send_command(EnterRanchCancel {})
+                .await
+                .map_err(|e| format!("Failed to send response: {:?}", e))?;
+            return Err(format!(
+                "Character {} can't enter ranch {}: {}",
+                character_id, command.ranch_uid, reason
+            ));
+        }
 
-        let (ranch_characters, new_ranch_character) = {
-            let mut new_ranch_character: Option<RanchCharacter> = None;
-            let mut ranch_characters = Vec::new();
-            for ranch_session in ranch.character_sessions.as_slice() {
-                ranch_index = ranch_index + 1;
-                let ranch_session = ranch_session.lock().await;
-                let ranch_session_character = ranch_session
-                    .character
-                    .as_ref()
-                    .ok_or("Ranch session has no character")?;
-                let ranch_session_mount = ranch_session.get_mount().ok_or(format!(
-                    "Ranch session with character id {} has no mount",
-                    ranch_session_character.character_id
-                ))?;
-                let ranch_character = RanchCharacter {
-                    uid: ranch_session_character.character_id,
-                    name: CString::new(ranch_session_character.nickname.clone())
-                        .map_err(|e| format!("Failed to convert nickname to CString: {}", e))?,
-                    gender: ranch_session_character.character.parts.gender(),
-                    unk0: 1,
-                    unk1: 1,
-                    description: c"Description".into(),
-                    character: ranch_session_character.character.clone(),
-                    mount: ranch_session_mount.clone(),
-                    character_equipment: LengthPrefixedVec::default(),
-                    player_related_thing: PlayerRelatedThing::default(),
-                    ranch_index,
-                    unk2: 0,
-                    unk3: 0,
-                    another_player_related_thing: AnotherPlayerRelatedThing {
-                        mount_uid: ranch_session_mount.uid,
-                        ..Default::default()
-                    },
-                    yet_another_player_related_thing: YetAnotherPlayerRelatedThing::default(),
-                    unk4: 0,
-                    unk5: 0,
-                };
-                if ranch_character.uid == command.character_uid {
-                    new_ranch_character = Some(ranch_character.clone());
-                }
-                ranch_characters.push(ranch_character);
+        // Nothing from here on can fail until the character is in the ranch, so that a
+        // failed attempt can't leave it behind
+        let mut ranch_characters = Vec::new();
+        for ranch_session in ranch.character_sessions.as_slice() {
+            let ranch_session = ranch_session.lock().await;
+            let ranch_index = ranch_session
+                .character
+                .as_ref()
+                .and_then(|c| ranch.character_ranch_index(c.character_id))
+                .unwrap_or_default();
+            match ranch_character(&ranch_session, ranch_index) {
+                Ok(ranch_character) => ranch_characters.push(ranch_character),
+                Err(e) => eprintln!(
+                    "Leaving out character of ranch {}: {}",
+                    ranch_info.ranch_id, e
+                ),
             }
-            (ranch_characters, new_ranch_character.ok_or("What")?)
-        };
+        }
+
+        // Options may have been changed from the lobby since the ranch was opened
+        ranch.info = ranch_info;
+        ranch.set_horses(owner_horses);
+        let ranch_horses = ranch
+            .horses
+            .iter()
+            .filter_map(|horse| {
+                Some(RanchHorse {
+                    ranch_index: ranch.horse_ranch_index(horse.uid)?,
+                    horse: horse.clone(),
+                })
+            })
+            .collect::<Vec<RanchHorse>>();
+
+        // Add new player to ranch
+        new_ranch_character.ranch_index = ranch.add_character(character_id, Arc::clone(&session));
+        ranch_characters.push(new_ranch_character.clone());
+        let visitor_count = ranch.character_sessions.len() as u32;
 
         let response = EnterRanchOk {
             ranch_id: command.ranch_uid,
             unk0: c"Unk0".into(),
-            ranch_name: CString::new(ranch.name.clone())
-                .map_err(|_| "Failed to convert ranch name to CString")?,
+            ranch_name,
             horses: LengthPrefixedVec { vec: ranch_horses },
             character: LengthPrefixedVec {
                 vec: ranch_characters,
@@ -195,21 +253,218 @@ impl CommandHandler for EnterRanchHandler {
         let notify = EnterRanchNotify {
             character: new_ranch_character,
         };
-        for ranch_session in ranch.character_sessions.as_slice() {
-            let mut ranch_session = ranch_session.lock().await;
-            if ranch_session
+        broadcast(&ranch.character_sessions, &notify, Some(character_id)).await;
+
+        database
+            .run_in_transaction(async |transaction| {
+                transaction
+                    .update_visitor_count(command.ranch_uid, visitor_count)
+                    .await
+            })
+            .await
+            .map_err(|e| format!("Failed to update visitor count: {}", e))
+    }
+}
+
+/// How a character in the ranch is shown to the rest of the visitors.
+fn ranch_character(session: &Session, ranch_index: u16) -> Result<RanchCharacter, String> {
+    let character = session
+        .character
+        .as_ref()
+        .ok_or("Ranch session has no character")?;
+    let mount = session.get_mount().ok_or(format!(
+        "Ranch session with character id {} has no mount",
+        character.character_id
+    ))?;
+    Ok(RanchCharacter {
+        uid: character.character_id,
+        name: CString::new(character.nickname.clone())
+            .map_err(|e| format!("Failed to convert nickname to CString: {}", e))?,
+        gender: character.character.parts.gender(),
+        unk0: 1,
+        unk1: 1,
+        description: c"Description".into(),
+        character: character.character.clone(),
+        mount: mount.clone(),
+        character_equipment: LengthPrefixedVec::default(),
+        player_related_thing: PlayerRelatedThing::default(),
+        ranch_index,
+        unk2: 0,
+        unk3: 0,
+        another_player_related_thing: AnotherPlayerRelatedThing {
+            mount_uid: mount.uid,
+            ..Default::default()
+        },
+        yet_another_player_related_thing: YetAnotherPlayerRelatedThing::default(),
+        unk4: 0,
+        unk5: 0,
+    })
+}
+impl_packet_handler!(EnterRanchHandler);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::ranch::leave_ranch::LeaveRanch,
+        handlers::ranch::leave_ranch::LeaveRanchHandler,
+        ranch::get_open_ranch,
+        server::{
+            ServerType,
+            testing::{create_character, entry_code, test_server, test_session},
+        },
+    };
+
+    async fn get_ranch_id(server: &Server, owner_character_id: u32) -> u32 {
+        server
+            .database
+            .run_in_transaction(async |transaction| {
+                transaction
+                    .get_or_create_ranch_by_owner(owner_character_id)
+                    .await
+            })
+            .await
+            .unwrap()
+            .unwrap()
+            .ranch_id
+    }
+
+    #[tokio::test]
+    async fn test_entering_takes_the_lobby_code() {
+        let server = test_server(ServerType::Ranch).await;
+        let (session, _client) = test_session().await;
+        let (owner, _) = create_character(&server.database, "Owner").await;
+        let (visitor, _) = create_character(&server.database, "Visitor").await;
+        let ranch_id = get_ranch_id(&server, owner.character_id).await;
+        let other_ranch_id = get_ranch_id(&server, visitor.character_id).await;
+        let enter = async |character_uid, otp| {
+            EnterRanchHandler::handle_command(
+                Arc::clone(&server),
+                Arc::clone(&session),
+                &EnterRanch {
+                    character_uid,
+                    otp,
+                    ranch_uid: ranch_id,
+                },
+            )
+            .await
+        };
+
+        // Whoever the client claims to be, the code has to have been issued to that character
+        // for this ranch
+        assert!(enter(visitor.character_id, 0).await.is_err());
+        let code = entry_code(&server.database, visitor.character_id, ranch_id).await;
+        assert!(enter(owner.character_id, code).await.is_err());
+        let code = entry_code(&server.database, visitor.character_id, other_ranch_id).await;
+        assert!(enter(visitor.character_id, code).await.is_err());
+        // Codes can't be guessed at, the first wrong one uses them up
+        let code = entry_code(&server.database, visitor.character_id, ranch_id).await;
+        assert!(
+            enter(visitor.character_id, code.wrapping_add(1))
+                .await
+                .is_err()
+        );
+        assert!(enter(visitor.character_id, code).await.is_err());
+        assert!(session.lock().await.character.is_none());
+
+        let code = entry_code(&server.database, visitor.character_id, ranch_id).await;
+        enter(visitor.character_id, code).await.unwrap();
+        assert_eq!(
+            session
+                .lock()
+                .await
                 .character
                 .as_ref()
-                .is_some_and(|c| c.character_id != command.character_uid)
-            {
-                ranch_session
-                    .send_command(notify.clone())
-                    .await
-                    .map_err(|e| format!("Failed to send notify: {:?}", e).to_owned())?;
-            }
+                .unwrap()
+                .character_id,
+            visitor.character_id
+        );
+        // Nor can the code be used twice
+        assert!(enter(visitor.character_id, code).await.is_err());
+
+        // Once logged in, the session stays with its character
+        let code = entry_code(&server.database, owner.character_id, ranch_id).await;
+        assert!(enter(owner.character_id, code).await.is_err());
+        assert_eq!(
+            session
+                .lock()
+                .await
+                .character
+                .as_ref()
+                .unwrap()
+                .character_id,
+            visitor.character_id
+        );
+    }
+
+    #[tokio::test]
+    async fn test_enter_and_leave_ranch() {
+        let server = test_server(ServerType::Ranch).await;
+        let (owner_session, _owner_client) = test_session().await;
+        let (visitor_session, _visitor_client) = test_session().await;
+        let (owner, _) = create_character(&server.database, "Owner").await;
+        let (visitor, _) = create_character(&server.database, "Visitor").await;
+        let ranch_id = get_ranch_id(&server, owner.character_id).await;
+        let enter = async |session: &Arc<Mutex<Session>>, character_uid| {
+            EnterRanchHandler::handle_command(
+                Arc::clone(&server),
+                Arc::clone(session),
+                &EnterRanch {
+                    character_uid,
+                    otp: entry_code(&server.database, character_uid, ranch_id).await,
+                    ranch_uid: ranch_id,
+                },
+            )
+            .await
+        };
+        let visitor_count = async || {
+            server
+                .database
+                .run_in_transaction(async |transaction| transaction.get_ranch_by_id(ranch_id).await)
+                .await
+                .unwrap()
+                .unwrap()
+                .visitor_count
+        };
+
+        enter(&owner_session, owner.character_id).await.unwrap();
+        enter(&visitor_session, visitor.character_id).await.unwrap();
+        assert_eq!(visitor_session.lock().await.ranch_id, Some(ranch_id));
+        assert_eq!(visitor_count().await, 2);
+        {
+            let ranch = get_open_ranch(&server, ranch_id).unwrap();
+            let ranch = ranch.lock().await;
+            assert_eq!(ranch.character_sessions.len(), 2);
+            let owner_index = ranch.character_ranch_index(owner.character_id).unwrap();
+            let visitor_index = ranch.character_ranch_index(visitor.character_id).unwrap();
+            assert_ne!(owner_index, visitor_index);
         }
 
-        Ok(())
+        LeaveRanchHandler::handle_command(
+            Arc::clone(&server),
+            Arc::clone(&visitor_session),
+            &LeaveRanch {},
+        )
+        .await
+        .unwrap();
+        assert_eq!(visitor_session.lock().await.ranch_id, None);
+        assert_eq!(visitor_count().await, 1);
+        {
+            let ranch = get_open_ranch(&server, ranch_id).unwrap();
+            let ranch = ranch.lock().await;
+            assert_eq!(ranch.character_sessions.len(), 1);
+            assert!(ranch.character_ranch_index(visitor.character_id).is_none());
+        }
+
+        // The ranch closes with its last visitor
+        LeaveRanchHandler::handle_command(
+            Arc::clone(&server),
+            Arc::clone(&owner_session),
+            &LeaveRanch {},
+        )
+        .await
+        .unwrap();
+        assert!(get_open_ranch(&server, ranch_id).is_none());
+        assert_eq!(visitor_count().await, 0);
     }
 }
-impl_packet_handler!(EnterRanchHandler);
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::{
+    commands::ranch::expand_mount_slot::{
+        ExpandMountSlot, ExpandMountSlotCancel, ExpandMountSlotOk,
+    },
+    database::character::MAX_MOUNT_SLOTS,
+    handlers::CommandHandler,
+    impl_packet_handler,
+    server::{Server, Session},
+};
+
+pub struct ExpandMountSlotHandler {}
+impl CommandHandler for ExpandMountSlotHandler {
+    type CommandType = ExpandMountSlot;
+    async fn handle_command(
+        server: Arc<Server>,
+        session: Arc<Mutex<Session>>,
+        _command: &Self::CommandType,
+    ) -> Result<(), String> {
+        let character_id = session
+            .lock()
+            .await
+            .character
+            .as_ref()
+            .ok_or("Player has no character")?
+            .character_id;
+
+        let (database, price) = (
+            Arc::clone(&server.database),
+            server.settings.ranch.mount_slot_price,
+        );
+        let result = database
+            .run_in_transaction(async |transaction| {
+                // Locking the wallet first keeps concurrent expansions from racing each other
+                let mut wallet = transaction.get_wallet_for_update(character_id).await?;
+                let mut character = transaction
+                    .get_character_by_id(character_id)
+                    .await?
+                    .ok_or(format!("Couldn't find character {}", character_id))?;
+                if character.mount_slots >= MAX_MOUNT_SLOTS {
+                    return Err("Mount slots are already maxed out".into());
+                }
+                wallet.carrots = wallet.carrots.checked_sub(price).ok_or(format!(
+                    "Character {} can't afford {} carrots",
+                    character_id, price
+                ))?;
+                character.mount_slots += 1;
+                transaction.update_wallet(&wallet).await?;
+                transaction.update_character(&character).await?;
+                Ok((character.mount_slots, wallet))
+            })
+            .await;
+
+        let mut session = session.lock().await;
+        match result {
+            Ok((mount_slots, wallet)) => {
+                if let Some(character) = session.character.as_mut() {
+                    character.mount_slots = mount_slots;
+                }
+                session
+                    .send_command(ExpandMountSlotOk {
+                        mount_slots,
+                        updated_carrot_count: wallet.carrots,
+                    })
+                    .await
+                    .map_err(|e| format!("Failed to send response: {:?}", e))
+            }
+            Err(e) => {
+                session
+                    .send_command(ExpandMountSlotCancel {})
+                    .await
+                    .map_err(|e| format!("Failed to send response: {:?}", e))?;
+                Err(format!("Failed to expand mount slots: {}", e))
+            }
+        }
+    }
+}
+impl_packet_handler!(ExpandMountSlotHandler);
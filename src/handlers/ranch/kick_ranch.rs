@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::{
+    commands::ranch::kick_ranch::{KickRanch, KickRanchCancel, KickRanchNotify, KickRanchOk},
+    handlers::CommandHandler,
+    impl_packet_handler,
+    ranch::{broadcast, get_open_ranch, leave_ranch},
+    server::{Server, Session},
+};
+
+pub struct KickRanchHandler {}
+impl CommandHandler for KickRanchHandler {
+    type CommandType = KickRanch;
+    async fn handle_command(
+        server: Arc<Server>,
+        session: Arc<Mutex<Session>>,
+        command: &Self::CommandType,
+    ) -> Result<(), String> {
+        let (character_id, ranch_id) = {
+            let session = session.lock().await;
+            (
+                session
+                    .character
+                    .as_ref()
+                    .ok_or("Player doesn't have a character")?
+                    .character_id,
+                session.ranch_id.ok_or("Player is not in any ranch")?,
+            )
+        };
+
+        let (database, ban_duration_secs, kicked_session, ranch_sessions) = {
+            let ranch = get_open_ranch(&server, ranch_id)
+                .ok_or(format!("Couldn't find ranch with id {}", ranch_id))?
+                .lock_owned()
+                .await;
+            // Owners can't kick themselves out of their own ranch
+            let kicked_session = if ranch.info.owner_character_id != character_id
+                || command.character_id == character_id
+            {
+                None
+            } else {
+                let mut kicked_session = None;
+                for ranch_session in ranch.character_sessions.iter() {
+                    if ranch_session
+                        .lock()
+                        .await
+                        .character
+                        .as_ref()
+                        .is_some_and(|c| c.character_id == command.character_id)
+                    {
+                        kicked_session = Some(Arc::clone(ranch_session));
+                        break;
+                    }
+                }
+                kicked_session
+            };
+            (
+                Arc::clone(&server.database),
+                server.settings.ranch.kick_ban_duration_secs,
+                kicked_session,
+                ranch.character_sessions.clone(),
+            )
+        };
+        let Some(kicked_session) = kicked_session else {
+            session
+                .lock()
+                .await
+                .send_command(KickRanchCancel {})
+                .await
+                .map_err(|e| format!("Failed to send response: {:?}", e))?;
+            return Err(format!(
+                "Character {} can't kick character {} from ranch {}",
+                character_id, command.character_id, ranch_id
+            ));
+        };
+
+        database
+            .run_in_transaction(async |transaction| {
+                transaction
+                    .ban_from_ranch(ranch_id, command.character_id, ban_duration_secs)
+                    .await
+            })
+            .await
+            .map_err(|e| format!("Failed to ban character from ranch: {}", e))?;
+
+        // The kicked player is told as well, so its client can head back to the lobby
+        let notify = KickRanchNotify {
+            character_id: command.character_id,
+        };
+        broadcast(&ranch_sessions, &notify, None).await;
+        leave_ranch(&server, &kicked_session).await?;
+
+        session
+            .lock()
+            .await
+            .send_command(KickRanchOk {})
+            .await
+            .map_err(|e| format!("Failed to send response: {:?}", e))
+    }
+}
+impl_packet_handler!(KickRanchHandler);
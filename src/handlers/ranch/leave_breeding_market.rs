@@ -13,7 +13,7 @@ pub struct LeaveBreedingMarketHandler {}
 impl CommandHandler for LeaveBreedingMarketHandler {
     type CommandType = LeaveBreedingMarket;
     async fn handle_command(
-        _server: Arc<Mutex<Server>>,
+        _server: Arc<Server>,
         _session: Arc<Mutex<Session>>,
         _command: &Self::CommandType,
     ) -> Result<(), String> {
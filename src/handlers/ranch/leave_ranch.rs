@@ -0,0 +1,30 @@
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::{
+    commands::ranch::leave_ranch::{LeaveRanch, LeaveRanchOk},
+    handlers::CommandHandler,
+    impl_packet_handler,
+    ranch::leave_ranch,
+    server::{Server, Session},
+};
+
+pub struct LeaveRanchHandler {}
+impl CommandHandler for LeaveRanchHandler {
+    type CommandType = LeaveRanch;
+    async fn handle_command(
+        server: Arc<Server>,
+        session: Arc<Mutex<Session>>,
+        _command: &Self::CommandType,
+    ) -> Result<(), String> {
+        leave_ranch(&server, &session).await?;
+        session
+            .lock()
+            .await
+            .send_command(LeaveRanchOk {})
+            .await
+            .map_err(|e| format!("Failed to send response: {:?}", e))
+    }
+}
+impl_packet_handler!(LeaveRanchHandler);
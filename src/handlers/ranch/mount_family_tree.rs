@@ -5,7 +5,9 @@ use tokio::sync::Mutex;
 use crate::{
     commands::{
         LengthPrefixedVec,
-        ranch::mount_family_tree::{MountFamilyTree, MountFamilyTreeItem, MountFamilyTreeOk},
+        ranch::mount_family_tree::{
+            MountFamilyTree, MountFamilyTreeCancel, MountFamilyTreeItem, MountFamilyTreeOk,
+        },
     },
     handlers::CommandHandler,
     impl_packet_handler,
@@ -16,36 +18,57 @@ pub struct MountFamilyTreeHandler {}
 impl CommandHandler for MountFamilyTreeHandler {
     type CommandType = MountFamilyTree;
     async fn handle_command(
-        _server: Arc<Mutex<Server>>,
+        server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         command: &Self::CommandType,
     ) -> Result<(), String> {
-        // TODO: Fetch
-        let response = MountFamilyTreeOk {
-            uid: command.uid,
-            items: LengthPrefixedVec {
-                vec: vec![
-                    MountFamilyTreeItem {
-                        unk0: 0,
-                        unk1: c"Parent0".to_owned(),
-                        unk2: 2,
-                        unk3: 3,
-                    },
-                    MountFamilyTreeItem {
-                        unk0: 1,
-                        unk1: c"Parent1".to_owned(),
-                        unk2: 12,
-                        unk3: 13,
-                    },
-                ],
-            },
-        };
-        session
-            .lock()
+        let database = Arc::clone(&server.database);
+        let family_tree = database
+            .run_in_transaction(async |transaction| {
+                if transaction.get_horse_by_uid(command.uid).await?.is_none() {
+                    return Ok(None);
+                }
+                let Some(lineage) = transaction.get_lineage(command.uid).await? else {
+                    // Horses that weren't bred have no known ancestors
+                    return Ok(Some(vec![]));
+                };
+                // The client renders up to the grandparents, which is as far as lineages go
+                let ancestor_uids = lineage.ancestors();
+                let ancestors = transaction.get_horses_by_uids(&ancestor_uids).await?;
+                let mut items = Vec::new();
+                for (position, uid) in ancestor_uids.iter().enumerate() {
+                    // Ancestors can be unknown or may no longer exist
+                    if let Some(ancestor) = ancestors.iter().find(|h| h.uid == *uid) {
+                        items.push(MountFamilyTreeItem {
+                            position: position as u8,
+                            name: ancestor.name.clone(),
+                            grade: ancestor.grade,
+                            tid: ancestor.tid as u16,
+                        });
+                    }
+                }
+                Ok(Some(items))
+            })
             .await
-            .send_command(response)
-            .await
-            .map_err(|e| format!("Failed to send response: {:?}", e))
+            .map_err(|e| format!("Failed to fetch family tree: {}", e))?;
+
+        let mut session = session.lock().await;
+        match family_tree {
+            Some(items) => session
+                .send_command(MountFamilyTreeOk {
+                    uid: command.uid,
+                    items: LengthPrefixedVec { vec: items },
+                })
+                .await
+                .map_err(|e| format!("Failed to send response: {:?}", e)),
+            None => {
+                session
+                    .send_command(MountFamilyTreeCancel {})
+                    .await
+                    .map_err(|e| format!("Failed to send response: {:?}", e))?;
+                Err(format!("Couldn't find horse with uid {}", command.uid))
+            }
+        }
     }
 }
 impl_packet_handler!(MountFamilyTreeHandler);
@@ -6,6 +6,7 @@ use crate::{
     commands::ranch::ranch_chat::{RanchChat, RanchChatNotify},
     handlers::CommandHandler,
     impl_packet_handler,
+    ranch::{broadcast, get_open_ranch},
     server::{Server, Session},
 };
 
@@ -13,7 +14,7 @@ pub struct RanchChatHandler {}
 impl CommandHandler for RanchChatHandler {
     type CommandType = RanchChat;
     async fn handle_command(
-        server: Arc<Mutex<Server>>,
+        server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         command: &Self::CommandType,
     ) -> Result<(), String> {
@@ -32,11 +33,10 @@ impl CommandHandler for RanchChatHandler {
                 .await
                 .ranch_id
                 .ok_or("Player is in no ranch")?;
-            let server = server.lock().await;
-            let ranch = server
-                .ranches
-                .get(&ranch_id)
-                .ok_or(format!("No ranch found with id {}", ranch_id))?;
+            let ranch = get_open_ranch(&server, ranch_id)
+                .ok_or(format!("No ranch found with id {}", ranch_id))?
+                .lock_owned()
+                .await;
             ranch.character_sessions.clone()
         };
 
@@ -47,13 +47,7 @@ impl CommandHandler for RanchChatHandler {
             unk1: command.unk1,
         };
 
-        for ranch_session in ranch_sessions {
-            let mut ranch_session = ranch_session.lock().await;
-            ranch_session
-                .send_command(response.clone())
-                .await
-                .map_err(|e| format!("Failed to send response: {:?}", e))?;
-        }
+        broadcast(&ranch_sessions, &response, None).await;
         Ok(())
     }
 }
@@ -6,6 +6,7 @@ use crate::{
     commands::ranch::ranch_cmd_action::{RanchCmdAction, RanchCmdActionNotify},
     handlers::CommandHandler,
     impl_packet_handler,
+    ranch::{broadcast, get_open_ranch},
     server::{Server, Session},
 };
 
@@ -13,18 +14,42 @@ pub struct RanchCmdActionHandler {}
 impl CommandHandler for RanchCmdActionHandler {
     type CommandType = RanchCmdAction;
     async fn handle_command(
-        _server: Arc<Mutex<Server>>,
+        server: Arc<Server>,
         session: Arc<Mutex<Session>>,
-        _command: &Self::CommandType,
+        command: &Self::CommandType,
     ) -> Result<(), String> {
-        // TODO: Send to all clients in the ranch
-        let response = RanchCmdActionNotify::default();
-        session
-            .lock()
-            .await
-            .send_command(response)
-            .await
-            .map_err(|e| format!("Failed to send response: {:?}", e))
+        command.action.check_no_extra_data(&command.extra)?;
+        let (character_id, ranch_id) = {
+            let session = session.lock().await;
+            (
+                session
+                    .character
+                    .as_ref()
+                    .ok_or("Player doesn't have a character")?
+                    .character_id,
+                session.ranch_id.ok_or("Player is not in any ranch")?,
+            )
+        };
+
+        let (ranch_index, ranch_sessions) = {
+            let ranch = get_open_ranch(&server, ranch_id)
+                .ok_or(format!("Couldn't find ranch with id {}", ranch_id))?
+                .lock_owned()
+                .await;
+            let ranch_index = ranch.character_ranch_index(character_id).ok_or(format!(
+                "Character {} is not in ranch {}",
+                character_id, ranch_id
+            ))?;
+            (ranch_index, ranch.character_sessions.clone())
+        };
+
+        // Everyone in the ranch sees the action, including whoever performed it
+        let notify = RanchCmdActionNotify {
+            ranch_index,
+            action: command.action.clone(),
+        };
+        broadcast(&ranch_sessions, &notify, None).await;
+        Ok(())
     }
 }
 impl_packet_handler!(RanchCmdActionHandler);
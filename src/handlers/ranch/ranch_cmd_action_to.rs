@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::{
+    commands::ranch::ranch_cmd_action::{RanchCmdActionNotify, RanchCmdActionTo},
+    handlers::CommandHandler,
+    impl_packet_handler,
+    ranch::get_open_ranch,
+    server::{Server, Session},
+};
+
+pub struct RanchCmdActionToHandler {}
+impl CommandHandler for RanchCmdActionToHandler {
+    type CommandType = RanchCmdActionTo;
+    async fn handle_command(
+        server: Arc<Server>,
+        session: Arc<Mutex<Session>>,
+        command: &Self::CommandType,
+    ) -> Result<(), String> {
+        command.action.check_no_extra_data(&command.extra)?;
+        let (character_id, ranch_id) = {
+            let session = session.lock().await;
+            (
+                session
+                    .character
+                    .as_ref()
+                    .ok_or("Player doesn't have a character")?
+                    .character_id,
+                session.ranch_id.ok_or("Player is not in any ranch")?,
+            )
+        };
+
+        let (ranch_index, target_session) = {
+            let ranch = get_open_ranch(&server, ranch_id)
+                .ok_or(format!("Couldn't find ranch with id {}", ranch_id))?
+                .lock_owned()
+                .await;
+            let ranch_index = ranch.character_ranch_index(character_id).ok_or(format!(
+                "Character {} is not in ranch {}",
+                character_id, ranch_id
+            ))?;
+            let mut target_session = None;
+            for ranch_session in ranch.character_sessions.iter() {
+                if ranch_session
+                    .lock()
+                    .await
+                    .character
+                    .as_ref()
+                    .is_some_and(|c| c.character_id == command.target_character_id)
+                {
+                    target_session = Some(Arc::clone(ranch_session));
+                    break;
+                }
+            }
+            let target_session = target_session.ok_or(format!(
+                "Character {} is not in ranch {}",
+                command.target_character_id, ranch_id
+            ))?;
+            (ranch_index, target_session)
+        };
+
+        // Only the two characters involved take part in the interaction
+        let notify = RanchCmdActionNotify {
+            ranch_index,
+            action: command.action.clone(),
+        };
+        // The target falling behind isn't the performer's problem
+        if !Arc::ptr_eq(&target_session, &session)
+            && let Err(e) = target_session
+                .lock()
+                .await
+                .send_command(notify.clone())
+                .await
+        {
+            eprintln!(
+                "Failed to send notify to character {}: {}",
+                command.target_character_id, e
+            );
+        }
+        session
+            .lock()
+            .await
+            .send_command(notify)
+            .await
+            .map_err(|e| format!("Failed to send notify: {:?}", e))
+    }
+}
+impl_packet_handler!(RanchCmdActionToHandler);
@@ -6,6 +6,7 @@ use crate::{
     commands::ranch::ranch_snapshot::{RanchSnapshot, RanchSnapshotNotify},
     handlers::CommandHandler,
     impl_packet_handler,
+    ranch::{broadcast, get_open_ranch},
     server::{Server, Session},
 };
 
@@ -13,7 +14,7 @@ pub struct RanchSnapshotHandler {}
 impl CommandHandler for RanchSnapshotHandler {
     type CommandType = RanchSnapshot;
     async fn handle_command(
-        server: Arc<Mutex<Server>>,
+        server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         command: &Self::CommandType,
     ) -> Result<(), String> {
@@ -29,52 +30,22 @@ impl CommandHandler for RanchSnapshotHandler {
             )
         };
 
-        let server = server.lock().await;
-        let ranch = server
-            .ranches
-            .get(&ranch_id)
-            .ok_or(format!("Couldn't find ranch with id {}", ranch_id))?;
-        let horses_count = ranch
-            .owner
-            .lock()
-            .await
-            .horses
-            .as_ref()
-            .ok_or("Ranch owner has no horses")?
-            .len();
-        let mut ranch_index = 0;
-        for (idx, ranch_session) in ranch.character_sessions.iter().enumerate() {
-            if ranch_session
-                .lock()
-                .await
-                .character
-                .as_ref()
-                .is_some_and(|c| c.character_id == character_id)
-            {
-                ranch_index = horses_count + idx;
-                break;
-            }
-        }
+        let ranch = get_open_ranch(&server, ranch_id)
+            .ok_or(format!("Couldn't find ranch with id {}", ranch_id))?
+            .lock_owned()
+            .await;
+        let ranch_index = ranch.character_ranch_index(character_id).ok_or(format!(
+            "Character {} is not in ranch {}",
+            character_id, ranch_id
+        ))?;
 
         let response = RanchSnapshotNotify {
-            ranch_index: ranch_index as u16,
+            ranch_index,
             snapshot: command.snapshot.clone(),
         };
 
         // Send to everyone in the ranch except the sender
-        for ranch_session in ranch.character_sessions.iter() {
-            let mut session = ranch_session.lock().await;
-            if session
-                .character
-                .as_ref()
-                .is_some_and(|c| c.character_id != character_id)
-            {
-                session
-                    .send_command(response.clone())
-                    .await
-                    .map_err(|e| format!("Failed to send response: {:?}", e))?;
-            }
-        }
+        broadcast(&ranch.character_sessions, &response, Some(character_id)).await;
         Ok(())
     }
 }
@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::{
+    commands::ranch::register_stallion::{
+        RegisterStallion, RegisterStallionCancel, RegisterStallionOk,
+    },
+    entities::stallion::Stallion,
+    handlers::CommandHandler,
+    impl_packet_handler,
+    server::{Server, Session},
+};
+
+pub const MIN_STALLION_PRICE: u32 = 1;
+pub const MAX_STALLION_PRICE: u32 = 100_000;
+
+pub struct RegisterStallionHandler {}
+impl CommandHandler for RegisterStallionHandler {
+    type CommandType = RegisterStallion;
+    async fn handle_command(
+        server: Arc<Server>,
+        session: Arc<Mutex<Session>>,
+        command: &Self::CommandType,
+    ) -> Result<(), String> {
+        let validation = {
+            let session = session.lock().await;
+            let character = session
+                .character
+                .as_ref()
+                .ok_or("Player has no character")?;
+            let owns_horse = session
+                .horses
+                .as_ref()
+                .ok_or("Character has no horses")?
+                .iter()
+                .any(|h| h.uid == command.horse_uid);
+            if !owns_horse {
+                Err(format!(
+                    "Character {} doesn't own horse {}",
+                    character.character_id, command.horse_uid
+                ))
+            } else if character.mount_uid == command.horse_uid {
+                Err("Can't register the current mount as a stallion".to_owned())
+            } else if !(MIN_STALLION_PRICE..=MAX_STALLION_PRICE).contains(&command.price) {
+                Err(format!("Invalid stallion price {}", command.price))
+            } else {
+                Ok(())
+            }
+        };
+
+        let result = match validation {
+            Ok(()) => {
+                let database = Arc::clone(&server.database);
+                database
+                    .run_in_transaction(async |transaction| {
+                        if transaction.get_stallion(command.horse_uid).await?.is_some() {
+                            return Err(format!(
+                                "Horse {} is already registered as a stallion",
+                                command.horse_uid
+                            )
+                            .into());
+                        }
+                        let stallion = Stallion {
+                            horse_uid: command.horse_uid,
+                            price: command.price,
+                            times_mated: 0,
+                        };
+                        transaction.insert_stallion(&stallion).await
+                    })
+                    .await
+                    .map_err(|e| format!("Failed to register stallion: {}", e))
+            }
+            Err(e) => Err(e),
+        };
+
+        let mut session = session.lock().await;
+        match result {
+            Ok(()) => session
+                .send_command(RegisterStallionOk {
+                    horse_uid: command.horse_uid,
+                })
+                .await
+                .map_err(|e| format!("Failed to send response: {:?}", e)),
+            Err(e) => {
+                session
+                    .send_command(RegisterStallionCancel {})
+                    .await
+                    .map_err(|e| format!("Failed to send response: {:?}", e))?;
+                Err(e)
+            }
+        }
+    }
+}
+impl_packet_handler!(RegisterStallionHandler);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{ranch::unregister_stallion::UnregisterStallion, shared::horse::Horse},
+        handlers::ranch::unregister_stallion::UnregisterStallionHandler,
+        server::{
+            ServerType,
+            testing::{create_character, create_horse, log_in, test_server, test_session},
+        },
+    };
+
+    #[tokio::test]
+    async fn test_register_and_unregister_stallion() {
+        let server = test_server(ServerType::Ranch).await;
+        let (session, _client) = test_session().await;
+        let (character, mount) = create_character(&server.database, "Owner").await;
+        let horse = create_horse(&server.database, character.character_id, Horse::default()).await;
+        log_in(&session, &character, &[mount.clone(), horse.clone()]).await;
+        let register = async |horse_uid, price| {
+            RegisterStallionHandler::handle_command(
+                Arc::clone(&server),
+                Arc::clone(&session),
+                &RegisterStallion { horse_uid, price },
+            )
+            .await
+        };
+        let get_stallion = async |horse_uid| {
+            server
+                .database
+                .run_in_transaction(async |transaction| transaction.get_stallion(horse_uid).await)
+                .await
+                .unwrap()
+        };
+
+        // Mounts and horses of somebody else can't be registered, and neither can free ones
+        assert!(register(mount.uid, 100).await.is_err());
+        assert!(register(horse.uid + 1000, 100).await.is_err());
+        assert!(register(horse.uid, 0).await.is_err());
+        assert!(get_stallion(horse.uid).await.is_none());
+
+        register(horse.uid, 100).await.unwrap();
+        assert_eq!(get_stallion(horse.uid).await.unwrap().price, 100);
+        // Only once
+        assert!(register(horse.uid, 200).await.is_err());
+
+        UnregisterStallionHandler::handle_command(
+            Arc::clone(&server),
+            Arc::clone(&session),
+            &UnregisterStallion {
+                horse_uid: horse.uid,
+            },
+        )
+        .await
+        .unwrap();
+        assert!(get_stallion(horse.uid).await.is_none());
+    }
+}
@@ -13,7 +13,7 @@ pub struct RequestNpcDressListHandler {}
 impl CommandHandler for RequestNpcDressListHandler {
     type CommandType = RequestNpcDressList;
     async fn handle_command(
-        _server: Arc<Mutex<Server>>,
+        _server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         command: &Self::CommandType,
     ) -> Result<(), String> {
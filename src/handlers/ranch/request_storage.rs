@@ -13,7 +13,7 @@ pub struct RequestStorageHandler {}
 impl CommandHandler for RequestStorageHandler {
     type CommandType = RequestStorage;
     async fn handle_command(
-        _server: Arc<Mutex<Server>>,
+        _server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         command: &Self::CommandType,
     ) -> Result<(), String> {
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{ffi::CString, sync::Arc};
 
 use tokio::sync::Mutex;
 
@@ -6,43 +6,83 @@ use crate::{
     commands::{
         LengthPrefixedVec,
         ranch::search_stallion::{SearchStallion, SearchStallionOk, Stallion},
-        shared::horse::{Appearance, Parts, Stats},
     },
+    database::horse::StallionFilter,
     handlers::CommandHandler,
     impl_packet_handler,
     server::{Server, Session},
 };
 
+/// Maximum amount of stallions sent in a single search result.
+pub const STALLION_SEARCH_LIMIT: u32 = 50;
+
 pub struct SearchStallionHandler {}
 impl CommandHandler for SearchStallionHandler {
     type CommandType = SearchStallion;
     async fn handle_command(
-        _server: Arc<Mutex<Server>>,
+        server: Arc<Server>,
         session: Arc<Mutex<Session>>,
-        _command: &Self::CommandType,
+        command: &Self::CommandType,
     ) -> Result<(), String> {
-        // TODO: Fetch from DB
+        let character_id = session
+            .lock()
+            .await
+            .character
+            .as_ref()
+            .ok_or("Player has no character")?
+            .character_id;
+
+        // TODO: Figure out the rest of the search criteria
+        let [skin_ids, mane_ids, tail_ids] = &command.unk9;
+        let filter = StallionFilter {
+            exclude_character_id: character_id,
+            skin_ids: skin_ids.vec.clone(),
+            mane_ids: mane_ids.vec.clone(),
+            tail_ids: tail_ids.vec.clone(),
+            limit: STALLION_SEARCH_LIMIT,
+        };
+
+        let database = Arc::clone(&server.database);
+        let (listings, records) = database
+            .run_in_transaction(async |transaction| {
+                let listings = transaction.search_stallions(&filter).await?;
+                let uids = listings.iter().map(|l| l.horse.uid).collect::<Vec<u32>>();
+                let records = transaction.get_breeding_records(&uids).await?;
+                Ok((listings, records))
+            })
+            .await
+            .map_err(|e| format!("Failed to search stallions: {}", e))?;
+
+        let mut stallions = Vec::with_capacity(listings.len());
+        for listing in listings {
+            // Stallions that weren't bred themselves have no coat bonus
+            let coat_bonus = records
+                .iter()
+                .find(|r| r.horse_uid == listing.horse.uid)
+                .map_or(0, |r| r.coat_bonus);
+            stallions.push(Stallion {
+                unk0: CString::new(listing.owner_nickname)
+                    .map_err(|e| format!("Failed to convert nickname to CString: {}", e))?,
+                uid: listing.horse.uid,
+                tid: listing.horse.tid,
+                name: listing.horse.name,
+                grade: listing.horse.grade,
+                chance: 0,
+                price: listing.stallion.price,
+                unk7: 0xFFFFFFFF,
+                unk8: 0xFFFFFFFF,
+                stats: listing.horse.stats,
+                parts: listing.horse.parts,
+                appearance: listing.horse.appearance,
+                unk11: 5,
+                coat_bonus,
+            });
+        }
+
         let response = SearchStallionOk {
             unk0: 0,
             unk1: 0,
-            stallions: LengthPrefixedVec {
-                vec: vec![Stallion {
-                    unk0: c"Unk0".to_owned(),
-                    uid: 0x3004e21,
-                    tid: 20001,
-                    name: c"Name".to_owned(),
-                    grade: 4,
-                    chance: 0,
-                    price: 1,
-                    unk7: 0xFFFFFFFF,
-                    unk8: 0xFFFFFFFF,
-                    stats: Stats::default(),
-                    parts: Parts::random(),
-                    appearance: Appearance::default(),
-                    unk11: 5,
-                    coat_bonus: 0,
-                }],
-            },
+            stallions: LengthPrefixedVec { vec: stallions },
         };
         session
             .lock()
@@ -53,3 +93,75 @@ impl CommandHandler for SearchStallionHandler {
     }
 }
 impl_packet_handler!(SearchStallionHandler);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::shared::horse::Horse,
+        entities::stallion::Stallion as StallionEntity,
+        genetics::{Foal, Parentage},
+        server::{
+            ServerType,
+            testing::{
+                create_character, create_horse, log_in, received_packets, test_server, test_session,
+            },
+        },
+    };
+
+    #[tokio::test]
+    async fn test_stallions_show_their_coat_bonus() {
+        let server = test_server(ServerType::Ranch).await;
+        let (owner, sire) = create_character(&server.database, "Owner").await;
+        let (searcher, searcher_mount) = create_character(&server.database, "Searcher").await;
+        let dam = create_horse(&server.database, owner.character_id, Horse::default()).await;
+        let foal = create_horse(&server.database, owner.character_id, Horse::default()).await;
+        server
+            .database
+            .run_in_transaction(async |transaction| {
+                transaction
+                    .insert_lineage(&Foal {
+                        horse: foal.clone(),
+                        coat_bonus: 3,
+                        parentage: Parentage {
+                            sire_uid: sire.uid,
+                            dam_uid: dam.uid,
+                        },
+                    })
+                    .await?;
+                for horse_uid in [dam.uid, foal.uid] {
+                    transaction
+                        .insert_stallion(&StallionEntity {
+                            horse_uid,
+                            price: 100,
+                            times_mated: 0,
+                        })
+                        .await?;
+                }
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        let (session, client) = test_session().await;
+        log_in(&session, &searcher, &[searcher_mount]).await;
+        SearchStallionHandler::handle_command(
+            Arc::clone(&server),
+            session,
+            &SearchStallion::default(),
+        )
+        .await
+        .unwrap();
+        let (mut client, _) = client.into_split();
+        let packets = received_packets(&mut client).await;
+        let response = SearchStallionOk::try_from(&packets[0]).unwrap();
+        let coat_bonuses = response
+            .stallions
+            .vec
+            .iter()
+            .map(|s| (s.uid, s.coat_bonus))
+            .collect::<Vec<(u32, u8)>>();
+        // Only the foal was bred, the dam was there from the start
+        assert_eq!(coat_bonuses, vec![(dam.uid, 0), (foal.uid, 3)]);
+    }
+}
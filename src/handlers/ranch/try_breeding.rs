@@ -1,13 +1,16 @@
 use std::sync::Arc;
 
+use rand::Rng;
 use tokio::sync::Mutex;
 
 use crate::{
     commands::{
-        ranch::try_breeding::{TryBreeding, TryBreedingOk},
-        shared::horse::{Appearance, Horse, Mastery, Parts, Stats, Vals0, Vals1},
+        ranch::try_breeding::{TryBreeding, TryBreedingCancel, TryBreedingOk},
+        shared::{alicia_time::AliciaTime, horse::Parts},
     },
-    database::horse::insert_horse,
+    database::wallet::transfer_breeding_fee,
+    entities::tracked::Tracked,
+    genetics::{breed, check_eligibility, check_sire_eligibility, draw_failure_cards},
     handlers::CommandHandler,
     impl_packet_handler,
     server::{Server, Session},
@@ -17,87 +20,166 @@ pub struct TryBreedingHandler {}
 impl CommandHandler for TryBreedingHandler {
     type CommandType = TryBreeding;
     async fn handle_command(
-        server: Arc<Mutex<Server>>,
+        server: Arc<Server>,
         session: Arc<Mutex<Session>>,
-        _command: &Self::CommandType,
+        command: &Self::CommandType,
     ) -> Result<(), String> {
-        // TODO: Substract carrots from self, add carrots to the other player, update breeding count, remove listing, god knows how many other things...
+        let (character_id, mount_uid, dam) = {
+            let session = session.lock().await;
+            let character = session
+                .character
+                .as_ref()
+                .ok_or("Player has no character")?;
+            let (character_id, mount_uid) = (character.character_id, character.mount_uid);
+            let dam = session
+                .horses
+                .as_ref()
+                .ok_or("Character has no horses")?
+                .iter()
+                .find(|h| h.uid == command.own_horse_uid)
+                .cloned()
+                .ok_or(format!(
+                    "Couldn't find horse with uid {}",
+                    command.own_horse_uid
+                ))?;
+            (character_id, mount_uid, dam)
+        };
+
+        let (database, rules, failure_rules, eligibility_rules) = (
+            Arc::clone(&server.database),
+            server.settings.genetics.clone(),
+            server.settings.breeding_failure.clone(),
+            server.settings.breeding_eligibility.clone(),
+        );
 
-        let character_id = session
-            .lock()
+        let stallion = database
+            .run_in_transaction(async |transaction| {
+                transaction
+                    .get_stallion_listing(command.other_horse_uid)
+                    .await
+            })
             .await
-            .character
-            .as_ref()
-            .ok_or("Player has no character")?
-            .character_id;
-        let mut new_horse = Horse {
-            uid: 0, // Will be set by the database
-            tid: 20001,
-            name: c"".to_owned(), // Will be set in the update mount nickname handler
-            parts: Parts::random(),
-            appearance: Appearance::default(),
-            stats: Stats::default(),
-            rating: 0,
-            class: 21,
-            class_progress: 1,
-            grade: 5,
-            growth_points: 0,
-            vals0: Vals0 {
-                stamina: 65535,
-                attractiveness: 65535,
-                hunger: 65535,
-                val0: 0,
-                val1: 1000,
-                val2: 0,
-                val3: 0,
-                val4: 0,
-                val5: 1000,
-                val6: 30,
-                val7: 10,
-                val8: 10,
-                val9: 10,
-                val10: 0,
-            },
-            vals1: Vals1 {
-                val0: 0,
-                val1: 0,
-                date_of_birth: 3097585636,
-                val3: 2,
-                val4: 0,
-                class_progression: 255,
-                val5: 0,
-                potential_level: 0,
-                has_potential: 0,
-                potential_value: 255,
-                val9: 0,
-                luck: 4,
-                has_luck: 0,
-                val12: 0,
-                fatigue: 0,
-                val14: 0,
-                emblem: 1,
-            },
-            mastery: Mastery {
-                spur_magic_count: 510,
-                jump_count: 1057,
-                sliding_time: 1528,
-                gliding_distance: 53156,
-            },
-            val16: 3097585636,
-            val17: 0,
+            .map_err(|e| format!("Failed to fetch stallion: {}", e))?;
+        let Some(stallion) = stallion else {
+            session
+                .lock()
+                .await
+                .send_command(TryBreedingCancel::default())
+                .await
+                .map_err(|e| format!("Failed to send response: {:?}", e))?;
+            return Err(format!(
+                "Horse {} is not registered as a stallion",
+                command.other_horse_uid
+            ));
         };
 
-        {
-            let server = server.lock().await;
-            let mut database = server.database.lock().await;
-            database
-                .run_in_transaction(async |transaction| {
-                    insert_horse(transaction, character_id, &mut new_horse).await
-                })
+        // The thread's rng can't be kept across awaits
+        let (failure_cards, mut foal) = {
+            let mut rng = rand::rng();
+            // A failed attempt still costs the fee, but the player gets to pick a card as compensation
+            let failure_cards = rng
+                .random_bool(failure_rules.failure_chance.clamp(0.0, 1.0))
+                .then(|| draw_failure_cards(&failure_rules, &mut rng));
+            let foal = breed(&dam, &stallion.horse, &rules, &mut rng);
+            (failure_cards, foal)
+        };
+        let now = AliciaTime::now();
+        foal.horse.vals1.date_of_birth = now.into();
+
+        // Charging the breeder, paying the stallion owner and creating the foal, or storing the
+        // cards of a failed attempt, must either all happen or not happen at all. Both parents are checked before any of it, against
+        // the stallion as it is now rather than when it was looked up
+        let result = database
+            .run_in_transaction(async |transaction| {
+                let stallion = transaction
+                    .get_stallion_listing(stallion.stallion.horse_uid)
+                    .await?
+                    .ok_or(format!(
+                        "Horse {} is no longer registered as a stallion",
+                        stallion.stallion.horse_uid
+                    ))?;
+                let records = transaction
+                    .get_breeding_records(&[dam.uid, stallion.horse.uid])
+                    .await?;
+                let record = |uid: u32| {
+                    records
+                        .iter()
+                        .find(|r| r.horse_uid == uid)
+                        .ok_or(format!("Couldn't find horse with uid {}", uid))
+                };
+                check_eligibility(&dam, record(dam.uid)?, mount_uid, &eligibility_rules, &now)?;
+                check_sire_eligibility(
+                    &stallion.horse,
+                    record(stallion.horse.uid)?,
+                    &eligibility_rules,
+                    &now,
+                )?;
+                // Attempts are rejected up front with a full stable, even if they would fail
+                let mount_slots = transaction
+                    .get_character_by_id(character_id)
+                    .await?
+                    .ok_or(format!("Couldn't find character {}", character_id))?
+                    .mount_slots;
+                if transaction
+                    .count_horses_by_character_id(character_id)
+                    .await?
+                    >= mount_slots
+                {
+                    return Err(
+                        format!("Character {} has no free mount slots", character_id).into(),
+                    );
+                }
+                let wallet = if stallion.owner_character_id == character_id {
+                    transaction.get_wallet_for_update(character_id).await?
+                } else {
+                    transfer_breeding_fee(
+                        transaction,
+                        character_id,
+                        stallion.owner_character_id,
+                        stallion.stallion.price,
+                    )
+                    .await?
+                };
+                transaction
+                    .record_stallion_mating(stallion.stallion.horse_uid)
+                    .await?;
+                if let Some(failure_cards) = &failure_cards {
+                    transaction
+                        .set_failure_cards(character_id, failure_cards)
+                        .await?;
+                } else {
+                    transaction
+                        .insert_horse(character_id, &mut foal.horse)
+                        .await?;
+                    transaction.insert_lineage(&foal).await?;
+                }
+                Ok(wallet)
+            })
+            .await;
+        let wallet = match result {
+            Ok(wallet) => wallet,
+            Err(e) => {
+                session
+                    .lock()
+                    .await
+                    .send_command(TryBreedingCancel::default())
+                    .await
+                    .map_err(|e| format!("Failed to send response: {:?}", e))?;
+                return Err(format!("Failed to breed: {}", e));
+            }
+        };
+
+        if failure_cards.is_some() {
+            // TODO: Find out how the client is told about the failure and the carrots it cost
+            return session
+                .lock()
                 .await
-                .map_err(|e| format!("Failed to insert horse: {}", e).to_owned())?;
+                .send_command(TryBreedingCancel::default())
+                .await
+                .map_err(|e| format!("Failed to send response: {:?}", e));
         }
 
+        let new_horse = foal.horse;
         let response = TryBreedingOk {
             uid: new_horse.uid,
             tid: new_horse.tid,
@@ -108,15 +190,17 @@ impl CommandHandler for TryBreedingHandler {
             },
             appearance: new_horse.appearance.clone(),
             stats: new_horse.stats.clone(),
+            updated_carrot_count: wallet.carrots,
             ..Default::default()
         };
 
         let mut session = session.lock().await;
+        session.pending_foal_uid = Some(new_horse.uid);
         session
             .horses
             .as_mut()
             .ok_or("Character has no horses")?
-            .push(new_horse);
+            .push(Tracked::stored(new_horse));
         session
             .send_command(response)
             .await
@@ -124,3 +208,168 @@ impl CommandHandler for TryBreedingHandler {
     }
 }
 impl_packet_handler!(TryBreedingHandler);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{
+            ranch::breeding_failure_card_choose::BreedingFailureCardChoose, shared::horse::Horse,
+        },
+        database::wallet::INITIAL_CARROTS,
+        entities::stallion::Stallion,
+        handlers::ranch::breeding_failure_card_choose::BreedingFailureCardChooseHandler,
+        server::{
+            ServerType,
+            testing::{
+                create_character, create_horse, log_in, test_server_with_settings, test_session,
+            },
+        },
+        settings::Settings,
+    };
+
+    #[tokio::test]
+    async fn test_breeding_charges_for_the_foal() {
+        let mut settings = Settings::default();
+        settings.breeding_failure.failure_chance = 0.0;
+        let server = test_server_with_settings(ServerType::Ranch, settings).await;
+        let (session, _client) = test_session().await;
+        let (owner, _) = create_character(&server.database, "Owner").await;
+        let (breeder, mount) = create_character(&server.database, "Breeder").await;
+        let fit = || Horse {
+            grade: 5,
+            ..Default::default()
+        };
+        let dam = create_horse(&server.database, breeder.character_id, fit()).await;
+        let sire = create_horse(&server.database, owner.character_id, fit()).await;
+        let unfit_sire = create_horse(
+            &server.database,
+            owner.character_id,
+            Horse {
+                grade: 1,
+                ..Default::default()
+            },
+        )
+        .await;
+        server
+            .database
+            .run_in_transaction(async |transaction| {
+                for horse_uid in [sire.uid, unfit_sire.uid] {
+                    transaction
+                        .insert_stallion(&Stallion {
+                            horse_uid,
+                            price: 100,
+                            times_mated: 0,
+                        })
+                        .await?;
+                }
+                Ok(())
+            })
+            .await
+            .unwrap();
+        log_in(&session, &breeder, &[mount, dam.clone()]).await;
+        let try_breeding = async |other_horse_uid| {
+            TryBreedingHandler::handle_command(
+                Arc::clone(&server),
+                Arc::clone(&session),
+                &TryBreeding {
+                    own_horse_uid: dam.uid,
+                    other_horse_uid,
+                },
+            )
+            .await
+        };
+        let wallet = async |character_id| {
+            server
+                .database
+                .run_in_transaction(async |transaction| {
+                    transaction.get_wallet_for_update(character_id).await
+                })
+                .await
+                .unwrap()
+        };
+
+        // Nothing is charged for a sire that can't be bred
+        assert!(try_breeding(unfit_sire.uid).await.is_err());
+        assert_eq!(wallet(breeder.character_id).await.carrots, INITIAL_CARROTS);
+        assert!(session.lock().await.pending_foal_uid.is_none());
+
+        try_breeding(sire.uid).await.unwrap();
+        assert_eq!(
+            wallet(breeder.character_id).await.carrots,
+            INITIAL_CARROTS - 100
+        );
+        assert_eq!(wallet(owner.character_id).await.breeding_earnings, 100);
+        let foal_uid = session.lock().await.pending_foal_uid.unwrap();
+        let foal = server
+            .database
+            .run_in_transaction(async |transaction| transaction.get_horse_by_uid(foal_uid).await)
+            .await
+            .unwrap();
+        assert!(foal.is_some());
+        assert!(
+            session
+                .lock()
+                .await
+                .horses
+                .as_ref()
+                .unwrap()
+                .iter()
+                .any(|h| h.uid == foal_uid)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failure_cards_outlive_the_session() {
+        let mut settings = Settings::default();
+        settings.breeding_failure.failure_chance = 1.0;
+        let server = test_server_with_settings(ServerType::Ranch, settings).await;
+        let (owner, _) = create_character(&server.database, "Owner").await;
+        let (breeder, mount) = create_character(&server.database, "Breeder").await;
+        let fit = || Horse {
+            grade: 5,
+            ..Default::default()
+        };
+        let dam = create_horse(&server.database, breeder.character_id, fit()).await;
+        let sire = create_horse(&server.database, owner.character_id, fit()).await;
+        server
+            .database
+            .run_in_transaction(async |transaction| {
+                transaction
+                    .insert_stallion(&Stallion {
+                        horse_uid: sire.uid,
+                        price: 100,
+                        times_mated: 0,
+                    })
+                    .await
+            })
+            .await
+            .unwrap();
+
+        {
+            let (session, _client) = test_session().await;
+            log_in(&session, &breeder, &[mount.clone(), dam.clone()]).await;
+            TryBreedingHandler::handle_command(
+                Arc::clone(&server),
+                session,
+                &TryBreeding {
+                    own_horse_uid: dam.uid,
+                    other_horse_uid: sire.uid,
+                },
+            )
+            .await
+            .unwrap();
+        }
+
+        // The attempt was paid for, so the cards are still there after reconnecting
+        let (session, _client) = test_session().await;
+        log_in(&session, &breeder, &[mount, dam]).await;
+        BreedingFailureCardChooseHandler::handle_command(
+            Arc::clone(&server),
+            session,
+            &BreedingFailureCardChoose { position: 0 },
+        )
+        .await
+        .unwrap();
+    }
+}
@@ -0,0 +1,59 @@
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::{
+    commands::ranch::unregister_stallion::{
+        UnregisterStallion, UnregisterStallionCancel, UnregisterStallionOk,
+    },
+    handlers::CommandHandler,
+    impl_packet_handler,
+    server::{Server, Session},
+};
+
+pub struct UnregisterStallionHandler {}
+impl CommandHandler for UnregisterStallionHandler {
+    type CommandType = UnregisterStallion;
+    async fn handle_command(
+        server: Arc<Server>,
+        session: Arc<Mutex<Session>>,
+        command: &Self::CommandType,
+    ) -> Result<(), String> {
+        let owns_horse = session
+            .lock()
+            .await
+            .horses
+            .as_ref()
+            .ok_or("Character has no horses")?
+            .iter()
+            .any(|h| h.uid == command.horse_uid);
+
+        let result = if owns_horse {
+            let database = Arc::clone(&server.database);
+            database
+                .run_in_transaction(async |transaction| {
+                    transaction.remove_stallion(command.horse_uid).await
+                })
+                .await
+                .map_err(|e| format!("Failed to unregister stallion: {}", e))
+        } else {
+            Err(format!("Player doesn't own horse {}", command.horse_uid))
+        };
+
+        let mut session = session.lock().await;
+        match result {
+            Ok(()) => session
+                .send_command(UnregisterStallionOk {})
+                .await
+                .map_err(|e| format!("Failed to send response: {:?}", e)),
+            Err(e) => {
+                session
+                    .send_command(UnregisterStallionCancel {})
+                    .await
+                    .map_err(|e| format!("Failed to send response: {:?}", e))?;
+                Err(e)
+            }
+        }
+    }
+}
+impl_packet_handler!(UnregisterStallionHandler);
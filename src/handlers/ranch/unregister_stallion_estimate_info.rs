@@ -0,0 +1,52 @@
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::{
+    commands::ranch::unregister_stallion_estimate_info::{
+        UnregisterStallionEstimateInfo, UnregisterStallionEstimateInfoCancel,
+        UnregisterStallionEstimateInfoOk,
+    },
+    handlers::CommandHandler,
+    impl_packet_handler,
+    server::{Server, Session},
+};
+
+pub struct UnregisterStallionEstimateInfoHandler {}
+impl CommandHandler for UnregisterStallionEstimateInfoHandler {
+    type CommandType = UnregisterStallionEstimateInfo;
+    async fn handle_command(
+        server: Arc<Server>,
+        session: Arc<Mutex<Session>>,
+        command: &Self::CommandType,
+    ) -> Result<(), String> {
+        let database = Arc::clone(&server.database);
+        let stallion = database
+            .run_in_transaction(async |transaction| {
+                transaction.get_stallion(command.horse_uid).await
+            })
+            .await
+            .map_err(|e| format!("Failed to fetch stallion: {}", e))?;
+
+        let mut session = session.lock().await;
+        match stallion {
+            Some(stallion) => session
+                .send_command(UnregisterStallionEstimateInfoOk {
+                    times_mated: stallion.times_mated,
+                    mating_compensation: stallion.times_mated.saturating_mul(stallion.price),
+                    mating_price: stallion.price,
+                    ..Default::default()
+                })
+                .await
+                .map_err(|e| format!("Failed to send response: {:?}", e)),
+            None => {
+                session
+                    .send_command(UnregisterStallionEstimateInfoCancel {})
+                    .await
+                    .map_err(|e| format!("Failed to send response: {:?}", e))?;
+                Err(format!("Horse {} is not a stallion", command.horse_uid))
+            }
+        }
+    }
+}
+impl_packet_handler!(UnregisterStallionEstimateInfoHandler);
@@ -3,8 +3,9 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 
 use crate::{
-    commands::ranch::update_mount_nickname::{UpdateMountNickname, UpdateMountNicknameOk},
-    database::horse::update_horse,
+    commands::ranch::update_mount_nickname::{
+        UpdateMountNickname, UpdateMountNicknameCancel, UpdateMountNicknameOk,
+    },
     handlers::CommandHandler,
     impl_packet_handler,
     server::{Server, Session},
@@ -14,30 +15,52 @@ pub struct UpdateMountNicknameHandler {}
 impl CommandHandler for UpdateMountNicknameHandler {
     type CommandType = UpdateMountNickname;
     async fn handle_command(
-        server: Arc<Mutex<Server>>,
+        server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         command: &Self::CommandType,
     ) -> Result<(), String> {
+        let old_name = {
+            let mut session = session.lock().await;
+            let horses = session.horses.as_mut().ok_or("Character has no horses")?;
+            let horse = horses
+                .iter_mut()
+                .find(|h| h.uid == command.uid)
+                .ok_or(format!("Couldn't find horse with uid {}", command.uid).to_owned())?;
+            std::mem::replace(&mut horse.name, command.nickname.to_owned())
+        };
+
+        // The player is only told about the new name once it's stored
+        let flush_result = Session::flush(&session, &server.database).await;
         let mut session = session.lock().await;
-        let horses = session.horses.as_mut().ok_or("Character has no horses")?;
-        let horse = horses
+        let horse = session
+            .horses
             .iter_mut()
+            .flatten()
             .find(|h| h.uid == command.uid)
-            .ok_or(format!("Couldn't find horse with uid {}", command.uid).to_owned())?;
-        horse.name = command.nickname.to_owned();
-
-        let server = server.lock().await;
-        let mut database = server.database.lock().await;
-        database
-            .run_in_transaction(async |transaction| update_horse(transaction, horse).await)
-            .await
-            .map_err(|e| format!("Couldn't update horse: {}", e).to_owned())?;
+            .ok_or(format!("Couldn't find horse with uid {}", command.uid))?;
+        if let Err(e) = flush_result
+            && horse.is_dirty()
+        {
+            horse.name = old_name;
+            session
+                .send_command(UpdateMountNicknameCancel::default())
+                .await
+                .map_err(|e| format!("Failed to send response: {:?}", e))?;
+            return Err(format!(
+                "Failed to store nickname of horse {}: {}",
+                command.uid, e
+            ));
+        }
 
         let response = UpdateMountNicknameOk {
             uid: horse.uid,
             nickname: horse.name.to_owned(),
             ..Default::default() // TODO
         };
+        // Naming a foal means the player decided to keep it
+        if session.pending_foal_uid == Some(command.uid) {
+            session.pending_foal_uid = None;
+        }
         session
             .send_command(response)
             .await
@@ -3,10 +3,10 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 
 use crate::{
-    commands::ranch::wear_equipment::{WearEquipment, WearEquipmentOk},
-    database::character::update_character,
+    commands::ranch::wear_equipment::{WearEquipment, WearEquipmentCancel, WearEquipmentOk},
     handlers::CommandHandler,
     impl_packet_handler,
+    ranch::{broadcast, get_open_ranch},
     server::{Server, Session},
 };
 
@@ -14,26 +14,40 @@ pub struct WearEquipmentHandler {}
 impl CommandHandler for WearEquipmentHandler {
     type CommandType = WearEquipment;
     async fn handle_command(
-        server: Arc<Mutex<Server>>,
+        server: Arc<Server>,
         session: Arc<Mutex<Session>>,
         command: &Self::CommandType,
     ) -> Result<(), String> {
-        let server = server.lock().await;
         {
+            let old_mount_uid = {
+                let mut session = session.lock().await;
+                let character = session
+                    .character
+                    .as_mut()
+                    .ok_or("Player has no character")?;
+                std::mem::replace(&mut character.mount_uid, command.item_uid)
+            };
+
+            // Nobody is told about the change until it's stored
+            let flush_result = Session::flush(&session, &server.database).await;
             let mut session = session.lock().await;
             let character = session
                 .character
                 .as_mut()
                 .ok_or("Player has no character")?;
-            character.mount_uid = command.item_uid;
-
-            let mut database = server.database.lock().await;
-            database
-                .run_in_transaction(async |transaction| {
-                    update_character(transaction, character).await
-                })
-                .await
-                .map_err(|e| format!("Failed to update mount: {}", e))?;
+            if let Err(e) = flush_result
+                && character.is_dirty()
+            {
+                character.mount_uid = old_mount_uid;
+                session
+                    .send_command(WearEquipmentCancel {
+                        unk0: command.item_uid,
+                        unk1: command.member,
+                    })
+                    .await
+                    .map_err(|e| format!("Failed to send response: {:?}", e))?;
+                return Err(format!("Failed to store equipment change: {}", e));
+            }
         }
 
         let response = WearEquipmentOk {
@@ -42,20 +56,13 @@ impl CommandHandler for WearEquipmentHandler {
         };
 
         let ranch_uid = session.lock().await.ranch_id.ok_or("Player has no ranch")?;
-        for ranch_session in server
-            .ranches
-            .get(&ranch_uid)
+        let ranch_sessions = get_open_ranch(&server, ranch_uid)
             .ok_or(format!("Ranch {} doesn't exist", ranch_uid))?
+            .lock()
+            .await
             .character_sessions
-            .as_slice()
-        {
-            ranch_session
-                .lock()
-                .await
-                .send_command(response.clone())
-                .await
-                .map_err(|e| format!("Failed to send response: {:?}", e))?;
-        }
+            .clone();
+        broadcast(&ranch_sessions, &response, None).await;
         Ok(())
     }
 }
@@ -1,20 +1,21 @@
 mod commands;
 mod database;
 mod entities;
+mod genetics;
 mod handlers;
 mod packet;
 mod ranch;
 mod server;
 mod settings;
 
-use tokio::{signal, sync::Mutex};
+use tokio::signal;
 
 use std::{error::Error, fs::File, str::FromStr, sync::Arc};
 
 use crate::{
     database::{Database, init_database},
     server::{Server, ServerType},
-    settings::Settings,
+    settings::{DatabaseBackend, Settings},
 };
 
 #[tokio::main]
@@ -39,13 +40,45 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     // Set up database
     println!("Setting up database");
-    let (embedded_psql, connection_url) = init_database(&settings.database).await?;
-    println!("Connected to database on {}", connection_url);
+    let (embedded_psql, database) = match settings.database.backend {
+        DatabaseBackend::Postgres => {
+            let (embedded_psql, connection_url) = init_database(&settings.database).await?;
+            println!("Connected to database on {}", connection_url);
 
-    let pg_config = tokio_postgres::Config::from_str(&connection_url)?;
-    let database = Arc::new(Mutex::new(
-        Database::new(&settings.database, pg_config).await?,
-    ));
+            let pg_config = tokio_postgres::Config::from_str(&connection_url)?;
+            let database = Database::new(&settings.database, pg_config).await?;
+            (embedded_psql, database)
+        }
+        #[cfg(not(feature = "dev"))]
+        DatabaseBackend::Memory => {
+            return Err(
+                "The in-memory database is only available when the server is built \
+                 with the dev feature. Set database.backend in the settings to postgres or sqlite"
+                    .into(),
+            );
+        }
+        #[cfg(feature = "dev")]
+        DatabaseBackend::Memory => {
+            // Meant for trying the server out, nobody should end up running it like this
+            eprintln!(
+                "WARNING: Using the in-memory database. Accounts, characters and horses will \
+                 be lost when the server stops. Set database.backend in the settings to keep them"
+            );
+            (None, Database::in_memory())
+        }
+        DatabaseBackend::Sqlite => {
+            println!("Using database file {}", settings.database.sqlite_path);
+            (None, Database::new_sqlite(&settings.database).await?)
+        }
+    };
+    let database = Arc::new(database);
+    if settings.database.migrations_dry_run {
+        println!("Migrations dry run finished, not starting servers");
+        if let Some(embedded_psql) = embedded_psql {
+            embedded_psql.stop().await?;
+        }
+        return Ok(());
+    }
 
     // Set up servers.
     let lobby_server = if settings.lobby_server.enabled {
@@ -73,11 +106,16 @@ async fn main() -> Result<(), Box<dyn Error>> {
     }
 
     // TODO: Move these to Drop traits? Maybe not a good idea
-    if let Some(lobby_server) = lobby_server {
-        lobby_server.lock().await.stop().await?;
+    // A server that fails to stop in time shouldn't keep the others from stopping
+    if let Some(lobby_server) = lobby_server
+        && let Err(e) = lobby_server.stop().await
+    {
+        eprintln!("Failed to stop lobby server: {}", e);
     }
-    if let Some(ranch_server) = ranch_server {
-        ranch_server.lock().await.stop().await?;
+    if let Some(ranch_server) = ranch_server
+        && let Err(e) = ranch_server.stop().await
+    {
+        eprintln!("Failed to stop ranch server: {}", e);
     }
 
     if let Some(embedded_psql) = embedded_psql {
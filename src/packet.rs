@@ -5,7 +5,7 @@ use std::vec;
 use deku::ctx::ReadExact;
 use deku::prelude::*;
 use tokio::io::AsyncReadExt;
-use tokio::net::TcpStream;
+use tokio::net::tcp::OwnedReadHalf;
 
 // A constant buffer size for message magic.
 // The maximum size of message payload is 4092 bytes.
@@ -51,7 +51,7 @@ pub struct Packet {
     pub payload: Vec<u8>,
 }
 impl Packet {
-    pub async fn from_stream(buf: &mut [u8], stream: &mut TcpStream) -> Result<Packet, String> {
+    pub async fn from_stream(buf: &mut [u8], stream: &mut OwnedReadHalf) -> Result<Packet, String> {
         let result = stream.read_exact(&mut buf[0..MAGIC_SIZE]).await;
         if result.is_err() || result.is_ok_and(|n| n == 0) {
             return Err("Failed to read command magic from stream".into());
@@ -795,6 +795,12 @@ pub enum CommandId {
     AcCmdCLEnterRoomQuickStop = 0x1f1,
 }
 impl CommandId {
+    /// Commands that are superseded by the next one of the same kind, so they can be skipped
+    /// when a client can't keep up
+    pub fn droppable(&self) -> bool {
+        matches!(self, CommandId::AcCmdCRRanchSnapshotNotify)
+    }
+
     pub fn muted(&self) -> bool {
         matches!(
             self,
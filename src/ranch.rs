@@ -1,11 +1,375 @@
-use std::sync::Arc;
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
 
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, OwnedMutexGuard};
 
-use crate::server::Session;
+use crate::{
+    commands::{Command, ranch::leave_ranch::LeaveRanchNotify, shared::horse::Horse},
+    entities,
+    server::{Server, Session},
+};
 
+/// Hands out the indices clients use to refer to horses and characters in a ranch,
+/// starting at 1. Released indices are handed out again, lowest first.
+#[derive(Debug, Default)]
+pub struct RanchIndexAllocator {
+    last: u16,
+    released: BTreeSet<u16>,
+}
+impl RanchIndexAllocator {
+    pub fn allocate(&mut self) -> u16 {
+        if let Some(index) = self.released.pop_first() {
+            return index;
+        }
+        self.last += 1;
+        self.last
+    }
+
+    pub fn release(&mut self, index: u16) {
+        if index != 0 && index <= self.last {
+            self.released.insert(index);
+        }
+    }
+}
+
+/// A ranch open in the server, with at least one character in it.
 pub struct Ranch {
-    pub name: String,
-    pub owner: Arc<Mutex<Session>>,
+    pub info: entities::ranch::Ranch,
+    /// Horses of the owner kept in the ranch, which are all of them except for the mount.
+    /// Refreshed every time a character enters.
+    pub horses: Vec<Horse>,
     pub character_sessions: Vec<Arc<Mutex<Session>>>,
+
+    index_allocator: RanchIndexAllocator,
+    horse_indices: HashMap<u32, u16>,
+    character_indices: HashMap<u32, u16>,
+    /// Set when the last character leaves and the ranch stops being open
+    closed: bool,
+}
+impl Ranch {
+    pub fn new(info: entities::ranch::Ranch) -> Self {
+        Ranch {
+            info,
+            horses: vec![],
+            character_sessions: vec![],
+            index_allocator: RanchIndexAllocator::default(),
+            horse_indices: HashMap::new(),
+            character_indices: HashMap::new(),
+            closed: false,
+        }
+    }
+
+    /// Replaces the ranch horses. Horses that were already in the ranch keep their index.
+    pub fn set_horses(&mut self, horses: Vec<Horse>) {
+        let gone = self
+            .horse_indices
+            .keys()
+            .filter(|uid| !horses.iter().any(|h| h.uid == **uid))
+            .copied()
+            .collect::<Vec<u32>>();
+        for uid in gone {
+            if let Some(index) = self.horse_indices.remove(&uid) {
+                self.index_allocator.release(index);
+            }
+        }
+        for horse in horses.iter() {
+            if !self.horse_indices.contains_key(&horse.uid) {
+                let index = self.index_allocator.allocate();
+                self.horse_indices.insert(horse.uid, index);
+            }
+        }
+        self.horses = horses;
+    }
+
+    /// Adds the character to the ranch, returning the index it's known by in there.
+    pub fn add_character(&mut self, character_id: u32, session: Arc<Mutex<Session>>) -> u16 {
+        let index = *self
+            .character_indices
+            .entry(character_id)
+            .or_insert_with(|| self.index_allocator.allocate());
+        self.character_sessions.push(session);
+        index
+    }
+
+    pub fn remove_character(&mut self, character_id: u32, session: &Arc<Mutex<Session>>) {
+        self.character_sessions.retain(|s| !Arc::ptr_eq(s, session));
+        if let Some(index) = self.character_indices.remove(&character_id) {
+            self.index_allocator.release(index);
+        }
+    }
+
+    pub fn horse_ranch_index(&self, horse_uid: u32) -> Option<u16> {
+        self.horse_indices.get(&horse_uid).copied()
+    }
+
+    pub fn character_ranch_index(&self, character_id: u32) -> Option<u16> {
+        self.character_indices.get(&character_id).copied()
+    }
+}
+
+/// The ranch with the given id, if anyone is in it.
+pub fn get_open_ranch(server: &Server, ranch_id: u32) -> Option<Arc<Mutex<Ranch>>> {
+    server
+        .ranches
+        .get(&ranch_id)
+        .map(|ranch| Arc::clone(ranch.value()))
+}
+
+/// Locks the ranch, opening it if nobody is in it yet.
+pub async fn lock_or_open_ranch(
+    server: &Server,
+    info: &entities::ranch::Ranch,
+) -> OwnedMutexGuard<Ranch> {
+    loop {
+        let ranch = Arc::clone(
+            server
+                .ranches
+                .entry(info.ranch_id)
+                .or_insert_with(|| Arc::new(Mutex::new(Ranch::new(info.clone()))))
+                .value(),
+        );
+        let ranch = ranch.lock_owned().await;
+        // The last character may have left while waiting, taking the ranch down with it
+        if !ranch.closed {
+            return ranch;
+        }
+    }
+}
+
+/// Takes the ranch down if nobody is left in it.
+pub fn close_ranch_if_empty(server: &Server, ranch: &mut OwnedMutexGuard<Ranch>) {
+    if ranch.closed || !ranch.character_sessions.is_empty() {
+        return;
+    }
+    ranch.closed = true;
+    let ranch_id = ranch.info.ranch_id;
+    let closed_ranch = Arc::clone(OwnedMutexGuard::mutex(ranch));
+    server.ranches.remove_if(&ranch_id, |_, open_ranch| {
+        Arc::ptr_eq(open_ranch, &closed_ranch)
+    });
+}
+
+/// Sends the command to every one of the sessions, except the one whose character is
+/// `except_character_id`. A session failing to take it is only logged, so that the rest
+/// still get it; sessions that fall behind are disconnected by [Session::send_command].
+pub async fn broadcast<T>(
+    sessions: &[Arc<Mutex<Session>>],
+    command: &T,
+    except_character_id: Option<u32>,
+) where
+    T: Command + Clone,
+{
+    for ranch_session in sessions {
+        let mut ranch_session = ranch_session.lock().await;
+        let character_id = ranch_session.character.as_ref().map(|c| c.character_id);
+        if except_character_id.is_some() && character_id == except_character_id {
+            continue;
+        }
+        if let Err(e) = ranch_session.send_command(command.clone()).await {
+            eprintln!(
+                "Failed to send {:?} to character {:?}: {}",
+                T::ID,
+                character_id,
+                e
+            );
+        }
+    }
+}
+
+/// Removes the session's character from the ranch it's in, if any, and lets the rest of
+/// the visitors know. Ranches left without visitors are dropped.
+pub async fn leave_ranch(
+    server: &Arc<Server>,
+    session: &Arc<Mutex<Session>>,
+) -> Result<(), String> {
+    let (ranch_id, character_id) = {
+        let mut session = session.lock().await;
+        let Some(ranch_id) = session.ranch_id.take() else {
+            return Ok(());
+        };
+        let character_id = session
+            .character
+            .as_ref()
+            .map(|c| c.character_id)
+            .unwrap_or_default();
+        (ranch_id, character_id)
+    };
+
+    let Some(ranch) = get_open_ranch(server, ranch_id) else {
+        return Ok(());
+    };
+    // Everything happens under the ranch's lock, so that nobody entering meanwhile can be given
+    // the index that's being released before the rest are told about it, or have the visitor
+    // count overwritten with an older one. Closing the ranch comes last, since whoever opens
+    // it again doesn't wait for this lock
+    let mut ranch = ranch.lock_owned().await;
+    let ranch_index = ranch.character_ranch_index(character_id);
+    ranch.remove_character(character_id, session);
+
+    // Visitors know each other by ranch index, and nobody was told about a character without one
+    if let Some(ranch_index) = ranch_index {
+        let notify = LeaveRanchNotify {
+            ranch_index: ranch_index.into(),
+        };
+        broadcast(&ranch.character_sessions, &notify, None).await;
+    }
+
+    let visitor_count = ranch.character_sessions.len() as u32;
+    let result = server
+        .database
+        .run_in_transaction(async |transaction| {
+            transaction
+                .update_visitor_count(ranch_id, visitor_count)
+                .await
+        })
+        .await
+        .map_err(|e| format!("Failed to update visitor count: {}", e));
+    close_ranch_if_empty(server, &mut ranch);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::sync::Notify;
+
+    use super::*;
+    use crate::{
+        commands::ranch::enter_ranch::{EnterRanch, EnterRanchNotify},
+        handlers::{CommandHandler, ranch::enter_ranch::EnterRanchHandler},
+        server::{
+            ServerType,
+            testing::{
+                create_character, entry_code, log_in, received_packets, test_server, test_session,
+            },
+        },
+    };
+
+    #[test]
+    fn test_ranch_indices_are_stable_and_reused() {
+        let mut allocator = RanchIndexAllocator::default();
+        let first = allocator.allocate();
+        let second = allocator.allocate();
+        let third = allocator.allocate();
+        assert_eq!((first, second, third), (1, 2, 3));
+
+        allocator.release(second);
+        assert_eq!(allocator.allocate(), second);
+        assert_eq!(allocator.allocate(), 4);
+
+        allocator.release(third);
+        allocator.release(first);
+        assert_eq!(allocator.allocate(), first);
+        assert_eq!(allocator.allocate(), third);
+    }
+
+    #[tokio::test]
+    async fn test_leaving_while_someone_enters() {
+        let server = test_server(ServerType::Ranch).await;
+        let (owner_session, owner_client) = test_session().await;
+        let (first_session, _first_client) = test_session().await;
+        let (second_session, _second_client) = test_session().await;
+        let (owner, owner_mount) = create_character(&server.database, "Owner").await;
+        let (first, first_mount) = create_character(&server.database, "First").await;
+        let (second, second_mount) = create_character(&server.database, "Second").await;
+        log_in(&owner_session, &owner, &[owner_mount]).await;
+        log_in(&first_session, &first, &[first_mount]).await;
+        log_in(&second_session, &second, &[second_mount]).await;
+        let ranch_id = server
+            .database
+            .run_in_transaction(async |transaction| {
+                transaction
+                    .get_or_create_ranch_by_owner(owner.character_id)
+                    .await
+            })
+            .await
+            .unwrap()
+            .unwrap()
+            .ranch_id;
+        let enter = |session: &Arc<Mutex<Session>>, character_uid| {
+            let (server, session) = (Arc::clone(&server), Arc::clone(session));
+            tokio::spawn(async move {
+                let otp = entry_code(&server.database, character_uid, ranch_id).await;
+                EnterRanchHandler::handle_command(
+                    server,
+                    session,
+                    &EnterRanch {
+                        character_uid,
+                        otp,
+                        ranch_uid: ranch_id,
+                    },
+                )
+                .await
+            })
+        };
+        let leave = |session: &Arc<Mutex<Session>>| {
+            let (server, session) = (Arc::clone(&server), Arc::clone(session));
+            tokio::spawn(async move { leave_ranch(&server, &session).await })
+        };
+
+        enter(&owner_session, owner.character_id)
+            .await
+            .unwrap()
+            .unwrap();
+        enter(&first_session, first.character_id)
+            .await
+            .unwrap()
+            .unwrap();
+        let (mut owner_client, _owner_writer) = owner_client.into_split();
+
+        // The first one leaves and the second one enters, queueing up on the ranch in that
+        // order. Holding the database once both are waiting has whichever of them gets to it
+        // first wait there, with everything it did up to that point visible to the other
+        let ranch = get_open_ranch(&server, ranch_id).unwrap();
+        let ranch_guard = ranch.lock().await;
+        let left = leave(&first_session);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let entered = enter(&second_session, second.character_id);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let release_database = Arc::new(Notify::new());
+        let held_database = {
+            let (database, release) = (Arc::clone(&server.database), Arc::clone(&release_database));
+            tokio::spawn(async move {
+                database
+                    .run_in_transaction(async |_| {
+                        release.notified().await;
+                        Ok(())
+                    })
+                    .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        drop(ranch_guard);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        release_database.notify_one();
+        held_database.await.unwrap().unwrap();
+        left.await.unwrap().unwrap();
+        entered.await.unwrap().unwrap();
+
+        // The owner's client has to end up seeing whoever is actually in the ranch
+        let mut seen = BTreeSet::new();
+        for packet in received_packets(&mut owner_client).await {
+            if let Ok(notify) = EnterRanchNotify::try_from(&packet) {
+                assert!(seen.insert(notify.character.ranch_index));
+            } else if let Ok(notify) = LeaveRanchNotify::try_from(&packet) {
+                assert!(seen.remove(&(notify.ranch_index as u16)));
+            }
+        }
+        let ranch = ranch.lock().await;
+        assert_eq!(
+            seen,
+            BTreeSet::from([ranch.character_ranch_index(second.character_id).unwrap()])
+        );
+        let visitor_count = server
+            .database
+            .run_in_transaction(async |transaction| transaction.get_ranch_by_id(ranch_id).await)
+            .await
+            .unwrap()
+            .unwrap()
+            .visitor_count;
+        assert_eq!(visitor_count, 2);
+    }
 }
@@ -1,74 +1,163 @@
-use std::{collections::HashMap, error::Error, io::Cursor, net::SocketAddr, sync::Arc};
+use std::{
+    error::Error,
+    io::Cursor,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use dashmap::DashMap;
 use deku::{DekuWriter, writer::Writer};
 use pretty_hex::pretty_hex;
 use tokio::{
     io::AsyncWriteExt,
-    net::{TcpListener, TcpStream},
-    runtime::Handle,
-    sync::Mutex,
-    task::{JoinHandle, LocalSet},
+    net::{TcpListener, TcpStream, tcp::OwnedReadHalf},
+    sync::{
+        Mutex, Notify,
+        mpsc::{self, error::TrySendError},
+    },
 };
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
 
 use crate::{
-    commands::{Command, shared::horse::Horse},
+    commands::{Command, lobby::notice::Notice, shared::horse::Horse},
     database::Database,
-    entities::{account::Account, character::Character},
+    entities::{account::Account, character::Character, tracked::Tracked},
     handlers::{
         PacketHandler,
         lobby::{
             achievement_complete_list::AchievementCompleteListHandler,
-            create_nickname::CreateNicknameHandler, get_messenger_info::GetMessengerInfoHandler,
-            login::LoginHandler, request_daily_quest_list::RequestDailyQuestListHandler,
+            change_ranch_option::ChangeRanchOptionHandler, create_nickname::CreateNicknameHandler,
+            enter_ranch_randomly::EnterRanchRandomlyHandler,
+            get_messenger_info::GetMessengerInfoHandler, login::LoginHandler,
+            request_daily_quest_list::RequestDailyQuestListHandler,
             request_league_info::RequestLeagueInfoHandler,
             request_quest_list::RequestQuestListHandler,
             request_special_event_list::RequestSpecialEventListHandler,
             show_inventory::ShowInventoryHandler,
         },
         ranch::{
+            breeding_abandon::BreedingAbandonHandler,
             breeding_failure_card::BreedingFailureCardHandler,
+            breeding_failure_card_choose::BreedingFailureCardChooseHandler,
+            breeding_take_money::BreedingTakeMoneyHandler,
             breeding_wishlist::BreedingWishlistHandler,
+            breeding_wishlist_add::BreedingWishlistAddHandler,
+            breeding_wishlist_del::BreedingWishlistDelHandler,
+            check_stallion_charge::CheckStallionChargeHandler,
             enter_breeding_market::EnterBreedingMarketHandler,
-            leave_breeding_market::LeaveBreedingMarketHandler,
+            expand_mount_slot::ExpandMountSlotHandler, kick_ranch::KickRanchHandler,
+            leave_breeding_market::LeaveBreedingMarketHandler, leave_ranch::LeaveRanchHandler,
             mount_family_tree::MountFamilyTreeHandler, ranch_chat::RanchChatHandler,
-            ranch_cmd_action::RanchCmdActionHandler, ranch_snapshot::RanchSnapshotHandler,
+            ranch_cmd_action::RanchCmdActionHandler, ranch_cmd_action_to::RanchCmdActionToHandler,
+            ranch_snapshot::RanchSnapshotHandler, register_stallion::RegisterStallionHandler,
             request_npc_dress_list::RequestNpcDressListHandler,
             request_storage::RequestStorageHandler, search_stallion::SearchStallionHandler,
-            try_breeding::TryBreedingHandler, update_mount_nickname::UpdateMountNicknameHandler,
+            try_breeding::TryBreedingHandler, unregister_stallion::UnregisterStallionHandler,
+            unregister_stallion_estimate_info::UnregisterStallionEstimateInfoHandler,
+            update_mount_nickname::UpdateMountNicknameHandler,
             wear_equipment::WearEquipmentHandler,
         },
     },
     packet::{CommandId, MAX_BUFFER_SIZE, Packet, PacketScrambler},
-    ranch::Ranch,
-    settings::Settings,
+    ranch::{Ranch, leave_ranch},
+    settings::{ServerSettings, Settings},
 };
 
+/// Packets that can be waiting to be written to a client before it's considered too slow
+const OUTBOUND_QUEUE_SIZE: usize = 256;
+/// Wait before accepting connections again after accepting one failed
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+/// How often idle connections are looked for, unless the idle timeout is shorter
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// How often session data left unstored, e.g. by a failed flush, is written again
+const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+/// Times the session data is tried to be stored when the connection closes
+const DISCONNECT_FLUSH_ATTEMPTS: u32 = 3;
+const DISCONNECT_FLUSH_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Keeps track of when the client was last heard from, which is whenever a packet arrives.
+/// Lives outside of the session's lock, so that checking for idle connections doesn't wait on
+/// handlers holding it. Packets are read without locking the session, which is only locked
+/// while a handler needs it.
+struct Heartbeat {
+    last_seen: std::sync::Mutex<Instant>,
+    timed_out: Notify,
+}
+impl Heartbeat {
+    fn new() -> Self {
+        Heartbeat {
+            last_seen: std::sync::Mutex::new(Instant::now()),
+            timed_out: Notify::new(),
+        }
+    }
+
+    fn beat(&self) {
+        *self.last_seen.lock().unwrap() = Instant::now();
+    }
+
+    fn idle_time(&self) -> Duration {
+        self.last_seen.lock().unwrap().elapsed()
+    }
+}
+
 pub struct Session {
-    buf: [u8; MAX_BUFFER_SIZE],
-    socket: TcpStream,
+    outbound: mpsc::Sender<Vec<u8>>,
+    disconnect: Arc<Notify>,
 
     pub scrambler: PacketScrambler,
 
     pub account: Option<Account>,
-    pub character: Option<Character>,
-    pub horses: Option<Vec<Horse>>,
+    /// Written to the database when the session is flushed, see [`Session::flush`]
+    pub character: Option<Tracked<Character>>,
+    pub horses: Option<Vec<Tracked<Horse>>>,
+    /// Foal from the last successful breeding attempt, until it's named or abandoned
+    pub pending_foal_uid: Option<u32>,
 
     pub ranch_id: Option<u32>,
+
+    heartbeat: Arc<Heartbeat>,
 }
 impl Session {
-    fn new(socket: TcpStream) -> Self {
-        Session {
-            buf: [0u8; MAX_BUFFER_SIZE],
-            socket,
+    /// Splits the socket, spawning a task that writes everything sent through the session.
+    /// The returned reader receives the incoming packets.
+    fn new(socket: TcpStream, tasks: &TaskTracker) -> (Self, SessionReader) {
+        let (read_half, mut write_half) = socket.into_split();
+        let (outbound, mut outbound_queue) = mpsc::channel::<Vec<u8>>(OUTBOUND_QUEUE_SIZE);
+        let disconnect = Arc::new(Notify::new());
+
+        let writer_disconnect = Arc::clone(&disconnect);
+        tasks.spawn(async move {
+            // Runs until the session is dropped or the client stops accepting data
+            while let Some(bytes) = outbound_queue.recv().await {
+                if let Err(e) = write_half.write_all(&bytes).await {
+                    eprintln!("Error sending data: {:?}", e);
+                    writer_disconnect.notify_one();
+                    break;
+                }
+            }
+        });
+
+        let session = Session {
+            outbound,
+            disconnect,
 
             scrambler: PacketScrambler::default(),
 
             account: None,
             character: None,
             horses: None,
+            pending_foal_uid: None,
 
             ranch_id: None,
-        }
+
+            heartbeat: Arc::new(Heartbeat::new()),
+        };
+        let reader = SessionReader {
+            buf: [0u8; MAX_BUFFER_SIZE],
+            socket: read_half,
+        };
+        (session, reader)
     }
 
     pub fn get_mount(&self) -> Option<&Horse> {
@@ -80,16 +169,68 @@ impl Session {
                 .unwrap()
                 .iter()
                 .filter(|h| h.uid == self.character.as_ref().unwrap().mount_uid)
+                .map(|h| &**h)
                 .next()
         }
     }
 
-    pub async fn recv_packet(&mut self) -> Result<Packet, String> {
-        let mut packet = Packet::from_stream(&mut self.buf, &mut self.socket)
-            .await
-            .map_err(|e| format!("Error reading command: {}:\n\t{}", e, pretty_hex(&self.buf)))?;
-        self.scrambler.scramble(&mut packet);
-        Ok(packet)
+    /// Copies the character and horses changed since they were last stored, so that they can
+    /// be written without holding on to the session
+    pub fn changes(&self) -> SessionChanges {
+        SessionChanges {
+            character: self
+                .character
+                .as_ref()
+                .filter(|c| c.is_dirty())
+                .map(|c| (Character::clone(c), c.version())),
+            horses: self
+                .horses
+                .iter()
+                .flatten()
+                .filter(|h| h.is_dirty())
+                .map(|h| (Horse::clone(h), h.version()))
+                .collect(),
+        }
+    }
+
+    /// Marks the written changes as stored. Whatever changed again in the meantime is still
+    /// considered changed.
+    pub fn mark_stored(&mut self, changes: &SessionChanges) {
+        if let Some((stored, version)) = &changes.character
+            && let Some(character) = self
+                .character
+                .as_mut()
+                .filter(|c| c.character_id == stored.character_id)
+        {
+            character.mark_stored(*version);
+        }
+        for (stored, version) in changes.horses.iter() {
+            if let Some(horse) = self
+                .horses
+                .iter_mut()
+                .flatten()
+                .find(|h| h.uid == stored.uid)
+            {
+                horse.mark_stored(*version);
+            }
+        }
+    }
+
+    /// Writes the character and horses changed since they were last stored, all in one
+    /// transaction. The session is only locked to copy the changes and to mark them stored
+    /// afterwards. If writing fails everything is still considered changed, and the next
+    /// flush retries it.
+    pub async fn flush(
+        session: &Mutex<Session>,
+        database: &Database,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let changes = session.lock().await.changes();
+        if changes.is_empty() {
+            return Ok(());
+        }
+        changes.store(database).await?;
+        session.lock().await.mark_stored(&changes);
+        Ok(())
     }
 
     pub async fn send_command<T>(&mut self, command: T) -> Result<(), String>
@@ -100,7 +241,7 @@ impl Session {
         let packet = command
             .try_into()
             .map_err(|e| format!("Failed to serialize response: {:?}", e))?;
-        self.do_send_packet(&packet).await?;
+        self.do_send_packet(&packet)?;
         if !packet.command_id.muted() {
             println!(
                 ">>> Sent command {:?}:\n\tLength: {} ({:#x}) bytes\n{}\n\n",
@@ -114,7 +255,7 @@ impl Session {
     }
 
     pub async fn send_packet(&mut self, packet: &Packet) -> Result<(), String> {
-        self.do_send_packet(packet).await?;
+        self.do_send_packet(packet)?;
         if !packet.command_id.muted() {
             println!(
                 ">>> Sent packet {:?}:\n\t{}\n\n",
@@ -125,22 +266,84 @@ impl Session {
         Ok(())
     }
 
-    async fn do_send_packet(&mut self, packet: &Packet) -> Result<(), String> {
-        // Outgoing commands aren't scrambled, so we can write directly to the buffer
-        let written_bytes = {
-            let mut cursor = Cursor::new(&mut self.buf[..]);
-            let mut writer = Writer::new(&mut cursor);
-            packet
-                .to_writer(&mut writer, ())
-                .map_err(|err| format!("Error serializing command: {}", err))?;
-            writer.bits_written / 8
-        };
-        let written_bytes = &self.buf[0..written_bytes];
-        self.socket
-            .write(&written_bytes)
+    /// Queues the packet for the writer task, without waiting for it to reach the client
+    fn do_send_packet(&mut self, packet: &Packet) -> Result<(), String> {
+        // Outgoing commands aren't scrambled, so they can be written as they are
+        let mut cursor = Cursor::new(Vec::new());
+        let mut writer = Writer::new(&mut cursor);
+        packet
+            .to_writer(&mut writer, ())
+            .and_then(|_| writer.finalize())
+            .map_err(|err| format!("Error serializing command: {}", err))?;
+        match self.outbound.try_send(cursor.into_inner()) {
+            Ok(()) => Ok(()),
+            // The client will be up to date once it gets the next one
+            Err(TrySendError::Full(_)) if packet.command_id.droppable() => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                // Skipping anything else would leave the client out of sync
+                self.disconnect.notify_one();
+                Err("Outbound queue is full, disconnecting client".to_owned())
+            }
+            Err(TrySendError::Closed(_)) => Err("Connection is closed".to_owned()),
+        }
+    }
+}
+
+/// Character and horses of a session that changed since they were last stored, along with
+/// the version each of them was copied at
+pub struct SessionChanges {
+    character: Option<(Character, u64)>,
+    horses: Vec<(Horse, u64)>,
+}
+impl SessionChanges {
+    pub fn is_empty(&self) -> bool {
+        self.character.is_none() && self.horses.is_empty()
+    }
+
+    /// Writes every change in a single transaction. Changes to entities that are gone from
+    /// the database are dropped, since they could never be written.
+    pub async fn store(&self, database: &Database) -> Result<(), Box<dyn Error + Send + Sync>> {
+        database
+            .run_in_transaction(async |transaction| {
+                if let Some((character, _)) = &self.character {
+                    if transaction
+                        .get_character_by_id(character.character_id)
+                        .await?
+                        .is_none()
+                    {
+                        eprintln!(
+                            "Character {} no longer exists, dropping its changes",
+                            character.character_id
+                        );
+                    } else {
+                        transaction.update_character(character).await?;
+                    }
+                }
+                for (horse, _) in self.horses.iter() {
+                    if transaction.get_horse_by_uid(horse.uid).await?.is_none() {
+                        eprintln!("Horse {} no longer exists, dropping its changes", horse.uid);
+                        continue;
+                    }
+                    transaction.update_horse(&mut horse.clone()).await?;
+                }
+                Ok(())
+            })
             .await
-            .map_err(|err| format!("Error sending command: {:?}", err))?;
-        Ok(())
+    }
+}
+
+/// Receiving half of a session's connection. It's kept out of the session so that waiting
+/// for the next packet doesn't keep the session locked.
+pub struct SessionReader {
+    buf: [u8; MAX_BUFFER_SIZE],
+    socket: OwnedReadHalf,
+}
+impl SessionReader {
+    /// Reads the next packet. It still has to be unscrambled with the session's scrambler.
+    pub async fn recv_packet(&mut self) -> Result<Packet, String> {
+        Packet::from_stream(&mut self.buf, &mut self.socket)
+            .await
+            .map_err(|e| format!("Error reading command: {}:\n\t{}", e, pretty_hex(&self.buf)))
     }
 }
 
@@ -153,351 +356,955 @@ pub enum ServerType {
 pub struct Server {
     pub server_type: ServerType,
     pub settings: Settings,
-    pub database: Arc<Mutex<Database>>,
+    /// Address the server is listening on, with the port it was given if bound to port 0
+    pub local_addr: SocketAddr,
+    pub database: Arc<Database>,
 
-    pub sessions: HashMap<SocketAddr, Arc<Mutex<Session>>>,
-    pub ranches: HashMap<u32, Ranch>,
+    pub sessions: DashMap<SocketAddr, Arc<Mutex<Session>>>,
+    heartbeats: DashMap<SocketAddr, Arc<Heartbeat>>,
+    /// Ranches with someone in them. Each one is locked on its own, so that unrelated ranches
+    /// don't wait on each other.
+    pub ranches: DashMap<u32, Arc<Mutex<Ranch>>>,
 
-    worker_task: Option<JoinHandle<()>>,
-    stop: bool,
+    /// Cancelled when the server is stopping
+    shutdown: CancellationToken,
+    /// Every task the server waits for before it's considered stopped
+    tasks: TaskTracker,
 }
 impl Server {
     pub async fn new(
         server_type: ServerType,
         settings: &Settings,
-        database: Arc<Mutex<Database>>,
-    ) -> Result<Arc<Mutex<Server>>, Box<dyn Error>> {
-        let bind_address = match server_type {
-            ServerType::Lobby => &settings.lobby_server.bind_address,
-            ServerType::Ranch => &settings.ranch_server.bind_address,
+        database: Arc<Database>,
+    ) -> Result<Arc<Server>, Box<dyn Error>> {
+        let server_settings = match server_type {
+            ServerType::Lobby => &settings.lobby_server,
+            ServerType::Ranch => &settings.ranch_server,
         };
+        let bind_address = &server_settings.bind_address;
 
-        let tcp_listener = TcpListener::bind(bind_address).await?;
-        println!("{:?} server listening on: {}", server_type, bind_address);
+        if let ServerType::Ranch = server_type {
+            // Nobody can be in a ranch before the ranch server is up
+            database
+                .run_in_transaction(async |transaction| transaction.reset_visitor_counts().await)
+                .await
+                .map_err(|e| format!("Failed to reset visitor counts: {}", e))?;
+        }
 
-        let server_instance = Arc::new(Mutex::new(Server {
+        let tcp_listener = TcpListener::bind(bind_address).await?;
+        let server_instance = Arc::new(Server {
             server_type: server_type,
             settings: settings.clone(),
+            local_addr: tcp_listener.local_addr()?,
             database: Arc::clone(&database),
 
-            sessions: HashMap::new(),
-            ranches: HashMap::new(),
+            sessions: DashMap::new(),
+            heartbeats: DashMap::new(),
+            ranches: DashMap::new(),
+
+            shutdown: CancellationToken::new(),
+            tasks: TaskTracker::new(),
+        });
 
-            worker_task: None,
-            stop: false,
-        }));
+        println!(
+            "{:?} server listening on: {}",
+            server_type, server_instance.local_addr
+        );
 
         // Spawn a task to deal with all incoming connections
         let server = Arc::clone(&server_instance);
-        let worker_task = tokio::spawn(async move {
-            while !server.lock().await.stop {
-                // Asynchronously wait for an inbound socket.
-                let accept_result = tcp_listener.accept().await;
-                if let Err(e) = accept_result {
-                    eprintln!("Failed to accept socket: {}", e);
-                    return;
-                }
-
-                let (socket, _) = accept_result.unwrap();
+        server_instance.tasks.spawn(async move {
+            loop {
+                // Asynchronously wait for an inbound socket, until the server stops.
+                // The listener is closed as soon as this task returns.
+                let accept_result = tokio::select! {
+                    result = tcp_listener.accept() => result,
+                    _ = server.shutdown.cancelled() => return,
+                };
                 // Use peer address as identifier when storing sessions in the hash map
-                let peer_addr = socket.peer_addr();
-                if let Err(e) = peer_addr {
-                    eprintln!("Failed to obtain peer address: {}", e);
-                    return;
-                }
-                let peer_addr = peer_addr.unwrap();
+                let (socket, peer_addr) = match accept_result {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        // Mostly running out of file descriptors, which frees up as sessions
+                        // close. Retrying right away would only spin on the same error.
+                        eprintln!("Failed to accept socket: {}", e);
+                        tokio::select! {
+                            _ = tokio::time::sleep(ACCEPT_RETRY_DELAY) => continue,
+                            _ = server.shutdown.cancelled() => return,
+                        }
+                    }
+                };
 
                 // Spawn a task for each accepted connection
                 let server = Arc::clone(&server);
-                tokio::task::spawn_blocking(move || {
-                    Handle::current().block_on(async move {
-                        println!("New connection established");
-                        let session = Arc::new(Mutex::new(Session::new(socket)));
-                        server.lock().await.sessions.insert(peer_addr, Arc::clone(&session));
+                let tasks = server.tasks.clone();
+                tasks.spawn(async move {
+                    println!("New connection established");
+                    let (session, mut reader) = Session::new(socket, &server.tasks);
+                    let heartbeat = Arc::clone(&session.heartbeat);
+                    let disconnect = Arc::clone(&session.disconnect);
+                    let session = Arc::new(Mutex::new(session));
+                    server.sessions.insert(peer_addr, Arc::clone(&session));
+                    server.heartbeats.insert(peer_addr, Arc::clone(&heartbeat));
 
-                        let server = Arc::clone(&server);
-                        // In a loop, handle incoming data until the server is stopped or we break the loop.
-                        while !server.lock().await.stop {
-                            // Local task set for every task spawned while handling this packet.
-                            // Tasks in this set will all run on the same thread, allowing to run code that cant
-                            // be moved across threads, such as postgres transactions
-                            // TODO: Look into FuturesUnordered
-                            let local_task_set = LocalSet::new();
-                            // Spawn a task for each incoming packet. These tasks will not be run in parallel
-                            // but we need them as tasks running in the LocalSet to ensure all the child tasks
-                            // are run in the same thread.
-                            let session = Arc::clone(&session);
-                            let server = Arc::clone(&server);
-                            let handle_result = local_task_set
-                                .run_until(async move {
-                                    tokio::task::spawn_local(async move {
-                                        // Receive the next packet
-                                        let packet = session.lock().await.recv_packet().await.map_err(|err| {
-                                                format!("Failed to receive packet: {}", err)
-                                            })?;
-
-                                        let handle_result = match server_type {
-                                            // Lobby server commands
-                                            ServerType::Lobby => match packet.command_id {
-                                                CommandId::AcCmdCLAchievementCompleteList => {
-                                                    AchievementCompleteListHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCLCreateNickname => {
-                                                    CreateNicknameHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCLEnterRanch => {
-                                                    crate::handlers::lobby::enter_ranch::EnterRanchHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCLGetMessengerInfo => {
-                                                    GetMessengerInfoHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCLLogin => {
-                                                    LoginHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCLRequestDailyQuestList => {
-                                                    RequestDailyQuestListHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCLRequestLeagueInfo => {
-                                                    RequestLeagueInfoHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCLRequestQuestList => {
-                                                    RequestQuestListHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCLRequestSpecialEventList => {
-                                                    RequestSpecialEventListHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCLShowInventory => {
-                                                    ShowInventoryHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                _ => Err("Unhandled command".into()),
-                                            },
-                                            // Ranch server commands
-                                            ServerType::Ranch => match packet.command_id {
-                                                CommandId::AcCmdCRBreedingFailureCard => {
-                                                    BreedingFailureCardHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCRBreedingWishlist => {
-                                                    BreedingWishlistHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCREnterBreedingMarket => {
-                                                    EnterBreedingMarketHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCREnterRanch => {
-                                                    crate::handlers::ranch::enter_ranch::EnterRanchHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCRLeaveBreedingMarket => {
-                                                    LeaveBreedingMarketHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCRMountFamilyTree => {
-                                                    MountFamilyTreeHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCRRanchChat => {
-                                                    RanchChatHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCRRanchCmdAction => {
-                                                    RanchCmdActionHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCRRanchSnapshot => {
-                                                    RanchSnapshotHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCRRequestNpcDressList => {
-                                                    RequestNpcDressListHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCRRequestStorage => {
-                                                    RequestStorageHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCRSearchStallion => {
-                                                    SearchStallionHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCRTryBreeding => {
-                                                    TryBreedingHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCRUpdateMountNickname => {
-                                                    UpdateMountNicknameHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                CommandId::AcCmdCRWearEquipment => {
-                                                    WearEquipmentHandler::handle_packet(
-                                                        Arc::clone(&server),
-                                                        Arc::clone(&session),
-                                                        &packet,
-                                                    )
-                                                    .await
-                                                }
-                                                _ => Err("Unhandled command".into()),
-                                            },
-                                        };
-
-                                        if let Err(e) = handle_result {
-                                            // TODO: Implement handlers for these. Use them to detect half-closed connections
-                                            let muted_packet = matches!(
-                                                packet.command_id,
-                                                CommandId::AcCmdCLHeartbeat
-                                                    | CommandId::AcCmdCRHeartbeat
-                                                    | CommandId::AcCmdCRRanchSnapshot
-                                            );
-                                            if !muted_packet {
-                                                eprintln!(
-                                                    "Failed to handle packet {:?}:\n\t{}\n\t{}\n",
-                                                    packet.command_id,
-                                                    e,
-                                                    pretty_hex(&packet.payload)
-                                                );
-                                            }
-                                        }
-
-                                        Ok::<(), String>(()) // Continue processing packets in this session
-                                    })
-                                    .await
-                                })
-                                .await
-                                .or_else(|join_err| {
-                                    Err(format!("Couldn't join handler task: {}", join_err))
-                                });
-
-                            if let Err(handling_error) = handle_result {
-                                eprintln!("/!\\ CONNECTION CLOSED\n{}", handling_error);
+                    let server = Arc::clone(&server);
+                    // In a loop, handle incoming data until the server is stopped or we break the loop.
+                    loop {
+                        // Receive the next packet, unless the client has to be disconnected first
+                        let packet = tokio::select! {
+                            result = reader.recv_packet() => result
+                                .map_err(|err| format!("Failed to receive packet: {}", err)),
+                            _ = heartbeat.timed_out.notified() => {
+                                Err("Client stopped sending packets".to_owned())
+                            }
+                            _ = disconnect.notified() => {
+                                Err("Client can't keep up with outgoing packets".to_owned())
+                            }
+                            _ = server.shutdown.cancelled() => {
+                                Err("Server is shutting down".to_owned())
+                            }
+                        };
+                        let mut packet = match packet {
+                            Ok(packet) => packet,
+                            Err(e) => {
+                                eprintln!("/!\\ CONNECTION CLOSED\n{}", e);
                                 break;
                             }
+                        };
+                        // Clients only send heartbeats when they have nothing else to send
+                        heartbeat.beat();
+                        session.lock().await.scrambler.scramble(&mut packet);
+
+                        // Spawn a task for each incoming packet, so that a panicking handler only
+                        // closes this connection
+                        let session = Arc::clone(&session);
+                        let server = Arc::clone(&server);
+                        let handle_result = tokio::spawn(async move {
+                            let handle_result = match server_type {
+                                // Lobby server commands
+                                ServerType::Lobby => match packet.command_id {
+                                    CommandId::AcCmdCLAchievementCompleteList => {
+                                        AchievementCompleteListHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCLChangeRanchOption => {
+                                        ChangeRanchOptionHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCLCreateNickname => {
+                                        CreateNicknameHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCLEnterRanch => {
+                                        crate::handlers::lobby::enter_ranch::EnterRanchHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCLEnterRanchRandomly => {
+                                        EnterRanchRandomlyHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCLGetMessengerInfo => {
+                                        GetMessengerInfoHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCLHeartbeat => {
+                                        crate::handlers::lobby::heartbeat::HeartbeatHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCLLogin => {
+                                        LoginHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCLRequestDailyQuestList => {
+                                        RequestDailyQuestListHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCLRequestLeagueInfo => {
+                                        RequestLeagueInfoHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCLRequestQuestList => {
+                                        RequestQuestListHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCLRequestSpecialEventList => {
+                                        RequestSpecialEventListHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCLShowInventory => {
+                                        ShowInventoryHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    _ => Err("Unhandled command".into()),
+                                },
+                                // Ranch server commands
+                                ServerType::Ranch => match packet.command_id {
+                                    CommandId::AcCmdCRBreedingAbandon => {
+                                        BreedingAbandonHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRBreedingFailureCard => {
+                                        BreedingFailureCardHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRBreedingFailureCardChoose => {
+                                        BreedingFailureCardChooseHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRBreedingTakeMoney => {
+                                        BreedingTakeMoneyHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRBreedingWishlist => {
+                                        BreedingWishlistHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRBreedingWishlistAdd => {
+                                        BreedingWishlistAddHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRBreedingWishlistDel => {
+                                        BreedingWishlistDelHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRCheckStallionCharge => {
+                                        CheckStallionChargeHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCREnterBreedingMarket => {
+                                        EnterBreedingMarketHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCREnterRanch => {
+                                        crate::handlers::ranch::enter_ranch::EnterRanchHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRExpandMountSlot => {
+                                        ExpandMountSlotHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRHeartbeat => {
+                                        crate::handlers::ranch::heartbeat::HeartbeatHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRKickRanch => {
+                                        KickRanchHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRLeaveBreedingMarket => {
+                                        LeaveBreedingMarketHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRLeaveRanch => {
+                                        LeaveRanchHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRMountFamilyTree => {
+                                        MountFamilyTreeHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRRanchChat => {
+                                        RanchChatHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRRanchCmdAction => {
+                                        RanchCmdActionHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRRanchCmdActionTo => {
+                                        RanchCmdActionToHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRRanchSnapshot => {
+                                        RanchSnapshotHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRRegisterStallion => {
+                                        RegisterStallionHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRRequestNpcDressList => {
+                                        RequestNpcDressListHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRRequestStorage => {
+                                        RequestStorageHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRSearchStallion => {
+                                        SearchStallionHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRTryBreeding => {
+                                        TryBreedingHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRUnregisterStallion => {
+                                        UnregisterStallionHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRUnregisterStallionEstimateInfo => {
+                                        UnregisterStallionEstimateInfoHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRUpdateMountNickname => {
+                                        UpdateMountNicknameHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    CommandId::AcCmdCRWearEquipment => {
+                                        WearEquipmentHandler::handle_packet(
+                                            Arc::clone(&server),
+                                            Arc::clone(&session),
+                                            &packet,
+                                        )
+                                        .await
+                                    }
+                                    _ => Err("Unhandled command".into()),
+                                },
+                            };
+
+                            // Whatever the handler changed is stored before the next packet
+                            if let Err(e) = Session::flush(&session, &server.database).await {
+                                eprintln!("Failed to store session data: {}", e);
+                            }
+
+                            if let Err(e) = handle_result {
+                                let muted_packet = matches!(
+                                    packet.command_id,
+                                    CommandId::AcCmdCRRanchSnapshot
+                                );
+                                if !muted_packet {
+                                    eprintln!(
+                                        "Failed to handle packet {:?}:\n\t{}\n\t{}\n",
+                                        packet.command_id,
+                                        e,
+                                        pretty_hex(&packet.payload)
+                                    );
+                                }
+                            }
+
+                            Ok::<(), String>(()) // Continue processing packets in this session
+                        })
+                        .await
+                        .or_else(|join_err| {
+                            Err(format!("Couldn't join handler task: {}", join_err))
+                        });
+
+                        if let Err(handling_error) = handle_result {
+                            eprintln!("/!\\ CONNECTION CLOSED\n{}", handling_error);
+                            break;
                         }
+                    }
 
-                        println!("Connection closed");
-                        server.lock().await.sessions.remove(&peer_addr);
-                    })
+                    println!("Connection closed");
+                    if server.shutdown.is_cancelled()
+                        && let Err(e) = notify_shutdown(server_type, &session).await
+                    {
+                        eprintln!("Failed to notify shutdown: {}", e);
+                    }
+                    if let Err(e) = flush_on_disconnect(&server, &session).await {
+                        eprintln!("/!\\ SESSION DATA LOST\n{}", e);
+                    }
+                    if let Err(e) = leave_ranch(&server, &session).await {
+                        eprintln!("Failed to leave ranch on disconnect: {}", e);
+                    }
+                    server.sessions.remove(&peer_addr);
+                    server.heartbeats.remove(&peer_addr);
                 });
             }
         });
 
-        server_instance.lock().await.worker_task = Some(worker_task);
+        // Spawn a task to close connections whose client went silent without closing them
+        let idle_timeout = Duration::from_secs(server_settings.idle_timeout_secs);
+        let server = Arc::clone(&server_instance);
+        server_instance.tasks.spawn(async move {
+            let check_interval = IDLE_CHECK_INTERVAL
+                .min(idle_timeout / 2)
+                .max(Duration::from_millis(100));
+            let mut interval = tokio::time::interval(check_interval);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = server.shutdown.cancelled() => return,
+                }
+                for entry in server.heartbeats.iter() {
+                    if entry.value().idle_time() > idle_timeout {
+                        println!("Closing idle connection from {}", entry.key());
+                        entry.value().timed_out.notify_one();
+                    }
+                }
+            }
+        });
+
+        // Spawn a task to retry storing session data that couldn't be stored after a handler
+        let server = Arc::clone(&server_instance);
+        server_instance.tasks.spawn(async move {
+            let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = server.shutdown.cancelled() => return,
+                }
+                // Not iterating the map directly, it'd stay locked while waiting on sessions
+                let sessions = server
+                    .sessions
+                    .iter()
+                    .map(|entry| Arc::clone(entry.value()))
+                    .collect::<Vec<Arc<Mutex<Session>>>>();
+                for session in sessions {
+                    if let Err(e) = Session::flush(&session, &server.database).await {
+                        eprintln!("Failed to store session data: {}", e);
+                    }
+                }
+            }
+        });
 
         // Return server instance while it runs its client handling task
         Ok(server_instance)
     }
 
-    pub async fn stop(&mut self) -> Result<(), Box<dyn Error>> {
-        self.stop = true;
-        if let Some(worker_task) = self.worker_task.as_mut() {
-            worker_task.await?;
+    pub fn server_settings(&self) -> &ServerSettings {
+        match self.server_type {
+            ServerType::Lobby => &self.settings.lobby_server,
+            ServerType::Ranch => &self.settings.ranch_server,
         }
+    }
+
+    /// Stops accepting connections and closes every session once the packet it's handling
+    /// is done. Gives up if they take longer than the configured timeout.
+    pub async fn stop(&self) -> Result<(), Box<dyn Error>> {
+        self.shutdown.cancel();
+        self.tasks.close();
+        let timeout = Duration::from_secs(self.server_settings().shutdown_timeout_secs);
+        tokio::time::timeout(timeout, self.tasks.wait())
+            .await
+            .map_err(|_| {
+                format!(
+                    "{} sessions were still open after {:?}",
+                    self.sessions.len(),
+                    timeout
+                )
+            })?;
         Ok(())
     }
 }
+
+/// Stores what the session still holds before it's dropped, giving the database a few chances
+/// in case it's briefly unavailable
+async fn flush_on_disconnect(server: &Server, session: &Arc<Mutex<Session>>) -> Result<(), String> {
+    let mut attempt = 1;
+    loop {
+        match Session::flush(session, &server.database).await {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < DISCONNECT_FLUSH_ATTEMPTS => {
+                eprintln!("Failed to store session data, retrying: {}", e);
+                attempt += 1;
+                tokio::time::sleep(DISCONNECT_FLUSH_RETRY_DELAY).await;
+            }
+            Err(e) => {
+                return Err(format!(
+                    "Couldn't store session data after {} attempts: {}",
+                    attempt, e
+                ));
+            }
+        }
+    }
+}
+
+/// Lets the client know the session is closing because the server is going down
+async fn notify_shutdown(
+    server_type: ServerType,
+    session: &Arc<Mutex<Session>>,
+) -> Result<(), String> {
+    match server_type {
+        ServerType::Lobby => {
+            session
+                .lock()
+                .await
+                .send_command(Notice {
+                    message: c"The server is shutting down".into(),
+                })
+                .await
+        }
+        // TODO: Find out if ranch clients can be told anything
+        ServerType::Ranch => Ok(()),
+    }
+}
+
+/// Servers and sessions for running handlers against the in-memory database.
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+
+    /// Server of the given type with an empty in-memory database, listening on any free port.
+    pub async fn test_server(server_type: ServerType) -> Arc<Server> {
+        test_server_with_settings(server_type, Settings::default()).await
+    }
+
+    /// Same as [test_server], with the given settings except for the addresses.
+    pub async fn test_server_with_settings(
+        server_type: ServerType,
+        mut settings: Settings,
+    ) -> Arc<Server> {
+        settings.lobby_server.bind_address = "127.0.0.1:0".to_owned();
+        settings.ranch_server.bind_address = "127.0.0.1:0".to_owned();
+        Server::new(server_type, &settings, Arc::new(Database::in_memory()))
+            .await
+            .unwrap()
+    }
+
+    /// Session connected to a client that never sends anything. The client's end has to be
+    /// kept around, or the session is disconnected.
+    pub async fn test_session() -> (Arc<Mutex<Session>>, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        let (session, _reader) = Session::new(socket, &TaskTracker::new());
+        (Arc::new(Mutex::new(session)), client)
+    }
+
+    /// Stores a new character riding a new horse, and returns both.
+    pub async fn create_character(database: &Database, nickname: &str) -> (Character, Horse) {
+        database
+            .run_in_transaction(async |transaction| {
+                let mut account = Account {
+                    member_no: 0,
+                    login_id: nickname.to_owned(),
+                    auth_key: String::new(),
+                };
+                transaction.add_account(&mut account).await?;
+                let mut character = Character {
+                    character_id: 0,
+                    nickname: nickname.to_owned(),
+                    mount_uid: 0,
+                    mount_slots: 3,
+                    character: Default::default(),
+                    create_character_unk0: 0,
+                };
+                transaction
+                    .insert_character(account.member_no, &mut character)
+                    .await?;
+                let mut horse = Horse::default();
+                transaction
+                    .insert_horse(character.character_id, &mut horse)
+                    .await?;
+                character.mount_uid = horse.uid;
+                transaction.update_character(&character).await?;
+                Ok((character, horse))
+            })
+            .await
+            .unwrap()
+    }
+
+    /// Stores the horse for the character, and returns it with its uid.
+    pub async fn create_horse(database: &Database, character_id: u32, mut horse: Horse) -> Horse {
+        database
+            .run_in_transaction(async |transaction| {
+                transaction.insert_horse(character_id, &mut horse).await?;
+                Ok(horse)
+            })
+            .await
+            .unwrap()
+    }
+
+    /// Packets the client has been sent so far, waiting a moment for any still on their way.
+    pub async fn received_packets(client: &mut OwnedReadHalf) -> Vec<Packet> {
+        let mut buf = [0u8; MAX_BUFFER_SIZE];
+        let mut packets = Vec::new();
+        while let Ok(Ok(packet)) = tokio::time::timeout(
+            Duration::from_millis(200),
+            Packet::from_stream(&mut buf, client),
+        )
+        .await
+        {
+            packets.push(packet);
+        }
+        packets
+    }
+
+    /// Has the lobby send the character to the ranch, returning the code to enter it with.
+    pub async fn entry_code(database: &Database, character_id: u32, ranch_id: u32) -> u32 {
+        let code = rand::random();
+        database
+            .run_in_transaction(async |transaction| {
+                transaction
+                    .issue_ranch_entry_code(character_id, ranch_id, code, 60)
+                    .await
+            })
+            .await
+            .unwrap();
+        code
+    }
+
+    /// Logs the character in on the session, as if it had just entered the server.
+    pub async fn log_in(session: &Arc<Mutex<Session>>, character: &Character, horses: &[Horse]) {
+        let mut session = session.lock().await;
+        session.character = Some(Tracked::stored(character.clone()));
+        session.horses = Some(horses.iter().cloned().map(Tracked::stored).collect());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_flush_stores_changed_entities() {
+        let database = Database::in_memory();
+        let (character, horse) = database
+            .run_in_transaction(async |transaction| {
+                let mut account = Account {
+                    member_no: 0,
+                    login_id: "login".to_owned(),
+                    auth_key: String::new(),
+                };
+                transaction.add_account(&mut account).await?;
+                let mut character = Character {
+                    character_id: 0,
+                    nickname: "Nickname".to_owned(),
+                    mount_uid: 0,
+                    mount_slots: 3,
+                    character: Default::default(),
+                    create_character_unk0: 0,
+                };
+                transaction
+                    .insert_character(account.member_no, &mut character)
+                    .await?;
+                let mut horse = Horse::default();
+                transaction
+                    .insert_horse(character.character_id, &mut horse)
+                    .await?;
+                Ok((character, horse))
+            })
+            .await
+            .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        let (mut session, _reader) = Session::new(socket, &TaskTracker::new());
+        session.character = Some(Tracked::stored(character.clone()));
+        session.horses = Some(vec![Tracked::stored(horse.clone())]);
+
+        session.character.as_mut().unwrap().mount_uid = horse.uid;
+        session.horses.as_mut().unwrap()[0].growth_points = 10;
+        assert!(session.character.as_ref().unwrap().is_dirty());
+        let session = Mutex::new(session);
+        Session::flush(&session, &database).await.unwrap();
+        let session = session.lock().await;
+        assert!(!session.character.as_ref().unwrap().is_dirty());
+        assert!(!session.horses.as_ref().unwrap()[0].is_dirty());
+
+        let (stored_character, stored_horse) = database
+            .run_in_transaction(async |transaction| {
+                let character = transaction
+                    .get_character_by_id(character.character_id)
+                    .await?;
+                let horse = transaction.get_horse_by_uid(horse.uid).await?;
+                Ok((character.unwrap(), horse.unwrap()))
+            })
+            .await
+            .unwrap();
+        assert_eq!(stored_character.mount_uid, horse.uid);
+        assert_eq!(stored_horse.growth_points, 10);
+    }
+
+    #[tokio::test]
+    async fn test_flush_stores_everything_or_nothing() {
+        use testing::{create_character, log_in, test_session};
+
+        let database = Database::in_memory();
+        let (character, mount) = create_character(&database, "Nickname").await;
+        let (other_character, _) = create_character(&database, "Taken").await;
+        let gone_horse = database
+            .run_in_transaction(async |transaction| {
+                let mut horse = Horse::default();
+                transaction
+                    .insert_horse(character.character_id, &mut horse)
+                    .await?;
+                transaction.remove_horse(horse.uid).await?;
+                Ok(horse)
+            })
+            .await
+            .unwrap();
+        let (session, _client) = test_session().await;
+        log_in(&session, &character, &[mount.clone(), gone_horse.clone()]).await;
+        let get_mount = async || {
+            database
+                .run_in_transaction(async |transaction| {
+                    transaction.get_horse_by_uid(mount.uid).await
+                })
+                .await
+                .unwrap()
+                .unwrap()
+        };
+
+        {
+            let mut session = session.lock().await;
+            // Can't be stored, the nickname belongs to somebody else
+            session.character.as_mut().unwrap().nickname = other_character.nickname.clone();
+            for horse in session.horses.as_mut().unwrap().iter_mut() {
+                horse.growth_points = 10;
+            }
+        }
+        assert!(Session::flush(&session, &database).await.is_err());
+        // The horses were in the same transaction, so they weren't stored either
+        assert_eq!(get_mount().await.growth_points, mount.growth_points);
+        {
+            let session = session.lock().await;
+            assert!(session.character.as_ref().unwrap().is_dirty());
+            assert!(
+                session
+                    .horses
+                    .as_ref()
+                    .unwrap()
+                    .iter()
+                    .all(|h| h.is_dirty())
+            );
+        }
+
+        // Fixing the character lets everything through on the next flush
+        session.lock().await.character.as_mut().unwrap().nickname = "Renamed".to_owned();
+        Session::flush(&session, &database).await.unwrap();
+        assert_eq!(get_mount().await.growth_points, 10);
+        // The horse that's gone can never be stored, so it's not retried either
+        let session = session.lock().await;
+        assert!(!session.character.as_ref().unwrap().is_dirty());
+        assert!(
+            session
+                .horses
+                .as_ref()
+                .unwrap()
+                .iter()
+                .all(|h| !h.is_dirty())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_flush_keeps_changes_made_while_storing() {
+        use testing::{create_character, log_in, test_session};
+
+        let database = Database::in_memory();
+        let (character, mount) = create_character(&database, "Nickname").await;
+        let (session, _client) = test_session().await;
+        log_in(&session, &character, &[mount]).await;
+
+        let mut session = session.lock().await;
+        session.horses.as_mut().unwrap()[0].growth_points = 10;
+        let changes = session.changes();
+        // Changed again after the copy was taken, while the session wasn't locked
+        session.horses.as_mut().unwrap()[0].growth_points = 20;
+        changes.store(&database).await.unwrap();
+        session.mark_stored(&changes);
+        assert!(session.horses.as_ref().unwrap()[0].is_dirty());
+
+        let changes = session.changes();
+        changes.store(&database).await.unwrap();
+        session.mark_stored(&changes);
+        assert!(!session.horses.as_ref().unwrap()[0].is_dirty());
+    }
+
+    #[tokio::test]
+    async fn test_any_packet_keeps_the_connection_alive() {
+        let mut settings = Settings::default();
+        settings.ranch_server.idle_timeout_secs = 1;
+        let server = testing::test_server_with_settings(ServerType::Ranch, settings).await;
+        let mut active = TcpStream::connect(server.local_addr).await.unwrap();
+        let mut silent = TcpStream::connect(server.local_addr).await.unwrap();
+
+        // Anything but a heartbeat, with an empty payload so that it needs no scrambling
+        let mut cursor = Cursor::new(Vec::new());
+        let mut writer = Writer::new(&mut cursor);
+        Packet {
+            command_id: CommandId::AcCmdCRLeaveRanch,
+            payload: vec![],
+        }
+        .to_writer(&mut writer, ())
+        .unwrap();
+        writer.finalize().unwrap();
+        let packet = cursor.into_inner();
+        for _ in 0..10 {
+            active.write_all(&packet).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(250)).await;
+        }
+
+        // Only the silent one timed out, well before the active one stopped sending
+        let mut buf = [0u8; MAX_BUFFER_SIZE];
+        let closed = tokio::time::timeout(Duration::from_secs(1), async {
+            while silent.read(&mut buf).await.unwrap() != 0 {}
+        });
+        assert!(closed.await.is_ok());
+        assert_eq!(server.sessions.len(), 1);
+        assert!(active.read(&mut buf).await.unwrap() > 0);
+    }
+}
@@ -2,7 +2,10 @@ use std::net::Ipv4Addr;
 
 use serde::{Deserialize, Serialize};
 
-use crate::commands::shared::address::Address;
+use crate::{
+    commands::shared::address::Address,
+    genetics::{EligibilityRules, FailureCardRules, InheritanceRules},
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
@@ -11,6 +14,14 @@ pub struct Settings {
     pub race_server: ServerSettings,
     pub messenger_server: ServerSettings,
     pub database: DatabaseSettings,
+    #[serde(default)]
+    pub genetics: InheritanceRules,
+    #[serde(default)]
+    pub breeding_failure: FailureCardRules,
+    #[serde(default)]
+    pub breeding_eligibility: EligibilityRules,
+    #[serde(default)]
+    pub ranch: RanchSettings,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,12 +29,66 @@ pub struct ServerSettings {
     pub enabled: bool,
     pub bind_address: String,
     pub announce_address: Address,
+    /// Seconds without hearing from a client before its connection is closed
+    #[serde(default = "default_idle_timeout_secs")]
+    pub idle_timeout_secs: u64,
+    /// Seconds to wait for sessions to close when stopping the server
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+}
+fn default_idle_timeout_secs() -> u64 {
+    60
+}
+fn default_shutdown_timeout_secs() -> u64 {
+    10
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DatabaseBackend {
+    #[default]
+    Postgres,
+    /// Nothing is stored, everything is lost when the server stops. Only available when the
+    /// server is built with the `dev` feature
+    Memory,
+    /// Single database file, for machines that can't run Postgres
+    Sqlite,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatabaseSettings {
+    #[serde(default)]
+    pub backend: DatabaseBackend,
+    /// Postgres server to connect to. An embedded one is started if not set
     pub url: Option<String>,
+    /// Database file of the SQLite backend, created if it doesn't exist
+    #[serde(default = "default_sqlite_path")]
+    pub sqlite_path: String,
+    /// Drops every table before applying the migrations
     pub wipe_on_startup: bool,
+    /// Only lists the migrations that would be applied, and exits without starting the servers
+    #[serde(default)]
+    pub migrations_dry_run: bool,
+}
+fn default_sqlite_path() -> String {
+    "database/alicia.sqlite3".to_owned()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RanchSettings {
+    /// Seconds a character kicked out of a ranch has to wait before entering it again
+    pub kick_ban_duration_secs: u64,
+    /// Carrots charged for every additional mount slot
+    pub mount_slot_price: u32,
+}
+impl Default for RanchSettings {
+    fn default() -> Self {
+        RanchSettings {
+            kick_ban_duration_secs: 600,
+            mount_slot_price: 1000,
+        }
+    }
 }
 
 impl Default for Settings {
@@ -36,6 +101,8 @@ impl Default for Settings {
                     ip: Ipv4Addr::new(192, 168, 1, 32),
                     port: 10030,
                 },
+                idle_timeout_secs: default_idle_timeout_secs(),
+                shutdown_timeout_secs: default_shutdown_timeout_secs(),
             },
             ranch_server: ServerSettings {
                 enabled: true,
@@ -44,6 +111,8 @@ impl Default for Settings {
                     ip: Ipv4Addr::new(192, 168, 1, 32),
                     port: 10031,
                 },
+                idle_timeout_secs: default_idle_timeout_secs(),
+                shutdown_timeout_secs: default_shutdown_timeout_secs(),
             },
             race_server: ServerSettings {
                 enabled: true,
@@ -52,6 +121,8 @@ impl Default for Settings {
                     ip: Ipv4Addr::new(192, 168, 1, 32),
                     port: 10032,
                 },
+                idle_timeout_secs: default_idle_timeout_secs(),
+                shutdown_timeout_secs: default_shutdown_timeout_secs(),
             },
             messenger_server: ServerSettings {
                 enabled: true,
@@ -60,11 +131,20 @@ impl Default for Settings {
                     ip: Ipv4Addr::new(192, 168, 1, 32),
                     port: 10033,
                 },
+                idle_timeout_secs: default_idle_timeout_secs(),
+                shutdown_timeout_secs: default_shutdown_timeout_secs(),
             },
             database: DatabaseSettings {
+                backend: DatabaseBackend::Postgres,
                 url: None,
+                sqlite_path: default_sqlite_path(),
                 wipe_on_startup: false,
+                migrations_dry_run: false,
             },
+            genetics: InheritanceRules::default(),
+            breeding_failure: FailureCardRules::default(),
+            breeding_eligibility: EligibilityRules::default(),
+            ranch: RanchSettings::default(),
         }
     }
 }